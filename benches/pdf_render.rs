@@ -0,0 +1,81 @@
+//! Per-page render latency of the PDF backends.\
+//! Run with `cargo bench --bench pdf_render -- <file.pdf>`, and `--features pdfium` to compare pdfium with poppler.
+//! The file can also be given in `FIAPO_BENCH_PDF`.
+use fiapo::core::pdf::PdfBackendKind;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Resolution of the pages in the reader
+const PAGE_DPI: u32 = 150;
+/// Resolution of the page thumbnails
+const THUMBNAIL_DPI: u32 = 30;
+/// Pages rendered from the start of the file. Long files would take minutes with poppler
+const MAX_PAGES: usize = 20;
+
+fn main() {
+    let path = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .or_else(|| std::env::var("FIAPO_BENCH_PDF").ok())
+        .map(PathBuf::from);
+    let Some(path) = path else {
+        eprintln!("No PDF to render. Pass one after `--`, or set FIAPO_BENCH_PDF");
+        return;
+    };
+
+    println!("{}", path.display());
+    for kind in PdfBackendKind::ALL {
+        let backend = match kind.create() {
+            Ok(backend) => backend,
+            Err(e) => {
+                println!("{:<8} skipped: {}", kind.name(), e);
+                continue;
+            }
+        };
+        let start = Instant::now();
+        let document = match backend.open(&path, None) {
+            Ok(document) => document,
+            Err(e) => {
+                println!("{:<8} skipped: {}", kind.name(), e);
+                continue;
+            }
+        };
+        let open_time = start.elapsed();
+        let pages = document.page_count().min(MAX_PAGES);
+        println!(
+            "{:<8} open {:>9.2?} ({} pages rendered)",
+            kind.name(),
+            open_time,
+            pages
+        );
+        for dpi in [PAGE_DPI, THUMBNAIL_DPI] {
+            let mut times = Vec::with_capacity(pages);
+            for page in 0..pages {
+                let start = Instant::now();
+                if let Err(e) = document.render(page, dpi) {
+                    println!("{:<8} {}", kind.name(), e);
+                    continue;
+                }
+                times.push(start.elapsed());
+            }
+            print_latency(kind.name(), dpi, &mut times);
+        }
+    }
+}
+
+fn print_latency(backend: &str, dpi: u32, times: &mut [Duration]) {
+    if times.is_empty() {
+        return;
+    }
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / times.len() as u32;
+    println!(
+        "{:<8} {:>3} dpi  mean {:>9.2?}  median {:>9.2?}  min {:>9.2?}  max {:>9.2?}",
+        backend,
+        dpi,
+        mean,
+        times[times.len() / 2],
+        times[0],
+        times[times.len() - 1]
+    );
+}
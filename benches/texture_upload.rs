@@ -0,0 +1,75 @@
+//! Time taken to turn a rendered page into a texture, with the pixbuf conversion used before and with memory textures.\
+//! Run with `cargo bench --bench texture_upload`. No display is needed, since textures are only uploaded when drawn.
+use fiapo::core::image::{texture_from_image, texture_from_shared};
+use gtk4::{gdk, gdk_pixbuf, glib};
+use image::{DynamicImage, GrayImage, RgbImage};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+/// The conversion before memory textures: RGBA8, then a copy into `glib::Bytes`, then a pixbuf
+fn texture_from_pixbuf(img: &DynamicImage) -> gdk::Texture {
+    let rgba_img = img.to_rgba8();
+    let (width, height) = rgba_img.dimensions();
+    let bytes = rgba_img.into_raw();
+    let pixbuf = gdk_pixbuf::Pixbuf::from_bytes(
+        &glib::Bytes::from(&bytes),
+        gdk_pixbuf::Colorspace::Rgb,
+        true,
+        8,
+        width as i32,
+        height as i32,
+        (width * 4) as i32,
+    );
+    gdk::Texture::for_pixbuf(&pixbuf)
+}
+
+/// Scanned-looking page: a gradient, so the pixels aren't all the same
+fn page(width: u32, height: u32, color: bool) -> DynamicImage {
+    if color {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+        }))
+    } else {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([((x ^ y) % 256) as u8])
+        }))
+    }
+}
+
+/// Mean time of `convert`, leaving out the time `setup` takes to prepare its input
+fn measure<T>(setup: impl Fn() -> T, convert: impl Fn(T) -> gdk::Texture) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let input = setup();
+        let start = Instant::now();
+        black_box(convert(input));
+        total += start.elapsed();
+    }
+    total / ITERATIONS
+}
+
+fn main() {
+    // A4 pages at the resolution of the reader, and at the resolution of high quality scans
+    for (width, height) in [(1240, 1754), (2480, 3508)] {
+        for color in [true, false] {
+            let img = page(width, height, color);
+            let shared = Arc::new(img.clone());
+            let name = format!("{}x{} {}", width, height, if color { "RGB8" } else { "L8" });
+            let before = measure(|| &img, texture_from_pixbuf);
+            let owned = measure(|| img.clone(), texture_from_image);
+            let shared = measure(|| Arc::clone(&shared), texture_from_shared);
+            println!(
+                "{:<15} pixbuf {:>9.2?}  owned {:>9.2?} ({:>5.1}x)  shared {:>9.2?} ({:>5.1}x)",
+                name,
+                before,
+                owned,
+                before.as_secs_f64() / owned.as_secs_f64(),
+                shared,
+                before.as_secs_f64() / shared.as_secs_f64()
+            );
+        }
+    }
+}
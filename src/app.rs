@@ -1,27 +1,73 @@
-use crate::core::config::{FiapoConfig, resolve_config_path};
-use crate::core::reader::Server;
-use crate::ui::home::Home;
-use crate::ui::reader::Reader;
-use gtk::prelude::GtkWindowExt;
-use gtk::{Application, ApplicationWindow, Stack, gdk};
+use crate::core::config::{ConfigIssue, FiapoConfig};
+use crate::core::crop::CropBox;
+use crate::core::error::SourceError;
+use crate::core::keybindings::Action;
+use crate::core::layout::Rotation;
+use crate::core::logging;
+use crate::core::paths::AppPaths;
+use crate::core::pdf;
+use crate::core::reader::{self, FilePage, Server, Source, SourceType, Volume};
+use crate::core::scanner;
+use crate::core::theme;
+use crate::server::database::Library;
+use crate::server::keyring::Keyring;
+use crate::server::opds::OpdsServer;
+use crate::server::watcher::{self, LibraryWatcher, RescanReport};
+use crate::ui::actions::ActionRegistry;
+use crate::ui::components::card::LibrarySourceObject;
+use crate::ui::components::notification::Notification;
+use crate::ui::components::password_dialog;
+use crate::ui::diagnostics::{DIAGNOSTICS_SCREEN, Diagnostics};
+use crate::ui::home::{HOME_SCREEN, Home};
+use crate::ui::reader::{READER_SCREEN, Reader};
+use crate::ui::settings::{SETTINGS_SCREEN, Settings};
+use gtk::prelude::{ApplicationExt, GtkWindowExt, ListModelExt, ObjectExt, WidgetExt};
+use gtk::{Application, ApplicationWindow, Stack, gdk, gio, glib};
 use gtk4 as gtk;
-use log::{error, info};
+use log::{error, info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::SystemTime;
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct FiapoController {
+    /// Where the config, library and caches are kept
+    pub paths: AppPaths,
     pub config: FiapoConfig,
-    pub config_path: Option<PathBuf>,
+    /// Problems found in the config file the last time it was loaded
+    pub config_issues: Vec<ConfigIssue>,
+    /// Modification time of the config file the last time it was loaded, to skip duplicated change events
+    config_modified: Option<SystemTime>,
+    config_watcher: Option<RecommendedWatcher>,
     pub window: ApplicationWindow,
     pub view_stack: Stack,
     pub server: Server,
+    /// Title in the library of the series in the reader, whose filters override the global ones
+    reading_series: Option<String>,
+    pub library: Option<Library>,
+    /// Encrypts the PDF passwords saved in the library
+    keyring: Option<Keyring>,
+    /// Sources of the library shown in the home screen, kept in sync with the database by `refresh_library_model`
+    pub library_model: gio::ListStore,
+    library_watcher: Option<LibraryWatcher>,
+    pub opds_server: Option<OpdsServer>,
+    /// Handlers of the actions bound in the keymap
+    pub actions: ActionRegistry,
+    pub notification: Notification,
+    /// Styles built from the theme of the config
+    theme_provider: gtk::CssProvider,
+    /// Styles of the user, loaded from `AppPaths::user_css_file` on top of everything else
+    user_css_provider: gtk::CssProvider,
 }
 
 impl FiapoController {
-    pub fn new(app: &Application) -> Self {
+    pub fn new(app: &Application, paths: AppPaths) -> Self {
         let config = FiapoConfig::defaults();
 
         let window = ApplicationWindow::builder()
@@ -39,11 +85,24 @@ impl FiapoController {
         let server = Server::new();
 
         Self {
+            paths,
             config,
-            config_path: None,
+            config_issues: Vec::new(),
+            config_modified: None,
+            config_watcher: None,
             window,
             view_stack,
             server: server,
+            reading_series: None,
+            library: None,
+            keyring: None,
+            library_model: gio::ListStore::new::<LibrarySourceObject>(),
+            library_watcher: None,
+            opds_server: None,
+            actions: ActionRegistry::default(),
+            notification: Notification::new(),
+            theme_provider: gtk::CssProvider::new(),
+            user_css_provider: gtk::CssProvider::new(),
         }
     }
 
@@ -52,73 +111,861 @@ impl FiapoController {
         let stack = controller.borrow_mut().view_stack.clone();
 
         {
-            let controller = Rc::clone(&controller);
-            let home = Home::new(controller);
+            let home = Home::new(Rc::clone(&controller));
             let home_screen = home.build();
 
-            stack.add_named(&home_screen, Some("home_screen"));
-            stack.set_visible_child_name("home_screen");
+            stack.add_named(&home_screen, Some(HOME_SCREEN));
+            stack.set_visible_child_name(HOME_SCREEN);
 
-            window.set_child(Some(&stack));
+            let overlay = gtk::Overlay::new();
+            overlay.set_child(Some(&stack));
+            overlay.add_overlay(controller.borrow().notification.widget());
+
+            window.set_child(Some(&overlay));
             window.present();
         }
+        controller.borrow().show_config_issues();
+
+        FiapoController::register_global_actions(&controller);
+        let key_handler = gtk::EventControllerKey::new();
+        let weak_controller = Rc::downgrade(&controller);
+        key_handler.connect_key_pressed(move |_, key, _, modifiers| {
+            let Some(controller) = weak_controller.upgrade() else {
+                return gtk::glib::Propagation::Proceed;
+            };
+            // Release the borrows before running the handler, since most of them borrow the controller again
+            let handler = {
+                let ctrl = controller.borrow();
+                let screen = ctrl.view_stack.visible_child_name();
+                ctrl.config
+                    .keymap
+                    .lookup(key, modifiers)
+                    .and_then(|action| ctrl.actions.get(screen.as_deref(), action))
+            };
+            match handler {
+                Some(handler) => {
+                    handler();
+                    gtk::glib::Propagation::Stop
+                }
+                None => gtk::glib::Propagation::Proceed,
+            }
+        });
+        window.add_controller(key_handler);
+    }
+
+    /// Actions available on every screen
+    fn register_global_actions(controller: &Rc<RefCell<FiapoController>>) {
+        let weak_controller = Rc::downgrade(controller);
+        let mut ctrl = controller.borrow_mut();
+        let window = ctrl.window.clone();
+
+        ctrl.actions.register(None, Action::ToggleFullscreen, {
+            let window = window.downgrade();
+            move || {
+                if let Some(window) = window.upgrade() {
+                    window.set_fullscreened(!window.is_fullscreen());
+                }
+            }
+        });
+        ctrl.actions.register(None, Action::GoHome, move || {
+            if let Some(controller) = weak_controller.upgrade() {
+                controller.borrow_mut().go_home();
+            }
+        });
+        ctrl.actions.register(None, Action::RescanLibrary, {
+            let weak_controller = Rc::downgrade(controller);
+            move || {
+                if let Some(controller) = weak_controller.upgrade() {
+                    FiapoController::rescan_library(&controller);
+                }
+            }
+        });
+        ctrl.actions.register(None, Action::Quit, {
+            let window = window.downgrade();
+            move || {
+                if let Some(app) = window.upgrade().and_then(|window| window.application()) {
+                    app.quit();
+                }
+            }
+        });
     }
 
-    pub fn go_home(&self) {
-        self.view_stack.set_visible_child_name("home_screen");
+    /// Show the home screen. The reader can't be shown again, so its handlers and the reader they hold are dropped
+    pub fn go_home(&mut self) {
+        self.view_stack.set_visible_child_name(HOME_SCREEN);
+        self.actions.unregister_screen(READER_SCREEN);
     }
     pub fn open_reader(controller: Rc<RefCell<FiapoController>>) {
-        if let Some(old_reader) = controller
-            .borrow()
-            .view_stack
-            .child_by_name("reader_screen")
-        {
+        if let Some(old_reader) = controller.borrow().view_stack.child_by_name(READER_SCREEN) {
             controller.borrow().view_stack.remove(&old_reader);
         }
 
         let stack = controller.borrow_mut().view_stack.clone();
         let reader = Rc::new(RefCell::new(Reader::new(controller)));
         let reader_screen = Reader::build(reader);
-        stack.add_named(&reader_screen, Some("reader_screen"));
-        stack.set_visible_child_name("reader_screen");
+        stack.add_named(&reader_screen, Some(READER_SCREEN));
+        stack.set_visible_child_name(READER_SCREEN);
+    }
+    /// Show the recent logs and the bug report tools
+    pub fn open_diagnostics(controller: Rc<RefCell<FiapoController>>) {
+        if let Some(old_diagnostics) = controller
+            .borrow()
+            .view_stack
+            .child_by_name(DIAGNOSTICS_SCREEN)
+        {
+            controller.borrow().view_stack.remove(&old_diagnostics);
+        }
+
+        let stack = controller.borrow().view_stack.clone();
+        let diagnostics_screen = Diagnostics::new(controller).build();
+        stack.add_named(&diagnostics_screen, Some(DIAGNOSTICS_SCREEN));
+        stack.set_visible_child_name(DIAGNOSTICS_SCREEN);
+    }
+    /// Show the settings screen, built again so it reflects the current config
+    pub fn open_settings(controller: Rc<RefCell<FiapoController>>) {
+        if let Some(old_settings) = controller
+            .borrow()
+            .view_stack
+            .child_by_name(SETTINGS_SCREEN)
+        {
+            controller.borrow().view_stack.remove(&old_settings);
+        }
+
+        let stack = controller.borrow().view_stack.clone();
+        let settings_screen = Settings::new(controller).build();
+        stack.add_named(&settings_screen, Some(SETTINGS_SCREEN));
+        stack.set_visible_child_name(SETTINGS_SCREEN);
+    }
+
+    /// Load every chapter of a library source into the server and open the reader
+    pub fn open_library_source(controller: Rc<RefCell<FiapoController>>, source_id: i64) {
+        FiapoController::read_library_source(controller, source_id, true);
+    }
+
+    /// `ask_passwords` asks for the passwords of the encrypted PDFs first, then opens the source again
+    fn read_library_source(
+        controller: Rc<RefCell<FiapoController>>,
+        source_id: i64,
+        ask_passwords: bool,
+    ) {
+        let chapters = match &controller.borrow().library {
+            Some(library) => library.list_chapters(source_id),
+            None => Err(anyhow::anyhow!("Library is unavailable")),
+        };
+        let chapters = match chapters {
+            Ok(chapters) => chapters,
+            Err(e) => {
+                error!("Could not open source {}: {}", source_id, e);
+                return;
+            }
+        };
+
+        let mut failures: Vec<SourceError> = Vec::new();
+        let mut sources: Vec<(Option<i32>, Source)> = Vec::new();
+        // Chapters of a file that holds several are sections of it, so the file is only opened once
+        let mut file: Option<Source> = None;
+        for (i, chapter) in chapters
+            .into_iter()
+            .filter(|chapter| !chapter.missing)
+            .enumerate()
+        {
+            let source = match chapter.pages() {
+                Some(pages) => {
+                    if file
+                        .as_ref()
+                        .is_none_or(|file| *file.get_path() != chapter.path)
+                    {
+                        file = controller
+                            .borrow()
+                            .open_source(chapter.path.clone(), false)
+                            .inspect_err(|e| {
+                                error!("{}", e);
+                                failures.push(e.clone());
+                            })
+                            .ok();
+                    }
+                    let Some(file) = &file else {
+                        continue;
+                    };
+                    // Read once for every section, instead of once per section
+                    file.table_of_contents();
+                    file.section(&chapter.title, pages)
+                }
+                None => match controller.borrow().open_source(chapter.path, i == 0) {
+                    Ok(source) => source,
+                    Err(e) => {
+                        error!("{}", e);
+                        failures.push(e);
+                        continue;
+                    }
+                },
+            };
+            if source.get_page_count() > 0 {
+                sources.push((chapter.volume_number, source));
+            }
+        }
+        let volumes = Volume::group(sources);
+        let locked = locked_pdfs(&failures);
+        if ask_passwords && !locked.is_empty() {
+            let weak_controller = Rc::downgrade(&controller);
+            FiapoController::ask_pdf_passwords(&controller, locked, move || {
+                if let Some(controller) = weak_controller.upgrade() {
+                    FiapoController::read_library_source(controller, source_id, false);
+                }
+            });
+            return;
+        }
+        controller
+            .borrow()
+            .show_source_errors("Some chapters could not be opened", &failures);
+        if volumes.is_empty() {
+            warn!("Source {} has nothing to read", source_id);
+            return;
+        }
+
+        {
+            let mut ctrl = controller.borrow_mut();
+            ctrl.reading_series = ctrl.series_title(source_id);
+            ctrl.apply_filters();
+            ctrl.load_page_settings(&volumes);
+            ctrl.server.set_volumes(volumes);
+        }
+        FiapoController::open_reader(controller);
+    }
+
+    /// Import files and directories into the library, load them into the server and open the reader.\
+    /// Used by the open button and by files opened from the command line or a file manager.
+    pub fn import_and_read(controller: Rc<RefCell<FiapoController>>, paths: &[PathBuf]) {
+        FiapoController::read_paths(controller, paths.to_vec(), true);
+    }
+
+    /// `ask_passwords` asks for the passwords of the encrypted PDFs first, then imports the paths again
+    fn read_paths(
+        controller: Rc<RefCell<FiapoController>>,
+        paths: Vec<PathBuf>,
+        ask_passwords: bool,
+    ) {
+        let mut sources: Vec<Source> = Vec::new();
+        let mut failures: Vec<SourceError> = Vec::new();
+        let mut series_id: Option<i64> = None;
+
+        for (i, path) in scanner::scan_paths(&paths).into_iter().enumerate() {
+            let str_path = path.display().to_string();
+
+            let should_keep_pdf_object: bool = i == 0;
+            let source = match controller
+                .borrow()
+                .open_source(path, should_keep_pdf_object)
+            {
+                Ok(source) => source,
+                Err(e) => {
+                    error!("{}", e);
+                    failures.push(e);
+                    continue;
+                }
+            };
+            let page_count = source.get_page_count();
+
+            // Ignore empty files
+            if page_count == 0 {
+                warn!("Skipping empty file: {}", str_path);
+                continue;
+            }
+
+            if let Some(library) = &controller.borrow().library {
+                match library.import_source(&source) {
+                    Ok(chapter_id) if series_id.is_none() => {
+                        series_id = library
+                            .get_chapter(chapter_id)
+                            .ok()
+                            .flatten()
+                            .map(|chapter| chapter.source_id);
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Could not add `{}` to the library: {}", str_path, e),
+                }
+            }
+
+            sources.push(source);
+        }
+        controller.borrow().refresh_library_model();
+        let locked = locked_pdfs(&failures);
+        if ask_passwords && !locked.is_empty() {
+            let weak_controller = Rc::downgrade(&controller);
+            FiapoController::ask_pdf_passwords(&controller, locked, move || {
+                if let Some(controller) = weak_controller.upgrade() {
+                    FiapoController::read_paths(controller, paths, false);
+                }
+            });
+            return;
+        }
+        controller
+            .borrow()
+            .show_source_errors("Some files could not be imported", &failures);
+        if sources.is_empty() {
+            warn!("Nothing to read in {:?}", paths);
+            return;
+        }
+
+        sources.sort();
+        let volumes = Volume::group(sources.into_iter().flat_map(|source| {
+            let volume = source.get_comic_info().and_then(|info| info.volume);
+            // PDF volumes are read chapter by chapter, like they were imported. See `Library::import_source`
+            let chapters = source.table_of_contents().chapters(source.get_page_count());
+            if chapters.is_empty() {
+                return vec![(volume, source)];
+            }
+            chapters
+                .into_iter()
+                .map(|chapter| (volume, source.section(&chapter.title, chapter.pages)))
+                .collect()
+        }));
+
+        {
+            let mut ctrl = controller.borrow_mut();
+            ctrl.reading_series = series_id.and_then(|id| ctrl.series_title(id));
+            ctrl.apply_filters();
+            ctrl.load_page_settings(&volumes);
+            ctrl.server.set_volumes(volumes);
+        }
+        FiapoController::open_reader(controller);
+    }
+
+    /// Title of a source of the library, which is the series its filters are set for. See `FiltersConfig::for_series`
+    fn series_title(&self, source_id: i64) -> Option<String> {
+        let library = self.library.as_ref()?;
+        match library.get_source(source_id) {
+            Ok(source) => source.map(|source| source.title),
+            Err(e) => {
+                warn!("Could not read source {}: {}", source_id, e);
+                None
+            }
+        }
+    }
+
+    /// Give the server the crop and rotation set by hand for the pages of `volumes`, from the library
+    fn load_page_settings(&mut self, volumes: &[Volume]) {
+        let mut crops: HashMap<FilePage, CropBox> = HashMap::new();
+        let mut rotations: HashMap<FilePage, Rotation> = HashMap::new();
+        if let Some(library) = &self.library {
+            for source in volumes.iter().flat_map(Volume::get_chapters) {
+                let first_page = source.get_file_pages().start;
+                let file_page = |page: usize| (source.get_path().clone(), first_page + page);
+                let result = library
+                    .get_chapter_id(source.get_path(), first_page)
+                    .and_then(|id| match id {
+                        Some(id) => {
+                            Ok((library.get_page_crops(id)?, library.get_page_rotations(id)?))
+                        }
+                        None => Ok(Default::default()),
+                    });
+                match result {
+                    Ok((page_crops, page_rotations)) => {
+                        crops.extend(
+                            page_crops
+                                .into_iter()
+                                .map(|(page, crop)| (file_page(page), crop)),
+                        );
+                        rotations.extend(
+                            page_rotations
+                                .into_iter()
+                                .map(|(page, rotation)| (file_page(page), rotation)),
+                        );
+                    }
+                    Err(e) => warn!(
+                        "Could not read the settings of the pages of `{}`: {}",
+                        source.get_path().display(),
+                        e
+                    ),
+                }
+            }
+        }
+        self.server.set_crop_overrides(crops);
+        self.server.set_rotations(rotations);
+    }
+
+    /// Apply the filters of the config to the pages, with the ones of the series being read.
+    /// Returns whether they changed
+    pub fn apply_filters(&mut self) -> bool {
+        let filters = self
+            .config
+            .filters
+            .for_series(self.reading_series.as_deref());
+        self.server.set_filters(filters)
     }
 
     pub fn get_window(&self) -> ApplicationWindow {
         self.window.clone()
     }
 
-    pub fn load_config(&mut self, path: &str) {
-        let resolve_path = resolve_config_path(path);
-        self.config_path = resolve_path.clone();
-        match resolve_path {
-            Some(config_path) => self.config.parse_config_file(config_path),
-            _ => {}
-        }
-    }
-
-    pub fn load_css(&self, file_path: &str) {
-        if let Some(display) = gdk::Display::default() {
-            let entry_path = PathBuf::from(file!());
-            if let Some(srcdir) = entry_path.parent() {
-                // 'canonicalize' might be overkill, but...
-                if let Ok(working_dir) = fs::canonicalize(&srcdir) {
-                    let css_path = working_dir.join(file_path);
-                    let provider = gtk::CssProvider::new();
-                    provider.load_from_resource("/github/uiriansan/fiapo/styles/main.css");
-                    gtk::style_context_add_provider_for_display(
-                        &display,
-                        &provider,
-                        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    pub fn load_config(&mut self) {
+        let config_path = self.paths.config_file.clone();
+        if config_path.exists() {
+            self.config_modified = modification_time(&config_path);
+            self.config_issues = self.config.parse_config_file(config_path);
+        } else {
+            warn!(
+                "Config not found in `{}`. Loading defaults...",
+                config_path.display()
+            );
+        }
+        self.server
+            .set_preloaded_pages(self.config.cache.preloaded_pages);
+        pdf::set_backend(self.config.reader.pdf_backend());
+        self.server.set_auto_crop(self.config.reader.auto_crop());
+        self.server
+            .set_split_wide_pages(self.config.reader.split_wide_pages);
+    }
+
+    /// Open a source, unlocking encrypted PDFs with their saved password
+    fn open_source(&self, path: PathBuf, keep_pdf_object: bool) -> Result<Source, SourceError> {
+        let source_type = Source::source_type_for_path(&path)?;
+        match Source::open(source_type.clone(), path.clone(), keep_pdf_object) {
+            Err(SourceError::Encrypted { .. }) if self.unlock_with_saved_password(&path) => {
+                Source::open(source_type, path, keep_pdf_object)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns whether the PDF was unlocked. Saved passwords that stopped working are forgotten
+    fn unlock_with_saved_password(&self, path: &Path) -> bool {
+        let (Some(library), Some(keyring)) = (&self.library, &self.keyring) else {
+            return false;
+        };
+        let sealed = match library.get_pdf_password(path) {
+            Ok(Some(sealed)) => sealed,
+            Ok(None) => return false,
+            Err(e) => {
+                warn!("Could not read the password of `{}`: {}", path.display(), e);
+                return false;
+            }
+        };
+        let result = keyring
+            .unseal(path, &sealed)
+            .and_then(|password| Ok(reader::unlock_pdf(path, &password)?));
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Saved password of `{}` doesn't work: {}", path.display(), e);
+                if let Err(e) = library.forget_pdf_password(path) {
+                    warn!(
+                        "Could not forget the password of `{}`: {}",
+                        path.display(),
+                        e
                     );
-                    info!("Loading styles from {}...", css_path.to_str().unwrap());
-                } else {
-                    error!("Failed to resolve path for styles");
                 }
-            } else {
-                error!("Failed to resolve path for styles");
+                false
             }
-        } else {
+        }
+    }
+
+    fn save_pdf_password(&self, path: &Path, password: &str) {
+        let (Some(library), Some(keyring)) = (&self.library, &self.keyring) else {
+            warn!("Library is unavailable. Can't save the password");
+            return;
+        };
+        let result = keyring
+            .seal(path, password)
+            .and_then(|sealed| library.save_pdf_password(path, &sealed));
+        if let Err(e) = result {
+            warn!("Could not save the password of `{}`: {}", path.display(), e);
+        }
+    }
+
+    /// Ask for the passwords of encrypted PDFs one after the other, then call `done`
+    fn ask_pdf_passwords(
+        controller: &Rc<RefCell<FiapoController>>,
+        mut paths: Vec<PathBuf>,
+        done: impl FnOnce() + 'static,
+    ) {
+        if paths.is_empty() {
+            done();
+            return;
+        }
+        let path = paths.remove(0);
+        let window = controller.borrow().window.clone();
+        let weak_controller = Rc::downgrade(controller);
+        password_dialog::ask_pdf_password(
+            &window,
+            &path.clone(),
+            {
+                let weak_controller = weak_controller.clone();
+                move |password, remember| {
+                    reader::unlock_pdf(&path, password)?;
+                    if remember && let Some(controller) = weak_controller.upgrade() {
+                        controller.borrow().save_pdf_password(&path, password);
+                    }
+                    Ok(())
+                }
+            },
+            move || {
+                // The dialog may close while the controller is borrowed, e.g. by a key handler
+                glib::idle_add_local_once(move || {
+                    if let Some(controller) = weak_controller.upgrade() {
+                        FiapoController::ask_pdf_passwords(&controller, paths, done);
+                    }
+                });
+            },
+        );
+    }
+
+    /// Show the files that couldn't be opened in a notification, one per line
+    pub fn show_source_errors(&self, title: &str, errors: &[SourceError]) {
+        if errors.is_empty() {
+            return;
+        }
+        let body = errors
+            .iter()
+            .map(SourceError::to_string)
+            .collect::<Vec<String>>()
+            .join("\n");
+        self.notification.show(title, &body);
+    }
+
+    /// Show the problems of the config file in a notification, or hide it if there are none
+    pub fn show_config_issues(&self) {
+        if self.config_issues.is_empty() {
+            self.notification.hide();
+            return;
+        }
+        let body = self
+            .config_issues
+            .iter()
+            .map(ConfigIssue::to_string)
+            .collect::<Vec<String>>()
+            .join("\n");
+        self.notification.show(
+            "Invalid config. These keys were reset to their defaults:",
+            &body,
+        );
+    }
+
+    /// Reload the config and the CSS file of the user whenever they change.\
+    /// The directory is watched instead of the files, since most editors save by replacing the file.
+    /// Until it's created, e.g. when the settings are saved for the first time, its nearest parent is watched instead.
+    pub fn watch_config(controller: &Rc<RefCell<FiapoController>>) {
+        let config_path = controller.borrow().paths.config_file.clone();
+        let user_css_path = controller.borrow().paths.user_css_file();
+        let config_dir = controller.borrow().paths.config_dir.clone();
+        let Some(watched_dir) = config_dir.ancestors().find(|dir| dir.is_dir()) else {
+            return;
+        };
+        let watched_dir = watched_dir.to_path_buf();
+        let waiting = watched_dir != config_dir;
+
+        let weak_controller = Arc::new(fragile::Sticky::new(Rc::downgrade(controller)));
+        let watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if waiting => {
+                    let created = event.kind.is_create()
+                        && event.paths.iter().any(|path| config_dir.starts_with(path));
+                    if !created {
+                        return;
+                    }
+                    // Watch the new directory, or its next missing parent. The config may already be in it
+                    let weak_controller = Arc::clone(&weak_controller);
+                    glib::MainContext::default().invoke(move || {
+                        fragile::stack_token!(tok);
+                        if let Some(controller) = weak_controller.get(tok).upgrade() {
+                            FiapoController::watch_config(&controller);
+                            FiapoController::reload_config(&controller);
+                        }
+                    });
+                }
+                Ok(event) => {
+                    let changed = |path: &PathBuf| event.paths.contains(path);
+                    let config_changed =
+                        (event.kind.is_create() || event.kind.is_modify()) && changed(&config_path);
+                    // Removing the CSS file goes back to the theme alone
+                    let user_css_changed = (event.kind.is_create()
+                        || event.kind.is_modify()
+                        || event.kind.is_remove())
+                        && changed(&user_css_path);
+                    if !config_changed && !user_css_changed {
+                        return;
+                    }
+
+                    let weak_controller = Arc::clone(&weak_controller);
+                    glib::MainContext::default().invoke(move || {
+                        fragile::stack_token!(tok);
+                        if let Some(controller) = weak_controller.get(tok).upgrade() {
+                            if config_changed {
+                                FiapoController::reload_config(&controller);
+                            } else {
+                                controller.borrow().apply_theme();
+                            }
+                        }
+                    });
+                }
+                Err(e) => warn!("Error watching the config file: {}", e),
+            })
+            .and_then(|mut watcher| {
+                watcher.watch(&watched_dir, RecursiveMode::NonRecursive)?;
+                Ok(watcher)
+            });
+
+        match watcher {
+            Ok(watcher) => controller.borrow_mut().config_watcher = Some(watcher),
+            Err(e) => warn!(
+                "Could not watch `{}` for changes: {}",
+                watched_dir.display(),
+                e
+            ),
+        }
+    }
+
+    /// Read the config file again and apply it to the running app
+    pub fn reload_config(controller: &Rc<RefCell<FiapoController>>) {
+        let mut ctrl = controller.borrow_mut();
+        let config_path = ctrl.paths.config_file.clone();
+        let modified = modification_time(&config_path);
+        if modified.is_none() || modified == ctrl.config_modified {
+            return;
+        }
+        ctrl.config_modified = modified;
+
+        info!("Config file changed. Reloading...");
+        let opds = ctrl.config.opds.clone();
+        let log = ctrl.config.log.clone();
+        let library_folders = ctrl.config.library.folders.clone();
+        let mut config = FiapoConfig::defaults();
+        ctrl.config_issues = config.parse_config_file(config_path);
+        ctrl.config = config;
+        let preloaded_pages = ctrl.config.cache.preloaded_pages;
+        ctrl.server.set_preloaded_pages(preloaded_pages);
+        pdf::set_backend(ctrl.config.reader.pdf_backend());
+        let auto_crop = ctrl.config.reader.auto_crop();
+        ctrl.server.set_auto_crop(auto_crop);
+        let split_wide_pages = ctrl.config.reader.split_wide_pages;
+        ctrl.server.set_split_wide_pages(split_wide_pages);
+        ctrl.apply_filters();
+        ctrl.apply_theme();
+        if ctrl.config.log != log {
+            logging::set_filters(&ctrl.config.log);
+        }
+
+        // Keybindings and reader options are read when they're used, so only the OPDS server needs to be restarted
+        if ctrl.config.opds != opds {
+            // Dropping the server stops it
+            ctrl.opds_server = None;
+            ctrl.start_opds_server();
+        }
+        ctrl.show_config_issues();
+        let library_folders_changed = ctrl.config.library.folders != library_folders;
+        drop(ctrl);
+        if library_folders_changed {
+            FiapoController::watch_library(controller);
+        }
+    }
+
+    pub fn load_library(&mut self) {
+        let library_path = self.paths.library_file();
+        match Library::open(&library_path) {
+            Ok(library) => self.library = Some(library),
+            Err(e) => error!(
+                "Failed to open library in `{}`: {}",
+                library_path.display(),
+                e
+            ),
+        }
+        match Keyring::open(&self.paths.keyring_key_file()) {
+            Ok(keyring) => self.keyring = Some(keyring),
+            Err(e) => warn!("PDF passwords can't be saved: {}", e),
+        }
+        self.refresh_library_model();
+    }
+
+    /// Load the sources of the library into `library_model` again
+    pub fn refresh_library_model(&self) {
+        let sources = match &self.library {
+            Some(library) => library.list_sources().unwrap_or_else(|e| {
+                warn!("Could not list the library: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let objects: Vec<LibrarySourceObject> =
+            sources.into_iter().map(LibrarySourceObject::new).collect();
+        self.library_model
+            .splice(0, self.library_model.n_items(), &objects);
+    }
+
+    /// Watch the library folders of the config, importing new files and marking the removed ones as missing.\
+    /// Replaces the previous watcher, so it's called again when the folders change.
+    pub fn watch_library(controller: &Rc<RefCell<FiapoController>>) {
+        let (library_path, folders) = {
+            let mut ctrl = controller.borrow_mut();
+            // Dropping the watcher stops it
+            ctrl.library_watcher = None;
+            let Some(library) = &ctrl.library else {
+                return;
+            };
+            (
+                library.get_path().to_path_buf(),
+                ctrl.config.library.folder_paths(),
+            )
+        };
+        if folders.is_empty() {
+            return;
+        }
+
+        let weak_controller = Arc::new(fragile::Sticky::new(Rc::downgrade(controller)));
+        let watcher = LibraryWatcher::start(library_path, folders, move |_| {
+            let weak_controller = Arc::clone(&weak_controller);
+            glib::MainContext::default().invoke(move || {
+                fragile::stack_token!(tok);
+                if let Some(controller) = weak_controller.get(tok).upgrade() {
+                    controller.borrow().refresh_library_model();
+                }
+            });
+        });
+        match watcher {
+            Ok(watcher) => controller.borrow_mut().library_watcher = Some(watcher),
+            Err(e) => warn!("Could not watch the library folders: {}", e),
+        }
+    }
+
+    /// Rescan the library folders in the background and show what changed
+    pub fn rescan_library(controller: &Rc<RefCell<FiapoController>>) {
+        let (library_path, folders) = {
+            let ctrl = controller.borrow();
+            let Some(library) = &ctrl.library else {
+                warn!("Library is unavailable. Can't rescan it");
+                return;
+            };
+            (
+                library.get_path().to_path_buf(),
+                ctrl.config.library.folder_paths(),
+            )
+        };
+        info!("Rescanning the library...");
+
+        let weak_controller = Arc::new(fragile::Sticky::new(Rc::downgrade(controller)));
+        thread::spawn(move || {
+            let result: anyhow::Result<RescanReport> = Library::open(&library_path)
+                .and_then(|library| watcher::rescan(&library, &folders));
+            glib::MainContext::default().invoke(move || {
+                fragile::stack_token!(tok);
+                let Some(controller) = weak_controller.get(tok).upgrade() else {
+                    return;
+                };
+                let ctrl = controller.borrow();
+                match result {
+                    Ok(report) => {
+                        ctrl.refresh_library_model();
+                        ctrl.notification
+                            .show("Library rescanned", &report.summary());
+                    }
+                    Err(e) => {
+                        error!("Could not rescan the library: {}", e);
+                        ctrl.notification
+                            .show("Could not rescan the library", &e.to_string());
+                    }
+                }
+            });
+        });
+    }
+
+    /// Write the library metadata of the archives open in the reader to their ComicInfo.xml
+    pub fn write_comic_info(&self) {
+        let Some(library) = &self.library else {
+            warn!("Library is unavailable. Can't write ComicInfo.xml");
+            return;
+        };
+        for source in self.server.get_chapters() {
+            if *source.get_source_type() != SourceType::Archive {
+                continue;
+            }
+            let path = source.get_path();
+            let result = library.get_chapter_id(path, 0).and_then(|id| match id {
+                Some(id) => library.write_comic_info(id),
+                None => Err(anyhow::anyhow!("not in the library")),
+            });
+            match result {
+                Ok(()) => info!("Wrote ComicInfo.xml to {}", path.display()),
+                Err(e) => error!(
+                    "Failed to write ComicInfo.xml to `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Start serving the library over OPDS if it's enabled in the config
+    pub fn start_opds_server(&mut self) {
+        if !self.config.opds.enabled || self.opds_server.is_some() {
+            return;
+        }
+        let Some(library) = &self.library else {
+            warn!("Library is unavailable. Not starting the OPDS server");
+            return;
+        };
+        match OpdsServer::start(&self.config.opds, library.get_path().to_path_buf()) {
+            Ok(server) => self.opds_server = Some(server),
+            Err(e) => error!("Failed to start the OPDS server: {}", e),
+        }
+    }
+
+    /// Load the app styles, the theme of the config and the CSS file of the user, in increasing priority
+    pub fn load_css(&self) {
+        let Some(display) = gdk::Display::default() else {
             error!("Could not retrieve default `Gdk.Display`");
+            return;
+        };
+
+        let provider = gtk::CssProvider::new();
+        provider.load_from_resource("/github/uiriansan/fiapo/styles/main.css");
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &self.theme_provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &self.user_css_provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 2,
+        );
+        self.user_css_provider
+            .connect_parsing_error(|_, section, e| {
+                warn!("Invalid user CSS at {}: {}", section.to_str(), e)
+            });
+
+        self.apply_theme();
+    }
+
+    /// Rebuild the theme from the config and reload the CSS file of the user
+    pub fn apply_theme(&self) {
+        self.theme_provider
+            .load_from_string(&theme::theme_css(&self.config));
+
+        let user_css_path = self.paths.user_css_file();
+        if user_css_path.exists() {
+            info!("Loading user styles from {}...", user_css_path.display());
+            self.user_css_provider.load_from_path(&user_css_path);
+        } else {
+            self.user_css_provider.load_from_string("");
         }
     }
 }
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// PDFs that couldn't be opened because they need a password
+fn locked_pdfs(errors: &[SourceError]) -> Vec<PathBuf> {
+    errors
+        .iter()
+        .filter(|error| matches!(error, SourceError::Encrypted { .. }))
+        .map(|error| error.path().to_path_buf())
+        .collect()
+}
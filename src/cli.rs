@@ -0,0 +1,432 @@
+use crate::core::config::FiapoConfig;
+use crate::core::export::{self, ExportFormat, PageFormat};
+use crate::core::paths::AppPaths;
+use crate::core::reader::Source;
+use crate::core::scanner;
+use crate::server::database::Library;
+use crate::server::download;
+use crate::server::watcher;
+use gtk4::glib;
+use serde::Serialize;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+const USAGE: &str = "Usage: fiapo [COMMAND] [--json]
+
+Without a command, the graphical interface is launched.
+
+Commands:
+  import <PATHS>...                   Import files and directories into the library
+  list                                List the series in the library
+  search <QUERY>                      Search the library
+  progress [SERIES_ID]                Show reading progress
+  export [OPTIONS] <FILES>...         Export files into a single CBZ, PDF or image folder
+  export [OPTIONS] --series <ID>      Export a series from the library
+  convert [OPTIONS] <FILES>...        Same as export
+  download <MANGADEX_CHAPTER_ID>...   Queue MangaDex chapters for download
+  download --list                     Show the download queue
+  download --run                      Download every queued chapter and import it
+  rescan                              Import new files from the library folders and mark removed ones as missing
+  check                               Check the library database for problems
+  help                                Show this message
+
+Export options:
+  -o, --output <PATH>                 Output file or directory (required)
+  --format cbz|pdf|images             Guessed from the output extension if not given
+  --page-format jpeg|png|webp
+  --quality 1-100
+  --max-resolution <PIXELS>
+
+Global options:
+  --config <PATH>                     Use this config file. Also set with the FIAPO_CONFIG variable
+
+Every command accepts --json to print machine-readable output.";
+
+/// Run a headless command if the arguments ask for one.\
+/// Returns `None` if the GTK application should be launched instead.
+pub async fn run(args: &[String], paths: &AppPaths) -> Option<glib::ExitCode> {
+    let command = args.get(1)?;
+    let json = args.iter().any(|arg| arg == "--json");
+    let command_args: Vec<String> = args[2..]
+        .iter()
+        .filter(|arg| *arg != "--json")
+        .cloned()
+        .collect();
+
+    let result = match command.as_str() {
+        "import" => match import(&command_args, paths, json) {
+            // Failures were already reported, just fail
+            Ok(false) => return Some(glib::ExitCode::FAILURE),
+            result => result.map(|_| ()),
+        },
+        "list" => list(paths, json),
+        "search" => search(&command_args, paths, json),
+        "progress" => progress(&command_args, paths, json),
+        "export" | "convert" => export(&command_args, paths, json),
+        "download" => download(&command_args, paths, json).await,
+        "rescan" => rescan(paths, json),
+        "check" => match check(paths, json) {
+            // Problems were already reported, just fail
+            Ok(false) => return Some(glib::ExitCode::FAILURE),
+            result => result.map(|_| ()),
+        },
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => return None,
+    };
+
+    match result {
+        Ok(()) => Some(glib::ExitCode::SUCCESS),
+        Err(e) => {
+            if json {
+                println!("{}", serde_json::json!({ "error": e.to_string() }));
+            } else {
+                eprintln!("{}", e);
+            }
+            Some(glib::ExitCode::FAILURE)
+        }
+    }
+}
+
+fn load_config(paths: &AppPaths) -> FiapoConfig {
+    let mut config = FiapoConfig::defaults();
+    if paths.config_file.exists() {
+        config.parse_config_file(paths.config_file.clone());
+    }
+    config
+}
+
+fn open_library(paths: &AppPaths) -> anyhow::Result<Library> {
+    Library::open(&paths.library_file())
+}
+
+/// Print `value` as JSON, or as text with `to_text`
+fn print<T: Serialize>(json: bool, value: &T, to_text: impl FnOnce(&T) -> String) {
+    if json {
+        match serde_json::to_string_pretty(value) {
+            Ok(output) => println!("{}", output),
+            Err(e) => eprintln!("Could not serialize output: {}", e),
+        }
+    } else {
+        let text = to_text(value);
+        if !text.is_empty() {
+            println!("{}", text);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ImportResult {
+    path: PathBuf,
+    chapter_id: Option<i64>,
+    error: Option<String>,
+}
+
+/// Returns whether every file was imported
+fn import(args: &[String], paths: &AppPaths, json: bool) -> anyhow::Result<bool> {
+    if args.is_empty() {
+        return Err(anyhow::anyhow!("No paths to import\n\n{}", USAGE));
+    }
+    let library = open_library(paths)?;
+    let paths: Vec<PathBuf> = args.iter().map(PathBuf::from).collect();
+
+    let results: Vec<ImportResult> = scanner::scan_paths(&paths)
+        .into_iter()
+        .map(|path| {
+            let result = Source::source_type_for_path(&path)
+                .and_then(|source_type| Source::open(source_type, path.clone(), false))
+                .map_err(anyhow::Error::from)
+                .and_then(|source| {
+                    if source.get_page_count() == 0 {
+                        Err(anyhow::anyhow!("File is empty"))
+                    } else {
+                        library.import_source(&source)
+                    }
+                });
+            match result {
+                Ok(chapter_id) => ImportResult {
+                    path,
+                    chapter_id: Some(chapter_id),
+                    error: None,
+                },
+                Err(e) => ImportResult {
+                    path,
+                    chapter_id: None,
+                    error: Some(e.to_string()),
+                },
+            }
+        })
+        .collect();
+
+    print(json, &results, |results| {
+        results
+            .iter()
+            .map(|result| match &result.error {
+                None => format!("Imported {}", result.path.display()),
+                Some(e) => format!("Failed to import {}: {}", result.path.display(), e),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    Ok(results.iter().all(|result| result.error.is_none()))
+}
+
+fn list(paths: &AppPaths, json: bool) -> anyhow::Result<()> {
+    let sources = open_library(paths)?.list_sources()?;
+    print(json, &sources, |sources| {
+        sources
+            .iter()
+            .map(|source| {
+                format!(
+                    "{}\t{}\t{} pages",
+                    source.id, source.title, source.page_count
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    Ok(())
+}
+
+fn search(args: &[String], paths: &AppPaths, json: bool) -> anyhow::Result<()> {
+    let query = args.join(" ");
+    let sources = open_library(paths)?.search_sources(&query)?;
+    print(json, &sources, |sources| {
+        sources
+            .iter()
+            .map(|source| format!("{}\t{}", source.id, source.title))
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    Ok(())
+}
+
+fn progress(args: &[String], paths: &AppPaths, json: bool) -> anyhow::Result<()> {
+    let library = open_library(paths)?;
+    let sources = match args.first() {
+        Some(id) => vec![
+            library
+                .get_source(id.parse()?)?
+                .ok_or_else(|| anyhow::anyhow!("Unknown series {}", id))?,
+        ],
+        None => library.list_sources()?,
+    };
+    print(json, &sources, |sources| {
+        sources
+            .iter()
+            .map(|source| {
+                format!(
+                    "{}\t{}\t{}/{}\tlast read: {}",
+                    source.id,
+                    source.title,
+                    source.progress,
+                    source.page_count,
+                    source.last_read.as_deref().unwrap_or("never")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    Ok(())
+}
+
+fn export(args: &[String], paths: &AppPaths, json: bool) -> anyhow::Result<()> {
+    let config = load_config(paths);
+    let mut output: Option<PathBuf> = None;
+    let mut format: Option<ExportFormat> = None;
+    let mut series: Option<i64> = None;
+    let mut options = config.export.options(ExportFormat::Cbz);
+    let mut files: Vec<PathBuf> = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for `{}`\n\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--format" => {
+                let name = value()?;
+                format = Some(
+                    ExportFormat::from_name(name)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown format `{}`", name))?,
+                );
+            }
+            "--page-format" => {
+                let name = value()?;
+                options.page_format = PageFormat::from_name(name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown page format `{}`", name))?;
+            }
+            "--quality" => options.quality = value()?.parse()?,
+            "--max-resolution" => options.max_resolution = Some(value()?.parse()?),
+            "--series" => series = Some(value()?.parse()?),
+            _ if arg.starts_with('-') => {
+                return Err(anyhow::anyhow!("Unknown option `{}`\n\n{}", arg, USAGE));
+            }
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+
+    let output = output.ok_or_else(|| anyhow::anyhow!("Missing output\n\n{}", USAGE))?;
+    if let Some(id) = series {
+        files.extend(
+            open_library(paths)?
+                .list_chapters(id)?
+                .into_iter()
+                .map(|chapter| chapter.path),
+        );
+        // Chapters of a PDF volume share its file, which is exported once
+        files.dedup();
+    }
+    if files.is_empty() {
+        return Err(anyhow::anyhow!("No files to export\n\n{}", USAGE));
+    }
+    // Guess the format from the output extension if it wasn't given
+    options.format = format.unwrap_or_else(|| {
+        output
+            .extension()
+            .and_then(|ext| ExportFormat::from_name(&ext.to_string_lossy()))
+            .unwrap_or(ExportFormat::ImageFolder)
+    });
+
+    let mut sources = files
+        .into_iter()
+        .map(|path| Source::open(Source::source_type_for_path(&path)?, path, false))
+        .collect::<Result<Vec<Source>, _>>()?;
+    export::export_sources(&mut sources, &options, &output, |done, total| {
+        if !json {
+            eprint!("\rExporting... {}/{}", done, total);
+        }
+    })?;
+
+    print(json, &serde_json::json!({ "output": output }), |_| {
+        format!("\nExported to {}", output.display())
+    });
+    Ok(())
+}
+
+async fn download(args: &[String], paths: &AppPaths, json: bool) -> anyhow::Result<()> {
+    let library = open_library(paths)?;
+    match args.first().map(String::as_str) {
+        Some("--list") => {}
+        Some("--run") => {
+            let downloads_dir = paths.downloads_dir();
+            for record in library.list_downloads()? {
+                if record.status == "done" {
+                    continue;
+                }
+                let result = async {
+                    let chapter_id = Uuid::parse_str(&record.mangadex_id)?;
+                    let path =
+                        download::download_chapter(chapter_id, &downloads_dir, |done, total| {
+                            if !json {
+                                eprint!(
+                                    "\rDownloading {}... {}/{}",
+                                    record.mangadex_id, done, total
+                                );
+                            }
+                        })
+                        .await?;
+                    let source =
+                        Source::open(Source::source_type_for_path(&path)?, path.clone(), false)?;
+                    library.import_source(&source)?;
+                    anyhow::Ok(path)
+                }
+                .await;
+                if !json {
+                    eprintln!();
+                }
+                match result {
+                    Ok(path) => {
+                        library.set_download_status(record.id, "done", None, Some(&path))?
+                    }
+                    Err(e) => library.set_download_status(
+                        record.id,
+                        "failed",
+                        Some(&e.to_string()),
+                        None,
+                    )?,
+                }
+            }
+        }
+        Some(_) => {
+            for id in args {
+                Uuid::parse_str(id)
+                    .map_err(|e| anyhow::anyhow!("Invalid MangaDex chapter id `{}`: {}", id, e))?;
+                library.queue_download(id)?;
+            }
+        }
+        None => return Err(anyhow::anyhow!("Nothing to download\n\n{}", USAGE)),
+    }
+
+    print(json, &library.list_downloads()?, |downloads| {
+        downloads
+            .iter()
+            .map(|download| {
+                format!(
+                    "{}\t{}\t{}{}",
+                    download.id,
+                    download.mangadex_id,
+                    download.status,
+                    download
+                        .error
+                        .as_ref()
+                        .map(|e| format!(": {}", e))
+                        .unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    Ok(())
+}
+
+fn rescan(paths: &AppPaths, json: bool) -> anyhow::Result<()> {
+    let folders = load_config(paths).library.folder_paths();
+    if folders.is_empty() {
+        eprintln!("No library folders in the config. Only checking for missing files...");
+    }
+    let report = watcher::rescan(&open_library(paths)?, &folders)?;
+    print(json, &report, |report| {
+        let mut lines: Vec<String> = Vec::new();
+        for path in &report.imported {
+            lines.push(format!("Imported {}", path.display()));
+        }
+        for path in &report.missing {
+            lines.push(format!("Missing {}", path.display()));
+        }
+        for path in &report.restored {
+            lines.push(format!("Restored {}", path.display()));
+        }
+        for (path, e) in &report.failed {
+            lines.push(format!("Failed to import {}: {}", path.display(), e));
+        }
+        lines.push(report.summary());
+        lines.join("\n")
+    });
+    Ok(())
+}
+
+/// Returns whether the library is fine
+fn check(paths: &AppPaths, json: bool) -> anyhow::Result<bool> {
+    let report = open_library(paths)?.check_integrity()?;
+    print(json, &report, |report| {
+        let mut lines: Vec<String> = Vec::new();
+        lines.push(format!("Database: {}", report.integrity.join(", ")));
+        for violation in &report.foreign_key_violations {
+            lines.push(format!("Foreign key violation: {}", violation));
+        }
+        for path in &report.missing_files {
+            lines.push(format!("Missing file: {}", path.display()));
+        }
+        if report.is_ok() {
+            lines.push("No problems found".to_string());
+        }
+        lines.join("\n")
+    });
+
+    Ok(report.is_ok())
+}
@@ -0,0 +1,279 @@
+use quick_xml::events::{BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::ZipArchive;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// Name of the metadata file inside CBZ archives
+pub const COMIC_INFO_FILE: &str = "ComicInfo.xml";
+
+/// Subset of the ComicInfo.xml schema (v2.0) used by Fiapo.\
+/// https://anansi-project.github.io/docs/comicinfo/schemas/v2.0
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename = "ComicInfo", rename_all = "PascalCase")]
+pub struct ComicInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    /// Chapter number. It's a string because of extras like "10.5"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub penciller: Option<String>,
+    /// Comma-separated list of genres
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<usize>,
+    #[serde(
+        default,
+        rename = "LanguageISO",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub language_iso: Option<String>,
+    #[serde(default, skip_serializing_if = "Manga::is_unknown")]
+    pub manga: Manga,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<ComicPages>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Manga {
+    #[default]
+    Unknown,
+    No,
+    Yes,
+    YesAndRightToLeft,
+}
+impl Manga {
+    fn is_unknown(&self) -> bool {
+        *self == Manga::Unknown
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ComicPages {
+    #[serde(rename = "Page", default)]
+    pub pages: Vec<ComicPage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ComicPage {
+    /// 0-based index of the page in the archive
+    #[serde(rename = "@Image")]
+    pub image: usize,
+    /// FrontCover, InnerCover, Roundup, Story, Advertisement, Editorial, Letters, Preview, BackCover, Other or Deleted
+    #[serde(rename = "@Type", default, skip_serializing_if = "Option::is_none")]
+    pub page_type: Option<String>,
+    #[serde(
+        rename = "@DoublePage",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub double_page: Option<bool>,
+}
+
+impl ComicInfo {
+    pub fn parse(xml: &str) -> anyhow::Result<Self> {
+        Ok(quick_xml::de::from_str(xml)?)
+    }
+
+    pub fn to_xml(&self) -> anyhow::Result<String> {
+        let body = quick_xml::se::to_string(self)?;
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n{}\n",
+            body
+        ))
+    }
+
+    /// Write the fields set in `self` into an existing ComicInfo.xml.\
+    /// Its other elements, including the ones Fiapo doesn't know about, are kept as they are.
+    pub fn merge_into(&self, xml: &str) -> anyhow::Result<String> {
+        let mut fields = top_level_elements(&quick_xml::se::to_string(self)?)?;
+        let mut reader = Reader::from_str(xml);
+        let mut writer = Writer::new(Vec::new());
+        let mut depth = 0;
+        // Events of the replaced element that are skipped
+        let mut skipping = false;
+        loop {
+            let event = reader.read_event()?;
+            match &event {
+                Event::Eof => break,
+                Event::Start(start) => {
+                    depth += 1;
+                    if depth == 2 {
+                        let name = String::from_utf8_lossy(start.name().as_ref()).to_string();
+                        if let Some(index) = fields.iter().position(|(n, _)| *n == name) {
+                            for field_event in fields.remove(index).1 {
+                                writer.write_event(field_event)?;
+                            }
+                            skipping = true;
+                            continue;
+                        }
+                    }
+                }
+                Event::Empty(start) if depth == 1 => {
+                    let name = String::from_utf8_lossy(start.name().as_ref()).to_string();
+                    if let Some(index) = fields.iter().position(|(n, _)| *n == name) {
+                        for field_event in fields.remove(index).1 {
+                            writer.write_event(field_event)?;
+                        }
+                        continue;
+                    }
+                }
+                Event::End(_) => {
+                    depth -= 1;
+                    if skipping {
+                        skipping = depth > 1;
+                        continue;
+                    }
+                    // Fields the file didn't have go at the end of the root element
+                    if depth == 0 {
+                        for (_, field_events) in fields.drain(..) {
+                            writer.write_event(Event::Text(BytesText::new("  ")))?;
+                            for field_event in field_events {
+                                writer.write_event(field_event)?;
+                            }
+                            writer.write_event(Event::Text(BytesText::new("\n")))?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if !skipping {
+                writer.write_event(event)?;
+            }
+        }
+        if !fields.is_empty() {
+            return Err(anyhow::anyhow!("No ComicInfo element in the existing file"));
+        }
+        Ok(String::from_utf8(writer.into_inner())?)
+    }
+
+    /// Read the ComicInfo.xml of an archive, if it has one
+    pub fn read_from_archive(path: &Path) -> anyhow::Result<Option<Self>> {
+        let mut archive = ZipArchive::new(fs::File::open(path)?)?;
+        read_xml(&mut archive)?
+            .map(|xml| Self::parse(&xml))
+            .transpose()
+    }
+
+    /// Write the fields set in `self` into the ComicInfo.xml of an archive, or add one. See `merge_into`.\
+    /// The archive is rewritten to a temporary file next to it and then moved over the original.
+    pub fn write_to_archive(&self, path: &Path) -> anyhow::Result<()> {
+        let mut archive = ZipArchive::new(fs::File::open(path)?)?;
+        let xml = match read_xml(&mut archive)? {
+            Some(existing) => self.merge_into(&existing)?,
+            None => self.to_xml()?,
+        };
+        let comic_info_index = find_comic_info(&mut archive);
+
+        let tmp_path = path.with_extension("fiapo-tmp");
+        let mut writer = ZipWriter::new(fs::File::create(&tmp_path)?);
+        for i in 0..archive.len() {
+            if Some(i) == comic_info_index {
+                continue;
+            }
+            writer.raw_copy_file(archive.by_index_raw(i)?)?;
+        }
+        writer.start_file(COMIC_INFO_FILE, SimpleFileOptions::default())?;
+        writer.write_all(xml.as_bytes())?;
+        writer.finish()?;
+
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn is_right_to_left(&self) -> bool {
+        self.manga == Manga::YesAndRightToLeft
+    }
+
+    /// Genres, split and trimmed
+    pub fn genres(&self) -> Vec<String> {
+        self.genre
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|g| !g.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+fn read_xml<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+) -> anyhow::Result<Option<String>> {
+    let Some(index) = find_comic_info(archive) else {
+        return Ok(None);
+    };
+    let mut xml = String::new();
+    archive.by_index(index)?.read_to_string(&mut xml)?;
+    Ok(Some(xml))
+}
+
+/// Name and events of each element inside the root element of `xml`
+fn top_level_elements(xml: &str) -> anyhow::Result<Vec<(String, Vec<Event<'static>>)>> {
+    let mut reader = Reader::from_str(xml);
+    let mut elements: Vec<(String, Vec<Event<'static>>)> = Vec::new();
+    let mut depth = 0;
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Eof => break,
+            Event::Start(start) => {
+                depth += 1;
+                if depth == 2 {
+                    let name = String::from_utf8_lossy(start.name().as_ref()).to_string();
+                    elements.push((name, Vec::new()));
+                }
+            }
+            Event::Empty(start) if depth == 1 => {
+                let name = String::from_utf8_lossy(start.name().as_ref()).to_string();
+                elements.push((name, vec![event.into_owned()]));
+                continue;
+            }
+            Event::End(_) => {
+                depth -= 1;
+                if depth == 1
+                    && let Some((_, events)) = elements.last_mut()
+                {
+                    events.push(event.into_owned());
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        if depth >= 2
+            && let Some((_, events)) = elements.last_mut()
+        {
+            events.push(event.into_owned());
+        }
+    }
+    Ok(elements)
+}
+
+/// ComicInfo.xml is usually at the root of the archive, but some tools nest it in a folder
+fn find_comic_info<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>) -> Option<usize> {
+    (0..archive.len()).find(|&i| {
+        archive.name_for_index(i).is_some_and(|name| {
+            Path::new(name)
+                .file_name()
+                .is_some_and(|n| n.eq_ignore_ascii_case(COMIC_INFO_FILE))
+        })
+    })
+}
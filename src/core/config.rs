@@ -1,119 +1,709 @@
+use crate::core::crop::AutoCrop;
+use crate::core::export::{ExportFormat, ExportOptions, PageFormat};
+use crate::core::filters::ImageFilters;
+use crate::core::keybindings::Keymap;
+use crate::core::pdf::PdfBackendKind;
 use log::{info, warn};
-use serde::Deserialize;
-use std::env;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
 use std::fs;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use toml;
+use toml::Spanned;
+use toml::de::{DeString, DeTable, DeValue};
+use toml_edit::{DocumentMut, Item, TableLike};
 
-#[derive(Deserialize, Debug, Default)]
+/// A problem found while loading the config. The key it refers to is reset to its default
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    /// 1-based line of the config file
+    pub line: Option<usize>,
+    /// Dotted path of the key, e.g. "reader.show_bottom_indicator"
+    pub key: Option<String>,
+    pub message: String,
+}
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "`{}`: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
 #[allow(dead_code)]
 pub struct FiapoConfig {
-    /// Color of the text in the app
-    #[serde(default = "text_color")]
-    pub text_color: String,
-    /// Color of the background of the app
-    #[serde(default = "background_color")]
-    pub background_color: String,
+    /// Color scheme: "dark", "light" or "amoled". See `core::theme`
+    #[serde(default = "theme")]
+    pub theme: String,
+    /// Color of the text in the app. Overrides the one of the theme
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_color: Option<String>,
+    /// Color of the background of the app. Overrides the one of the theme
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>,
 
     /// Reader options
     #[serde(default = "ReaderConfig::new")]
     pub reader: ReaderConfig,
+
+    /// OPDS server options
+    #[serde(default = "OpdsConfig::new")]
+    pub opds: OpdsConfig,
+
+    /// Default options for exporting sources
+    #[serde(default = "ExportConfig::new")]
+    pub export: ExportConfig,
+
+    /// Library options
+    #[serde(default)]
+    pub library: LibraryConfig,
+
+    /// Adjustments of the pages in the reader, for the whole library and for single series
+    #[serde(default = "FiltersConfig::new")]
+    pub filters: FiltersConfig,
+
+    /// Cache sizes
+    #[serde(default = "CacheConfig::new")]
+    pub cache: CacheConfig,
+
+    /// Accounts of the manga providers
+    #[serde(default)]
+    pub providers: ProvidersConfig,
+
+    /// Log levels. See `core::logging`
+    #[serde(default = "LogConfig::new")]
+    pub log: LogConfig,
+
+    /// Key combos mapped to action names, e.g. `"Ctrl+Right" = "next_chapter"`. See `core::keybindings`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keybindings: BTreeMap<String, String>,
+    /// Keymap built from the defaults and `keybindings`
+    #[serde(skip)]
+    pub keymap: Keymap,
 }
 
 impl FiapoConfig {
     pub fn defaults() -> Self {
         Self {
-            text_color: text_color(),
-            background_color: background_color(),
-            reader: ReaderConfig {
-                show_bottom_indicator: reader_show_bottom_indicator(),
-            },
+            theme: theme(),
+            text_color: None,
+            background_color: None,
+            reader: ReaderConfig::new(),
+            opds: OpdsConfig::new(),
+            export: ExportConfig::new(),
+            library: LibraryConfig::default(),
+            filters: FiltersConfig::new(),
+            cache: CacheConfig::new(),
+            providers: ProvidersConfig::default(),
+            log: LogConfig::new(),
+            keybindings: BTreeMap::new(),
+            keymap: Keymap::defaults(),
         }
     }
-    /// Parse a .toml file from a given path and mutate the struct
-    pub fn parse_config_file(&mut self, config_path: PathBuf) {
-        let str_path = config_path.to_str().unwrap();
+    /// Parse the contents of a config file key by key.\
+    /// Invalid keys are reset to their defaults and reported, instead of discarding the whole file.
+    pub fn from_toml(contents: &str) -> (Self, Vec<ConfigIssue>) {
+        let (document, errors) = DeTable::parse_recoverable(contents);
+        let mut issues: Vec<ConfigIssue> = errors
+            .iter()
+            .map(|e| ConfigIssue {
+                line: e.span().map(|span| line_number(contents, span.start)),
+                key: None,
+                message: e.message().to_string(),
+            })
+            .collect();
+
+        let valid = validate_table(document.get_ref(), &[], contents, &mut issues);
+        let mut config = match deserialize_config(valid, 0..contents.len()) {
+            Ok(config) => config,
+            // Every key was checked on its own, so this shouldn't happen
+            Err(e) => {
+                issues.push(ConfigIssue {
+                    line: None,
+                    key: None,
+                    message: e.message().to_string(),
+                });
+                Self::defaults()
+            }
+        };
+
+        let (keymap, errors) = Keymap::from_config(&config.keybindings);
+        config.keymap = keymap;
+        issues.extend(errors.into_iter().map(|(combo, message)| {
+            ConfigIssue {
+                line: find_key(document.get_ref(), &["keybindings", &combo])
+                    .map(|span| line_number(contents, span.start)),
+                key: Some(format!("keybindings.\"{}\"", combo)),
+                message,
+            }
+        }));
+        issues.sort_by_key(|issue| issue.line);
+
+        (config, issues)
+    }
+
+    /// Parse a .toml file from a given path and mutate the struct.\
+    /// Returns the problems found in the file, which are also logged.
+    pub fn parse_config_file(&mut self, config_path: PathBuf) -> Vec<ConfigIssue> {
+        let str_path = config_path.to_string_lossy().to_string();
         info!("Loading config from {}...", str_path);
-        match fs::read_to_string(&config_path) {
-            Ok(file_contents) => match toml::from_str::<FiapoConfig>(&file_contents) {
-                Ok(config) => {
-                    *self = config;
-                }
-                Err(e) => {
-                    warn!(
-                        "Failed to parse config file in `{}`: {}. Loading defaults...",
-                        str_path, e
-                    );
-                }
-            },
+        let issues = match fs::read_to_string(&config_path) {
+            Ok(file_contents) => {
+                let (config, issues) = FiapoConfig::from_toml(&file_contents);
+                *self = config;
+                issues
+            }
             Err(e) => {
                 warn!(
                     "Could not read config file in `{}`: {}. Loading defaults...",
                     str_path, e
                 );
+                vec![ConfigIssue {
+                    line: None,
+                    key: None,
+                    message: format!("Could not read the file: {}", e),
+                }]
             }
+        };
+        for issue in &issues {
+            warn!("Invalid config in `{}`, {}", str_path, issue);
         }
+        issues
+    }
+
+    /// Write the config to a .toml file, keeping the comments, key order and formatting of the existing one.\
+    /// Keys missing from the file are only added if they differ from the defaults.
+    /// Keys that aren't part of the config, like invalid ones, are removed.
+    pub fn save(&self, config_path: &Path) -> anyhow::Result<()> {
+        let contents = fs::read_to_string(config_path).unwrap_or_default();
+        let mut document = match contents.parse::<DocumentMut>() {
+            Ok(document) => document,
+            Err(e) => {
+                // Keep a copy of the broken file, since it can't be merged
+                let backup_path = config_path.with_extension("toml.bak");
+                warn!(
+                    "Could not parse `{}`: {}. Saving a backup to `{}`...",
+                    config_path.display(),
+                    e,
+                    backup_path.display()
+                );
+                fs::write(&backup_path, &contents)?;
+                DocumentMut::new()
+            }
+        };
+
+        let config = toml_edit::ser::to_document(self)?;
+        let defaults = toml_edit::ser::to_document(&FiapoConfig::defaults())?;
+        merge_table(
+            document.as_table_mut(),
+            config.as_table(),
+            Some(defaults.as_table()),
+        );
+
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        info!("Saving config to {}...", config_path.display());
+        fs::write(config_path, document.to_string())?;
+        Ok(())
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
-#[allow(dead_code)]
+/// Copy the values of `new` into `existing` without touching the decoration (comments, whitespace) of the values that didn't change.\
+/// Keys missing from `existing` are only added if they differ from `defaults`.
+fn merge_table(
+    existing: &mut dyn TableLike,
+    new: &dyn TableLike,
+    defaults: Option<&dyn TableLike>,
+) {
+    let removed: Vec<String> = existing
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in removed {
+        existing.remove(&key);
+    }
+
+    for (key, new_item) in new.iter() {
+        let default_item = defaults.and_then(|defaults| defaults.get(key));
+
+        if let Some(new_table) = new_item.as_table_like() {
+            let default_table = default_item.and_then(Item::as_table_like);
+            match existing.get_mut(key).and_then(Item::as_table_like_mut) {
+                Some(existing_table) => merge_table(existing_table, new_table, default_table),
+                None => {
+                    // New sections are only added if they have something other than defaults
+                    let mut table = toml_edit::Table::new();
+                    merge_table(&mut table, new_table, default_table);
+                    if !table.is_empty() {
+                        existing.insert(key, Item::Table(table));
+                    }
+                }
+            }
+            continue;
+        }
+
+        let Some(new_value) = new_item.as_value() else {
+            continue;
+        };
+        match existing.get_mut(key) {
+            Some(Item::Value(value)) => {
+                if !same_value(value, new_value) {
+                    let decor = value.decor().clone();
+                    *value = new_value.clone();
+                    *value.decor_mut() = decor;
+                }
+            }
+            _ => {
+                let is_default = default_item
+                    .and_then(Item::as_value)
+                    .is_some_and(|default| same_value(default, new_value));
+                if !is_default {
+                    existing.insert(key, Item::Value(new_value.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Compare values ignoring their decoration
+fn same_value(a: &toml_edit::Value, b: &toml_edit::Value) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    a.decor_mut().clear();
+    b.decor_mut().clear();
+    a.to_string() == b.to_string()
+}
+
+fn deserialize_config(table: DeTable, span: Range<usize>) -> Result<FiapoConfig, toml::de::Error> {
+    FiapoConfig::deserialize(Spanned::new(span, DeValue::Table(table)).into_deserializer())
+}
+
+/// Keep the keys of `table` that are valid on their own. Sections are checked key by key.\
+/// `path` holds the keys of the parent sections.
+fn validate_table<'i>(
+    table: &DeTable<'i>,
+    path: &[&Spanned<DeString<'i>>],
+    contents: &str,
+    issues: &mut Vec<ConfigIssue>,
+) -> DeTable<'i> {
+    let mut valid = DeTable::new();
+    for (key, value) in table.iter() {
+        let mut key_path = path.to_vec();
+        key_path.push(key);
+
+        // Nest the key in its sections, e.g. { reader = { show_bottom_indicator = ... } }
+        let nested = key_path.iter().rev().fold(value.clone(), |value, key| {
+            let mut table = DeTable::new();
+            table.insert((*key).clone(), value);
+            Spanned::new(key.span(), DeValue::Table(table))
+        });
+        let result = match nested.into_inner() {
+            DeValue::Table(table) => deserialize_config(table, key.span()),
+            _ => unreachable!(),
+        };
+
+        match (result, value.get_ref()) {
+            (Ok(_), _) => {
+                valid.insert(key.clone(), value.clone());
+            }
+            // Keep the valid keys of the section
+            (Err(_), DeValue::Table(section)) if !section.is_empty() => {
+                let section = validate_table(section, &key_path, contents, issues);
+                valid.insert(
+                    key.clone(),
+                    Spanned::new(value.span(), DeValue::Table(section)),
+                );
+            }
+            (Err(e), _) => issues.push(ConfigIssue {
+                line: Some(line_number(contents, key.span().start)),
+                key: Some(
+                    key_path
+                        .iter()
+                        .map(|key| key.get_ref().as_ref())
+                        .collect::<Vec<&str>>()
+                        .join("."),
+                ),
+                message: e.message().to_string(),
+            }),
+        }
+    }
+    valid
+}
+
+/// Span of a key, e.g. `["reader", "show_bottom_indicator"]`
+fn find_key(table: &DeTable, path: &[&str]) -> Option<Range<usize>> {
+    let (first, rest) = path.split_first()?;
+    let (key, value) = table.iter().find(|(key, _)| key.get_ref() == first)?;
+    match rest {
+        [] => Some(key.span()),
+        _ => find_key(value.get_ref().as_table()?, rest),
+    }
+}
+
+/// 1-based line of a byte offset
+fn line_number(contents: &str, offset: usize) -> usize {
+    contents.as_bytes()[..offset.min(contents.len())]
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count()
+        + 1
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ReaderConfig {
+    /// Show the HUD with the chapter, page, progress slider and time left at the bottom of the reader
     #[serde(default = "reader_show_bottom_indicator")]
-    show_bottom_indicator: bool,
+    pub show_bottom_indicator: bool,
+    /// Renderer of PDFs: "poppler", or "pdfium" if fiapo was built with the `pdfium` feature
+    #[serde(default = "reader_pdf_backend")]
+    pub pdf_backend: String,
+    /// Crop the uniform margins of pages, white or black. Pages cropped by hand keep their crop either way
+    #[serde(default)]
+    pub auto_crop: bool,
+    /// How far, out of 255, the tone of a pixel can be from the margin to still be part of it
+    #[serde(default = "reader_crop_tolerance")]
+    pub crop_tolerance: u8,
+    /// Largest share of the page cropped off each side, from 0.0 to 0.45
+    #[serde(default = "reader_max_crop")]
+    pub max_crop: f64,
+    /// Cut pages much wider than they're tall, which are usually two pages scanned together, in two pages
+    #[serde(default)]
+    pub split_wide_pages: bool,
 }
 impl ReaderConfig {
     pub fn new() -> Self {
         ReaderConfig {
             show_bottom_indicator: reader_show_bottom_indicator(),
+            pdf_backend: reader_pdf_backend(),
+            auto_crop: false,
+            crop_tolerance: reader_crop_tolerance(),
+            max_crop: reader_max_crop(),
+            split_wide_pages: false,
         }
     }
+
+    /// Settings of the automatic crop, `None` if it's off
+    pub fn auto_crop(&self) -> Option<AutoCrop> {
+        self.auto_crop.then_some(AutoCrop {
+            tolerance: self.crop_tolerance,
+            max_crop: self.max_crop as f32,
+        })
+    }
+
+    pub fn pdf_backend(&self) -> PdfBackendKind {
+        PdfBackendKind::from_name(&self.pdf_backend).unwrap_or_else(|| {
+            warn!(
+                "Unknown PDF backend `{}`. Using poppler...",
+                self.pdf_backend
+            );
+            PdfBackendKind::Poppler
+        })
+    }
 }
 
-pub fn resolve_config_path(path: &str) -> Option<PathBuf> {
-    match env::var("HOME") {
-        Ok(home) => {
-            let resolved_path = path.replace("~", &home);
-            match PathBuf::from_str(&resolved_path) {
-                Ok(final_path) => {
-                    if final_path.exists() {
-                        return Some(final_path);
-                    } else {
-                        warn!(
-                            "Config not found in `{}`. Loading defaults...",
-                            resolved_path
-                        );
-                    }
-                }
-                Err(e) => {
-                    warn!(
-                        "Error parsing config path `{}`: {}. Loading defaults...",
-                        resolved_path, e
-                    );
-                }
-            }
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OpdsConfig {
+    /// Whether to serve the library over OPDS at startup
+    #[serde(default = "opds_enabled")]
+    pub enabled: bool,
+    /// Address the server will bind to, e.g. "0.0.0.0:8080" to expose it to the LAN
+    #[serde(default = "opds_address")]
+    pub address: String,
+    /// Password for HTTP basic auth. Any username is accepted. No auth if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+impl OpdsConfig {
+    pub fn new() -> Self {
+        OpdsConfig {
+            enabled: opds_enabled(),
+            address: opds_address(),
+            password: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Format of the exported pages: "jpeg", "png" or "webp"
+    #[serde(default = "export_page_format")]
+    pub page_format: String,
+    /// JPEG quality, from 1 to 100
+    #[serde(default = "export_quality")]
+    pub quality: u8,
+    /// Downscale pages larger than this, in pixels. No limit if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_resolution: Option<u32>,
+}
+impl ExportConfig {
+    pub fn new() -> Self {
+        ExportConfig {
+            page_format: export_page_format(),
+            quality: export_quality(),
+            max_resolution: None,
         }
-        Err(e) => {
+    }
+
+    pub fn options(&self, format: ExportFormat) -> ExportOptions {
+        let page_format = PageFormat::from_name(&self.page_format).unwrap_or_else(|| {
             warn!(
-                "Could not resolve `$HOME`: {}. Loading default config...",
-                e
+                "Unknown export page format `{}`. Using JPEG...",
+                self.page_format
             );
+            PageFormat::Jpeg
+        });
+        ExportOptions {
+            format,
+            page_format,
+            quality: self.quality,
+            max_resolution: self.max_resolution,
         }
     }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LibraryConfig {
+    /// Folders that belong to the library. They're watched, and new files in them are imported
+    #[serde(default)]
+    pub folders: Vec<String>,
+}
+impl LibraryConfig {
+    /// `folders` with a leading `~` expanded to the home directory
+    pub fn folder_paths(&self) -> Vec<PathBuf> {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        self.folders
+            .iter()
+            .map(|folder| match (&home, folder.strip_prefix('~')) {
+                (Some(home), Some(rest)) if rest.is_empty() || rest.starts_with('/') => {
+                    home.join(rest.trim_start_matches('/'))
+                }
+                _ => PathBuf::from(folder),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FiltersConfig {
+    /// From -1.0 (black) to 1.0 (white)
+    #[serde(default)]
+    pub brightness: f64,
+    /// From -1.0 (flat gray) to 1.0 (twice the contrast)
+    #[serde(default)]
+    pub contrast: f64,
+    /// Above 1.0 lightens the midtones, below darkens them
+    #[serde(default = "filters_gamma")]
+    pub gamma: f64,
+    #[serde(default)]
+    pub grayscale: bool,
+    /// Night mode: white pages become black
+    #[serde(default)]
+    pub invert: bool,
+    /// Stretch the tones of the pages to the full range, for faded or yellowed scans
+    #[serde(default)]
+    pub auto_levels: bool,
+    /// Sigma of the blur used to sharpen the pages: larger values sharpen wider edges. 0.0 for none
+    #[serde(default)]
+    pub sharpen: f64,
+    /// Filters of single series, by their title in the library, e.g. `[filters.series."Berserk"]`.
+    /// Keys they leave out are the global ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub series: BTreeMap<String, FilterOverrides>,
+}
+impl FiltersConfig {
+    pub fn new() -> Self {
+        FiltersConfig {
+            brightness: 0.0,
+            contrast: 0.0,
+            gamma: filters_gamma(),
+            grayscale: false,
+            invert: false,
+            auto_levels: false,
+            sharpen: 0.0,
+            series: BTreeMap::new(),
+        }
+    }
+
+    /// Filters of the series titled `series`: the global ones, with the keys set for the series
+    pub fn for_series(&self, series: Option<&str>) -> ImageFilters {
+        let overrides = series
+            .and_then(|series| self.series.get(series))
+            .cloned()
+            .unwrap_or_default();
+        ImageFilters {
+            brightness: overrides.brightness.unwrap_or(self.brightness) as f32,
+            contrast: overrides.contrast.unwrap_or(self.contrast) as f32,
+            gamma: overrides.gamma.unwrap_or(self.gamma) as f32,
+            grayscale: overrides.grayscale.unwrap_or(self.grayscale),
+            invert: overrides.invert.unwrap_or(self.invert),
+            auto_levels: overrides.auto_levels.unwrap_or(self.auto_levels),
+            sharpen: overrides.sharpen.unwrap_or(self.sharpen) as f32,
+        }
+    }
+}
 
-    None
+/// Filters of a series that differ from the global ones. See `FiltersConfig`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FilterOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contrast: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gamma: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grayscale: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invert: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_levels: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharpen: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Pages kept rendered before and after the current page in the reader
+    #[serde(default = "cache_preloaded_pages")]
+    pub preloaded_pages: usize,
+}
+impl CacheConfig {
+    pub fn new() -> Self {
+        CacheConfig {
+            preloaded_pages: cache_preloaded_pages(),
+        }
+    }
+}
+
+/// Accounts of the manga providers. They're not used yet, since there's no login or AniList integration.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProvidersConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mangadex_username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mangadex_password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anilist_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// "off", "error", "warn", "info", "debug" or "trace"
+    #[serde(default = "log_level", deserialize_with = "deserialize_log_level")]
+    pub level: String,
+    /// Levels of single modules, e.g. `"fiapo::server" = "debug"`
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_log_filters"
+    )]
+    pub filters: BTreeMap<String, String>,
+}
+impl LogConfig {
+    pub fn new() -> Self {
+        LogConfig {
+            level: log_level(),
+            filters: BTreeMap::new(),
+        }
+    }
+
+    /// Filters in the syntax of `$RUST_LOG`, e.g. "info,fiapo::server=debug"
+    pub fn filter_spec(&self) -> String {
+        std::iter::once(self.level.clone())
+            .chain(
+                self.filters
+                    .iter()
+                    .map(|(module, level)| format!("{}={}", module, level)),
+            )
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+fn deserialize_log_level<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let level = String::deserialize(deserializer)?;
+    match log::LevelFilter::from_str(&level) {
+        Ok(_) => Ok(level.to_lowercase()),
+        Err(_) => Err(serde::de::Error::custom(format!(
+            "unknown log level `{}`",
+            level
+        ))),
+    }
+}
+
+fn deserialize_log_filters<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    let filters = BTreeMap::<String, String>::deserialize(deserializer)?;
+    for (module, level) in &filters {
+        if log::LevelFilter::from_str(level).is_err() {
+            return Err(serde::de::Error::custom(format!(
+                "unknown log level `{}` for `{}`",
+                level, module
+            )));
+        }
+    }
+    Ok(filters)
 }
 
 /*
  * Default config values:
  */
-fn text_color() -> String {
-    "#FFFFFF".to_string()
-}
-fn background_color() -> String {
-    "#111416".to_string()
+fn theme() -> String {
+    "dark".to_string()
 }
 fn reader_show_bottom_indicator() -> bool {
     true
 }
+fn reader_pdf_backend() -> String {
+    "poppler".to_string()
+}
+fn reader_crop_tolerance() -> u8 {
+    24
+}
+fn reader_max_crop() -> f64 {
+    0.2
+}
+fn opds_enabled() -> bool {
+    false
+}
+fn opds_address() -> String {
+    "127.0.0.1:8080".to_string()
+}
+fn export_page_format() -> String {
+    "jpeg".to_string()
+}
+fn export_quality() -> u8 {
+    90
+}
+fn log_level() -> String {
+    "info".to_string()
+}
+fn filters_gamma() -> f64 {
+    1.0
+}
+fn cache_preloaded_pages() -> usize {
+    2
+}
@@ -0,0 +1,152 @@
+use crate::core::layout::Rotation;
+use image::DynamicImage;
+
+/// Share of the pixels of a line that can stand out from the margin, so specks of dust and page numbers don't end it
+const MARGIN_NOISE: f32 = 0.01;
+/// Smallest share of the page kept on each axis, whatever the crop box says
+const MIN_KEPT: f32 = 0.1;
+
+/// Margins cut off a page, as shares of its width and height so they fit any resolution
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CropBox {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl CropBox {
+    /// Leaves the page as it is
+    pub const NONE: CropBox = CropBox {
+        left: 0.0,
+        top: 0.0,
+        right: 0.0,
+        bottom: 0.0,
+    };
+
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Whether it leaves the page as it is
+    pub fn is_empty(&self) -> bool {
+        [self.left, self.top, self.right, self.bottom]
+            .iter()
+            .all(|side| *side <= 0.0)
+    }
+
+    /// The same margins once the page is turned by `rotation`, e.g. the left margin is on top after a clockwise turn
+    pub fn rotated(&self, rotation: Rotation) -> CropBox {
+        let CropBox {
+            left,
+            top,
+            right,
+            bottom,
+        } = *self;
+        match rotation {
+            Rotation::None => *self,
+            Rotation::Clockwise => CropBox::new(bottom, left, top, right),
+            Rotation::UpsideDown => CropBox::new(right, bottom, left, top),
+            Rotation::CounterClockwise => CropBox::new(top, right, bottom, left),
+        }
+    }
+
+    /// Rectangle kept of a page of `width` by `height`, as (x, y, width, height).
+    /// At least `MIN_KEPT` of each axis is kept, so a bad crop box can't make the page disappear
+    pub fn pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let (x, kept_width) = kept(self.left, self.right, width);
+        let (y, kept_height) = kept(self.top, self.bottom, height);
+        (x, y, kept_width, kept_height)
+    }
+
+    /// Crop `image` to the box
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let (x, y, width, height) = self.pixels(image.width(), image.height());
+        image.crop_imm(x, y, width, height)
+    }
+}
+
+/// Offset and length kept of `length` pixels after cutting `start` and `end` off it
+fn kept(start: f32, end: f32, length: u32) -> (u32, u32) {
+    let start = start.clamp(0.0, 1.0);
+    let end = end.clamp(0.0, 1.0);
+    let total = start + end;
+    let (start, end) = if total > 1.0 - MIN_KEPT {
+        let scale = (1.0 - MIN_KEPT) / total;
+        (start * scale, end * scale)
+    } else {
+        (start, end)
+    };
+    let offset = (start * length as f32).round() as u32;
+    let cut_end = (end * length as f32).round() as u32;
+    let kept = length.saturating_sub(offset + cut_end).max(1);
+    (offset.min(length.saturating_sub(1)), kept)
+}
+
+/// Detection of the uniform margins of pages, white or black, to crop them. See `ReaderConfig::auto_crop`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoCrop {
+    /// How far the tone of a pixel can be from the margin, out of 255, for it to still be part of it.
+    /// Scans need some, for the grain of the paper and JPEG artifacts
+    pub tolerance: u8,
+    /// Largest share of the page cut off each side, so pages with large blank areas aren't cropped down to a speck
+    pub max_crop: f32,
+}
+
+impl AutoCrop {
+    /// Find the uniform margins of a page. The tone of each margin is the one of the outermost line on its side
+    pub fn detect(&self, image: &DynamicImage) -> CropBox {
+        let luma = image.to_luma8();
+        let (width, height) = luma.dimensions();
+        if width == 0 || height == 0 {
+            return CropBox::NONE;
+        }
+        let max_crop = self.max_crop.clamp(0.0, 0.5 - MIN_KEPT / 2.0);
+        let max_columns = (width as f32 * max_crop) as u32;
+        let max_rows = (height as f32 * max_crop) as u32;
+
+        let column = |x: u32| -> Vec<u8> { (0..height).map(|y| luma.get_pixel(x, y)[0]).collect() };
+        let row = |y: u32| -> Vec<u8> { (0..width).map(|x| luma.get_pixel(x, y)[0]).collect() };
+        let left = self.margin(max_columns, column);
+        let right = self.margin(max_columns, |i| column(width - 1 - i));
+        let top = self.margin(max_rows, row);
+        let bottom = self.margin(max_rows, |i| row(height - 1 - i));
+
+        CropBox::new(
+            left as f32 / width as f32,
+            top as f32 / height as f32,
+            right as f32 / width as f32,
+            bottom as f32 / height as f32,
+        )
+    }
+
+    /// How many lines of a side, up to `max`, are margin. `line(0)` is the outermost line of the side
+    fn margin(&self, max: u32, line: impl Fn(u32) -> Vec<u8>) -> u32 {
+        if max == 0 {
+            return 0;
+        }
+        let tone = median(line(0));
+        (0..max)
+            .position(|i| !self.is_margin(&line(i), tone))
+            .map_or(max, |i| i as u32)
+    }
+
+    /// Whether nearly every pixel of a line is within `tolerance` of `tone`
+    fn is_margin(&self, line: &[u8], tone: u8) -> bool {
+        let outliers = line
+            .iter()
+            .filter(|value| value.abs_diff(tone) > self.tolerance)
+            .count();
+        outliers as f32 <= line.len() as f32 * MARGIN_NOISE
+    }
+}
+
+fn median(mut values: Vec<u8>) -> u8 {
+    let middle = values.len() / 2;
+    *values.select_nth_unstable(middle).1
+}
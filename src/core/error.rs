@@ -0,0 +1,148 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Why a source, or one of its pages, can't be read. Every error carries the path of the file,
+/// or the url of the chapter for downloads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceError {
+    /// The file can't be opened, e.g. it was deleted or isn't readable
+    Open { path: PathBuf, reason: String },
+    /// The file was opened but isn't a valid PDF or archive
+    Parse { path: PathBuf, reason: String },
+    /// The PDF is encrypted, and the password is missing or wrong. See `reader::unlock_pdf`
+    #[cfg_attr(not(feature = "pdfium"), allow(dead_code))]
+    Encrypted { path: PathBuf, reason: String },
+    /// The file is of a kind that can't be read, e.g. an unknown extension, or a password-protected PDF without pdfium
+    Unsupported { path: PathBuf, reason: String },
+    /// A page can't be rendered. `page` is 0-based
+    Render {
+        path: PathBuf,
+        page: usize,
+        reason: String,
+    },
+    /// The image of a page can't be decoded. `page` is 0-based
+    Decode {
+        path: PathBuf,
+        page: usize,
+        reason: String,
+    },
+    /// A chapter can't be downloaded
+    Network { path: PathBuf, reason: String },
+}
+
+impl SourceError {
+    pub fn open(path: &Path, reason: impl fmt::Display) -> Self {
+        Self::Open {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn parse(path: &Path, reason: impl fmt::Display) -> Self {
+        Self::Parse {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "pdfium"), allow(dead_code))]
+    pub fn encrypted(path: &Path, reason: impl fmt::Display) -> Self {
+        Self::Encrypted {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn unsupported(path: &Path, reason: impl fmt::Display) -> Self {
+        Self::Unsupported {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn render(path: &Path, page: usize, reason: impl fmt::Display) -> Self {
+        Self::Render {
+            path: path.to_path_buf(),
+            page,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn decode(path: &Path, page: usize, reason: impl fmt::Display) -> Self {
+        Self::Decode {
+            path: path.to_path_buf(),
+            page,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn network(path: &Path, reason: impl fmt::Display) -> Self {
+        Self::Network {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Open { path, .. }
+            | Self::Parse { path, .. }
+            | Self::Encrypted { path, .. }
+            | Self::Unsupported { path, .. }
+            | Self::Render { path, .. }
+            | Self::Decode { path, .. }
+            | Self::Network { path, .. } => path,
+        }
+    }
+
+    /// The 0-based page that failed, for errors of a single page
+    pub fn page(&self) -> Option<usize> {
+        match self {
+            Self::Render { page, .. } | Self::Decode { page, .. } => Some(*page),
+            _ => None,
+        }
+    }
+
+    pub fn reason(&self) -> &str {
+        match self {
+            Self::Open { reason, .. }
+            | Self::Parse { reason, .. }
+            | Self::Encrypted { reason, .. }
+            | Self::Unsupported { reason, .. }
+            | Self::Render { reason, .. }
+            | Self::Decode { reason, .. }
+            | Self::Network { reason, .. } => reason,
+        }
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path().display();
+        match self {
+            Self::Open { reason, .. } => write!(f, "Could not open `{}`: {}", path, reason),
+            Self::Parse { reason, .. } => write!(f, "Could not read `{}`: {}", path, reason),
+            Self::Encrypted { reason, .. } => {
+                write!(f, "Could not unlock `{}`: {}", path, reason)
+            }
+            Self::Unsupported { reason, .. } => write!(f, "Can't open `{}`: {}", path, reason),
+            Self::Render { page, reason, .. } => write!(
+                f,
+                "Could not render page {} of `{}`: {}",
+                page + 1,
+                path,
+                reason
+            ),
+            Self::Decode { page, reason, .. } => write!(
+                f,
+                "Could not decode page {} of `{}`: {}",
+                page + 1,
+                path,
+                reason
+            ),
+            Self::Network { reason, .. } => write!(f, "Could not download `{}`: {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
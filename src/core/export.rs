@@ -0,0 +1,385 @@
+use crate::core::reader::{self, Source};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use log::info;
+use std::fs;
+use std::io::{Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Cbz,
+    Pdf,
+    ImageFolder,
+}
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "cbz" => Some(ExportFormat::Cbz),
+            "pdf" => Some(ExportFormat::Pdf),
+            "images" | "folder" => Some(ExportFormat::ImageFolder),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PageFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Webp,
+}
+impl PageFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(PageFormat::Jpeg),
+            "png" => Some(PageFormat::Png),
+            "webp" => Some(PageFormat::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PageFormat::Jpeg => "jpg",
+            PageFormat::Png => "png",
+            PageFormat::Webp => "webp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Format of the pages. PDFs always embed JPEGs
+    pub page_format: PageFormat,
+    /// JPEG quality, from 1 to 100
+    pub quality: u8,
+    /// Pages larger than this (in either dimension) will be downscaled
+    pub max_resolution: Option<u32>,
+}
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Cbz,
+            page_format: PageFormat::Jpeg,
+            quality: 90,
+            max_resolution: None,
+        }
+    }
+}
+
+/// Render every page of `sources`, in order, into a single CBZ, PDF or image folder at `output`.\
+/// `progress` is called with (pages done, total pages) after each page.
+pub fn export_sources(
+    sources: &mut [Source],
+    options: &ExportOptions,
+    output: &Path,
+    progress: impl FnMut(usize, usize),
+) -> anyhow::Result<()> {
+    let total: usize = sources.iter().map(Source::get_page_count).sum();
+    if total == 0 {
+        return Err(anyhow::anyhow!("Nothing to export, there are no pages"));
+    }
+    info!(
+        "Exporting {} pages to {} as {:?}...",
+        total,
+        output.display(),
+        options.format
+    );
+    // Written next to the output and moved there once done, so a failed export doesn't leave half a file behind
+    let mut temp_output = output.as_os_str().to_owned();
+    temp_output.push(".fiapo-tmp");
+    let temp_output = PathBuf::from(temp_output);
+    // Left behind by a crash, and its pages would end up in this export
+    remove_output(&temp_output)?;
+    if let Err(e) = write_pages(sources, options, &temp_output, total, progress) {
+        let _ = remove_output(&temp_output);
+        return Err(e);
+    }
+    match options.format {
+        ExportFormat::ImageFolder => move_pages(&temp_output, output),
+        _ => Ok(fs::rename(&temp_output, output)?),
+    }
+}
+
+fn write_pages(
+    sources: &mut [Source],
+    options: &ExportOptions,
+    output: &Path,
+    total: usize,
+    mut progress: impl FnMut(usize, usize),
+) -> anyhow::Result<()> {
+    let mut exporter: Box<dyn PageWriter> = match options.format {
+        ExportFormat::Cbz => Box::new(CbzWriter::new(output)?),
+        ExportFormat::Pdf => Box::new(PdfWriter::new(output)?),
+        ExportFormat::ImageFolder => Box::new(FolderWriter::new(output)?),
+    };
+    let page_options = match options.format {
+        ExportFormat::Pdf => ExportOptions {
+            page_format: PageFormat::Jpeg,
+            ..*options
+        },
+        _ => *options,
+    };
+    // Enough digits so the pages sort correctly by name
+    let digits = total.max(1).to_string().len().max(3);
+
+    let mut done: usize = 0;
+    for source in sources.iter_mut() {
+        for page in 0..source.get_page_count() {
+            let image = source.render_page(page)?;
+            let image = scale_to_max_resolution(image, options.max_resolution);
+            let bytes = encode_page(&image, &page_options)?;
+            let name = format!(
+                "{:0digits$}.{}",
+                done + 1,
+                page_options.page_format.extension(),
+                digits = digits
+            );
+            exporter.write_page(&name, &image, bytes)?;
+
+            done += 1;
+            progress(done, total);
+        }
+    }
+
+    exporter.finish()
+}
+
+/// Remove a file or folder, if it exists
+fn remove_output(path: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Move the pages exported to `from` into the folder `to`, which may already exist
+fn move_pages(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        fs::rename(entry.path(), to.join(entry.file_name()))?;
+    }
+    fs::remove_dir(from)?;
+    Ok(())
+}
+
+fn scale_to_max_resolution(image: DynamicImage, max_resolution: Option<u32>) -> DynamicImage {
+    match max_resolution {
+        Some(max) if max > 0 && (image.width() > max || image.height() > max) => {
+            image.resize(max, max, FilterType::Lanczos3)
+        }
+        _ => image,
+    }
+}
+
+fn encode_page(image: &DynamicImage, options: &ExportOptions) -> anyhow::Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    match options.page_format {
+        PageFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut bytes, options.quality.clamp(1, 100));
+            image.to_rgb8().write_with_encoder(encoder)?;
+        }
+        // The WebP encoder from the image crate is lossless only, so quality is ignored
+        PageFormat::Png => image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?,
+        PageFormat::Webp => image
+            .to_rgba8()
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::WebP)?,
+    }
+    Ok(bytes)
+}
+
+trait PageWriter {
+    /// `bytes` is the page encoded in the chosen format
+    fn write_page(
+        &mut self,
+        name: &str,
+        image: &DynamicImage,
+        bytes: Vec<u8>,
+    ) -> anyhow::Result<()>;
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+struct CbzWriter {
+    zip: ZipWriter<fs::File>,
+}
+impl CbzWriter {
+    fn new(output: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            zip: ZipWriter::new(fs::File::create(output)?),
+        })
+    }
+}
+impl PageWriter for CbzWriter {
+    fn write_page(
+        &mut self,
+        name: &str,
+        _image: &DynamicImage,
+        bytes: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        // Images are already compressed
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        self.zip.start_file(name, options)?;
+        self.zip.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        self.zip.finish()?;
+        Ok(())
+    }
+}
+
+struct FolderWriter {
+    dir: std::path::PathBuf,
+}
+impl FolderWriter {
+    fn new(output: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(output)?;
+        Ok(Self {
+            dir: output.to_path_buf(),
+        })
+    }
+}
+impl PageWriter for FolderWriter {
+    fn write_page(
+        &mut self,
+        name: &str,
+        _image: &DynamicImage,
+        bytes: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        fs::write(self.dir.join(name), bytes)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Minimal PDF writer: one JPEG image per page, each page sized to its image at `reader::PAGE_DPI`,
+/// so pages of PDFs keep their size.\
+/// Object 1 is the catalog, object 2 the page tree and every page takes 3 objects (page, image, contents).
+struct PdfWriter {
+    file: fs::File,
+    /// Byte offset of every object, for the xref table
+    offsets: Vec<u64>,
+    page_count: usize,
+}
+impl PdfWriter {
+    fn new(output: &Path) -> anyhow::Result<Self> {
+        let mut file = fs::File::create(output)?;
+        file.write_all(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(Self {
+            file,
+            offsets: Vec::new(),
+            page_count: 0,
+        })
+    }
+
+    fn write_object(&mut self, id: usize, body: &[u8]) -> anyhow::Result<()> {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.file.stream_position()?;
+        writeln!(self.file, "{} 0 obj", id)?;
+        self.file.write_all(body)?;
+        self.file.write_all(b"\nendobj\n")?;
+        Ok(())
+    }
+}
+impl PageWriter for PdfWriter {
+    fn write_page(
+        &mut self,
+        _name: &str,
+        image: &DynamicImage,
+        bytes: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let page_id = 3 + self.page_count * 3;
+        let (image_id, contents_id) = (page_id + 1, page_id + 2);
+        let (width, height) = (image.width(), image.height());
+        // Page sizes are in points, 72 per inch
+        let scale = 72.0 / reader::PAGE_DPI as f32;
+        let (page_width, page_height) = (width as f32 * scale, height as f32 * scale);
+
+        self.write_object(
+            page_id,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {w:.2} {h:.2}] /Resources << /XObject << /Im0 {img} 0 R >> >> /Contents {c} 0 R >>",
+                w = page_width,
+                h = page_height,
+                img = image_id,
+                c = contents_id
+            )
+            .as_bytes(),
+        )?;
+
+        let mut image_object = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n",
+            width,
+            height,
+            bytes.len()
+        )
+        .into_bytes();
+        image_object.extend_from_slice(&bytes);
+        image_object.extend_from_slice(b"\nendstream");
+        self.write_object(image_id, &image_object)?;
+
+        let contents = format!(
+            "q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q",
+            page_width, page_height
+        );
+        self.write_object(
+            contents_id,
+            format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                contents.len(),
+                contents
+            )
+            .as_bytes(),
+        )?;
+
+        self.page_count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        let kids: Vec<String> = (0..self.page_count)
+            .map(|i| format!("{} 0 R", 3 + i * 3))
+            .collect();
+        self.write_object(1, b"<< /Type /Catalog /Pages 2 0 R >>")?;
+        self.write_object(
+            2,
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.page_count
+            )
+            .as_bytes(),
+        )?;
+
+        let xref_offset = self.file.stream_position()?;
+        writeln!(self.file, "xref\n0 {}", self.offsets.len() + 1)?;
+        self.file.write_all(b"0000000000 65535 f \n")?;
+        for offset in &self.offsets {
+            writeln!(self.file, "{:010} 00000 n ", offset)?;
+        }
+        writeln!(
+            self.file,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+            self.offsets.len() + 1,
+            xref_offset
+        )?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use image::DynamicImage;
+
+/// Share of the darkest and of the lightest pixels ignored by auto-levels, so specks of dust and glare don't count
+const LEVELS_CLIP: f32 = 0.005;
+/// Pages whose tones span less than this, like blank pages, are left alone by auto-levels
+const MIN_LEVELS_RANGE: u8 = 32;
+/// Differences below this are left alone by sharpening, so the grain of the paper isn't sharpened too
+const SHARPEN_THRESHOLD: i32 = 4;
+
+/// Adjustments of the rendered pages, for scans that are yellowed, low-contrast or noisy.\
+/// The defaults leave pages untouched. See `FiltersConfig` for the config of the global and per-series ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageFilters {
+    /// From -1.0 (black) to 1.0 (white)
+    pub brightness: f32,
+    /// From -1.0 (flat gray) to 1.0 (twice the contrast)
+    pub contrast: f32,
+    /// Above 1.0 lightens the midtones, below darkens them
+    pub gamma: f32,
+    pub grayscale: bool,
+    /// Night mode: white pages become black
+    pub invert: bool,
+    /// Stretch the tones of every channel to the full range. Faded scans get their black back and yellowed paper turns white
+    pub auto_levels: bool,
+    /// Sigma of the Gaussian blur subtracted by `unsharpen`. 0.0 for none
+    pub sharpen: f32,
+}
+impl Default for ImageFilters {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 0.0,
+            gamma: 1.0,
+            grayscale: false,
+            invert: false,
+            auto_levels: false,
+            sharpen: 0.0,
+        }
+    }
+}
+
+impl ImageFilters {
+    /// Whether the filters leave pages as they are
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Apply the filters to a rendered page. Pages are converted to 8 bits per channel if they aren't already
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        if self.is_identity() {
+            return image;
+        }
+        let mut image = if self.grayscale {
+            match image {
+                DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) => image,
+                image if image.color().has_alpha() => {
+                    DynamicImage::ImageLumaA8(image.to_luma_alpha8())
+                }
+                image => DynamicImage::ImageLuma8(image.to_luma8()),
+            }
+        } else {
+            to_8_bits(image)
+        };
+
+        let channels = image.color().channel_count() as usize;
+        let color_channels = if image.color().has_alpha() {
+            channels - 1
+        } else {
+            channels
+        };
+        let levels: Vec<(u8, u8)> = (0..color_channels)
+            .map(|channel| {
+                if self.auto_levels {
+                    levels(image.as_bytes(), channels, channel)
+                } else {
+                    (0, 255)
+                }
+            })
+            .collect();
+        let tables: Vec<[u8; 256]> = levels
+            .iter()
+            .map(|&(low, high)| self.lookup_table(low, high))
+            .collect();
+        if tables.iter().any(|table| !is_identity_table(table)) {
+            apply_tables(&mut image, channels, &tables);
+        }
+
+        if self.sharpen > 0.0 {
+            image = image.unsharpen(self.sharpen, SHARPEN_THRESHOLD);
+        }
+        image
+    }
+
+    /// New value of each tone of a channel whose tones go from `low` to `high`
+    fn lookup_table(&self, low: u8, high: u8) -> [u8; 256] {
+        let range = (high - low) as f32;
+        let contrast = 1.0 + self.contrast.clamp(-1.0, 1.0);
+        let gamma = self.gamma.max(0.01);
+        let mut table = [0; 256];
+        for (value, entry) in table.iter_mut().enumerate() {
+            let mut tone = (value as f32 - low as f32) / range;
+            tone = (tone - 0.5) * contrast + 0.5;
+            tone += self.brightness.clamp(-1.0, 1.0);
+            tone = tone.clamp(0.0, 1.0).powf(1.0 / gamma);
+            if self.invert {
+                tone = 1.0 - tone;
+            }
+            *entry = (tone * 255.0).round() as u8;
+        }
+        table
+    }
+}
+
+/// Convert images with more than 8 bits per channel, which are rare for pages, e.g. 16-bit PNGs
+fn to_8_bits(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => image,
+        image if !image.color().has_color() => DynamicImage::ImageLuma8(image.to_luma8()),
+        image if image.color().has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+        image => DynamicImage::ImageRgb8(image.to_rgb8()),
+    }
+}
+
+/// Darkest and lightest tones of `channel`, out of pixels of `channels` bytes, ignoring `LEVELS_CLIP` of the pixels at each end
+fn levels(pixels: &[u8], channels: usize, channel: usize) -> (u8, u8) {
+    let mut histogram = [0usize; 256];
+    for pixel in pixels.chunks_exact(channels) {
+        histogram[pixel[channel] as usize] += 1;
+    }
+    let clipped = ((pixels.len() / channels) as f32 * LEVELS_CLIP) as usize;
+    let mut seen = 0;
+    let low = histogram
+        .iter()
+        .position(|count| {
+            seen += count;
+            seen > clipped
+        })
+        .unwrap_or(0);
+    seen = 0;
+    let high = 255
+        - histogram
+            .iter()
+            .rev()
+            .position(|count| {
+                seen += count;
+                seen > clipped
+            })
+            .unwrap_or(0);
+    if high < low + MIN_LEVELS_RANGE as usize {
+        return (0, 255);
+    }
+    (low as u8, high as u8)
+}
+
+fn is_identity_table(table: &[u8; 256]) -> bool {
+    table
+        .iter()
+        .enumerate()
+        .all(|(value, entry)| *entry as usize == value)
+}
+
+/// Replace the color channels of every pixel by their entry in `tables`. Alpha is left as it is
+fn apply_tables(image: &mut DynamicImage, channels: usize, tables: &[[u8; 256]]) {
+    let pixels: &mut [u8] = match image {
+        DynamicImage::ImageLuma8(image) => image,
+        DynamicImage::ImageLumaA8(image) => image,
+        DynamicImage::ImageRgb8(image) => image,
+        DynamicImage::ImageRgba8(image) => image,
+        _ => return,
+    };
+    for pixel in pixels.chunks_exact_mut(channels) {
+        for (value, table) in pixel.iter_mut().zip(tables) {
+            *value = table[*value as usize];
+        }
+    }
+}
@@ -1,25 +1,73 @@
-use gtk4::{gdk, gdk_pixbuf, glib};
+use gtk4::prelude::*;
+use gtk4::{gdk, glib};
 use image::DynamicImage;
+use std::sync::Arc;
 
-pub fn dynamic_image_to_pixbuf(img: &DynamicImage) -> Result<gdk_pixbuf::Pixbuf, glib::Error> {
-    let rgba_img = img.to_rgba8();
-    let (width, height) = rgba_img.dimensions();
-    let bytes = rgba_img.into_raw();
-    let pixbuf = gdk_pixbuf::Pixbuf::from_bytes(
-        &glib::Bytes::from(&bytes),
-        gdk_pixbuf::Colorspace::Rgb,
-        true, // alpha
-        8,    // bits per pixel
+/// Pixels of an image shared with a texture, so they're not copied for it.
+/// The image is dropped once both the texture and its other owners are done with it.
+struct SharedPixels(Arc<DynamicImage>);
+impl AsRef<[u8]> for SharedPixels {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Format of the pixels of an image, if GDK can read them as they are.
+/// Returns the format and the bytes per pixel
+fn memory_format(img: &DynamicImage) -> Option<(gdk::MemoryFormat, usize)> {
+    match img {
+        DynamicImage::ImageLuma8(_) => Some((gdk::MemoryFormat::G8, 1)),
+        DynamicImage::ImageRgb8(_) => Some((gdk::MemoryFormat::R8g8b8, 3)),
+        DynamicImage::ImageRgba8(_) => Some((gdk::MemoryFormat::R8g8b8a8, 4)),
+        _ => None,
+    }
+}
+
+/// Convert the image to a format of `memory_format`. Other formats are rare for pages, e.g. 16-bit PNGs
+fn to_native_format(img: &DynamicImage) -> DynamicImage {
+    if img.color().has_alpha() {
+        DynamicImage::ImageRgba8(img.to_rgba8())
+    } else if img.color().has_color() {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    } else {
+        DynamicImage::ImageLuma8(img.to_luma8())
+    }
+}
+
+/// Texture over `bytes`, the pixels of an image of `width` by `height` in `format`
+fn memory_texture(
+    width: u32,
+    height: u32,
+    (format, bytes_per_pixel): (gdk::MemoryFormat, usize),
+    bytes: &glib::Bytes,
+) -> gdk::Texture {
+    gdk::MemoryTexture::new(
         width as i32,
         height as i32,
-        (width * 4) as i32,
-    );
-    Ok(pixbuf)
+        format,
+        bytes,
+        width as usize * bytes_per_pixel,
+    )
+    .upcast()
+}
+
+/// Convert a DynamicImage from the Image crate to Gdk.Texture.\
+/// Grayscale, RGB and RGBA images are handed to GDK as they are, without copying their pixels.
+pub fn texture_from_image(img: DynamicImage) -> gdk::Texture {
+    let Some(format) = memory_format(&img) else {
+        return texture_from_image(to_native_format(&img));
+    };
+    let (width, height) = (img.width(), img.height());
+    let bytes = glib::Bytes::from_owned(img.into_bytes());
+    memory_texture(width, height, format, &bytes)
 }
 
-// Convert a DynamicImage from the Image crate to Gdk.Texture
-pub fn dynamic_image_to_texture(img: &DynamicImage) -> Result<gdk::Texture, glib::Error> {
-    let pixbuf = dynamic_image_to_pixbuf(&img).unwrap();
-    let texture = gdk::Texture::for_pixbuf(&pixbuf);
-    Ok(texture)
+/// Like `texture_from_image`, for an image that's kept elsewhere too, e.g. in the page store
+pub fn texture_from_shared(img: Arc<DynamicImage>) -> gdk::Texture {
+    let Some(format) = memory_format(&img) else {
+        return texture_from_image(to_native_format(&img));
+    };
+    let (width, height) = (img.width(), img.height());
+    let bytes = glib::Bytes::from_owned(SharedPixels(img));
+    memory_texture(width, height, format, &bytes)
 }
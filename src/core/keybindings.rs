@@ -0,0 +1,253 @@
+use gtk4::gdk;
+use std::collections::{BTreeMap, HashMap};
+
+/// Named actions that can be bound to keys in the `[keybindings]` table of fiapo.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Next page in reading order
+    NextPage,
+    /// Previous page in reading order
+    PrevPage,
+    /// Page to the left, which is the next page in right-to-left sources
+    PageLeft,
+    /// Page to the right, which is the previous page in right-to-left sources
+    PageRight,
+    FirstPage,
+    LastPage,
+    NextChapter,
+    PrevChapter,
+    ToggleFullscreen,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    Bookmark,
+    /// Show or hide the filmstrip of page thumbnails
+    ToggleThumbnails,
+    /// Show or hide the grid of every page of the chapter
+    PageGrid,
+    /// Show or hide the outline of the PDF
+    TableOfContents,
+    /// Invert the colors of the pages until the reader is closed. See `FiltersConfig::invert`
+    NightMode,
+    /// Set the crop of the current page by hand. See `ReaderConfig::auto_crop`
+    CropPage,
+    /// Rotate the current page clockwise, on top of its rotation. It's saved in the library
+    Rotate90,
+    /// Turn the current page upside down, on top of its rotation
+    Rotate180,
+    /// Rotate the current page counterclockwise, on top of its rotation
+    Rotate270,
+    GoHome,
+    /// Import new files from the library folders and mark the removed ones as missing
+    RescanLibrary,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 24] = [
+        Action::NextPage,
+        Action::PrevPage,
+        Action::PageLeft,
+        Action::PageRight,
+        Action::FirstPage,
+        Action::LastPage,
+        Action::NextChapter,
+        Action::PrevChapter,
+        Action::ToggleFullscreen,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ZoomReset,
+        Action::Bookmark,
+        Action::ToggleThumbnails,
+        Action::PageGrid,
+        Action::TableOfContents,
+        Action::NightMode,
+        Action::CropPage,
+        Action::Rotate90,
+        Action::Rotate180,
+        Action::Rotate270,
+        Action::GoHome,
+        Action::RescanLibrary,
+        Action::Quit,
+    ];
+
+    /// Name used in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::NextPage => "next_page",
+            Action::PrevPage => "prev_page",
+            Action::PageLeft => "page_left",
+            Action::PageRight => "page_right",
+            Action::FirstPage => "first_page",
+            Action::LastPage => "last_page",
+            Action::NextChapter => "next_chapter",
+            Action::PrevChapter => "prev_chapter",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::ZoomReset => "zoom_reset",
+            Action::Bookmark => "bookmark",
+            Action::ToggleThumbnails => "toggle_thumbnails",
+            Action::PageGrid => "page_grid",
+            Action::TableOfContents => "table_of_contents",
+            Action::NightMode => "night_mode",
+            Action::CropPage => "crop_page",
+            Action::Rotate90 => "rotate_90",
+            Action::Rotate180 => "rotate_180",
+            Action::Rotate270 => "rotate_270",
+            Action::GoHome => "go_home",
+            Action::RescanLibrary => "rescan_library",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == name.trim().to_lowercase())
+    }
+}
+
+/// Bindings used when the config doesn't override them
+const DEFAULT_BINDINGS: [(&str, Action); 28] = [
+    ("Left", Action::PageLeft),
+    ("Right", Action::PageRight),
+    ("space", Action::NextPage),
+    ("Page_Down", Action::NextPage),
+    ("BackSpace", Action::PrevPage),
+    ("Page_Up", Action::PrevPage),
+    ("Home", Action::FirstPage),
+    ("End", Action::LastPage),
+    ("Ctrl+Page_Down", Action::NextChapter),
+    ("Ctrl+Page_Up", Action::PrevChapter),
+    ("F11", Action::ToggleFullscreen),
+    ("f", Action::ToggleFullscreen),
+    ("Ctrl+plus", Action::ZoomIn),
+    ("Ctrl+equal", Action::ZoomIn),
+    ("Ctrl+minus", Action::ZoomOut),
+    ("Ctrl+0", Action::ZoomReset),
+    ("b", Action::Bookmark),
+    ("t", Action::ToggleThumbnails),
+    ("g", Action::PageGrid),
+    ("o", Action::TableOfContents),
+    ("n", Action::NightMode),
+    ("c", Action::CropPage),
+    ("r", Action::Rotate90),
+    ("Ctrl+r", Action::Rotate180),
+    ("Shift+r", Action::Rotate270),
+    ("Escape", Action::GoHome),
+    ("F5", Action::RescanLibrary),
+    ("Ctrl+q", Action::Quit),
+];
+
+/// A key with modifiers, e.g. "Ctrl+Shift+Right"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    key: gdk::Key,
+    modifiers: gdk::ModifierType,
+}
+
+impl KeyCombo {
+    /// Modifiers that are taken into account when matching keys. Caps Lock, Num Lock and friends are ignored
+    const MODIFIERS: gdk::ModifierType = gdk::ModifierType::CONTROL_MASK
+        .union(gdk::ModifierType::SHIFT_MASK)
+        .union(gdk::ModifierType::ALT_MASK)
+        .union(gdk::ModifierType::SUPER_MASK);
+
+    pub fn new(key: gdk::Key, modifiers: gdk::ModifierType) -> Self {
+        Self {
+            key: key.to_lower(),
+            modifiers: modifiers & Self::MODIFIERS,
+        }
+    }
+
+    /// Parse a combo like "Ctrl+Shift+Right". Key names are the GDK ones, e.g. "Page_Down" or "space".
+    pub fn parse(combo: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = combo.split('+').map(str::trim).collect();
+        // "Ctrl++" binds the plus key
+        if combo.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("plus");
+        }
+        let Some(key_name) = parts.pop().filter(|name| !name.is_empty()) else {
+            return Err(format!("`{}` has no key", combo));
+        };
+
+        let mut modifiers = gdk::ModifierType::empty();
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => gdk::ModifierType::CONTROL_MASK,
+                "shift" => gdk::ModifierType::SHIFT_MASK,
+                "alt" => gdk::ModifierType::ALT_MASK,
+                "super" | "meta" => gdk::ModifierType::SUPER_MASK,
+                _ => return Err(format!("unknown modifier `{}` in `{}`", modifier, combo)),
+            };
+        }
+        let key = gdk::Key::from_name(key_name)
+            .ok_or_else(|| format!("unknown key `{}` in `{}`", key_name, combo))?;
+
+        Ok(Self::new(key, modifiers))
+    }
+}
+
+/// Maps key combos to actions. Built from the defaults and the `[keybindings]` table of the config.
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: HashMap<KeyCombo, Action>,
+}
+
+impl Keymap {
+    pub fn defaults() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .filter_map(|(combo, action)| Some((KeyCombo::parse(combo).ok()?, *action)))
+            .collect();
+        Self { bindings }
+    }
+
+    /// Apply the `[keybindings]` table on top of the defaults.\
+    /// Values are action names, or "none" to unbind a default.
+    /// Returns the keymap and the invalid entries as (key combo, error).
+    pub fn from_config(config: &BTreeMap<String, String>) -> (Self, Vec<(String, String)>) {
+        let mut keymap = Self::defaults();
+        let mut errors: Vec<(String, String)> = Vec::new();
+
+        for (combo_name, action_name) in config {
+            let combo = match KeyCombo::parse(combo_name) {
+                Ok(combo) => combo,
+                Err(e) => {
+                    errors.push((combo_name.clone(), e));
+                    continue;
+                }
+            };
+            if action_name.trim().eq_ignore_ascii_case("none") {
+                keymap.bindings.remove(&combo);
+                continue;
+            }
+            match Action::from_name(action_name) {
+                Some(action) => {
+                    keymap.bindings.insert(combo, action);
+                }
+                None => errors.push((
+                    combo_name.clone(),
+                    format!("unknown action `{}`", action_name),
+                )),
+            }
+        }
+
+        (keymap, errors)
+    }
+
+    pub fn lookup(&self, key: gdk::Key, modifiers: gdk::ModifierType) -> Option<Action> {
+        let combo = KeyCombo::new(key, modifiers);
+        self.bindings.get(&combo).copied().or_else(|| {
+            // Keys like "plus" need Shift on most layouts, so allow it to be left out of the binding
+            self.bindings
+                .get(&KeyCombo::new(
+                    key,
+                    combo.modifiers - gdk::ModifierType::SHIFT_MASK,
+                ))
+                .copied()
+        })
+    }
+}
@@ -0,0 +1,151 @@
+use image::DynamicImage;
+
+/// Pages this much wider than they're tall are spreads of two pages. A spread of two A4 pages is about 1.41
+const MIN_SPREAD_RATIO: f32 = 1.2;
+
+/// Rotation of a page set by hand, clockwise
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise,
+    UpsideDown,
+    CounterClockwise,
+}
+
+impl Rotation {
+    /// Rotation of `degrees` clockwise. Returns `None` for angles that aren't a multiple of 90
+    pub fn from_degrees(degrees: i64) -> Option<Self> {
+        match degrees.rem_euclid(360) {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise),
+            180 => Some(Rotation::UpsideDown),
+            270 => Some(Rotation::CounterClockwise),
+            _ => None,
+        }
+    }
+
+    /// Degrees clockwise, as saved in the library
+    pub fn degrees(&self) -> i64 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise => 90,
+            Rotation::UpsideDown => 180,
+            Rotation::CounterClockwise => 270,
+        }
+    }
+
+    /// This rotation followed by `other`
+    pub fn then(self, other: Rotation) -> Rotation {
+        Rotation::from_degrees(self.degrees() + other.degrees()).unwrap_or_default()
+    }
+
+    /// Rotation that undoes this one
+    pub fn inverse(self) -> Rotation {
+        Rotation::from_degrees(-self.degrees()).unwrap_or_default()
+    }
+
+    /// Whether the width and height of pages are swapped
+    pub fn is_sideways(&self) -> bool {
+        matches!(self, Rotation::Clockwise | Rotation::CounterClockwise)
+    }
+
+    /// Size of a page of `width` by `height` once rotated
+    pub fn size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        if self.is_sideways() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            Rotation::None => image.clone(),
+            Rotation::Clockwise => image.rotate90(),
+            Rotation::UpsideDown => image.rotate180(),
+            Rotation::CounterClockwise => image.rotate270(),
+        }
+    }
+}
+
+/// Half of a spread, shown as a page of its own in split mode. See `ReaderConfig::split_wide_pages`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Half {
+    Left,
+    Right,
+}
+
+impl Half {
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let half_width = image.width() / 2;
+        match self {
+            Half::Left => image.crop_imm(0, 0, half_width.max(1), image.height()),
+            Half::Right => {
+                image.crop_imm(half_width, 0, image.width() - half_width, image.height())
+            }
+        }
+    }
+}
+
+/// A page shown by the reader: a page of a source, or half of it in split mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReaderPage {
+    /// 0-based page of the source
+    pub page: usize,
+    pub half: Option<Half>,
+}
+
+impl ReaderPage {
+    pub fn whole(page: usize) -> Self {
+        Self { page, half: None }
+    }
+}
+
+/// How a page of a file is shown, e.g. for its thumbnail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageView {
+    /// 0-based page of the file
+    pub file_page: usize,
+    pub rotation: Rotation,
+    pub half: Option<Half>,
+}
+
+impl PageView {
+    /// Rotate a rendered page and cut the half that's shown
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        match (self.rotation, self.half) {
+            (Rotation::None, None) => image.clone(),
+            (Rotation::None, Some(half)) => half.apply(image),
+            (rotation, None) => rotation.apply(image),
+            (rotation, Some(half)) => half.apply(&rotation.apply(image)),
+        }
+    }
+}
+
+/// Whether a page of `width` by `height` is a spread of two pages
+pub fn is_spread((width, height): (u32, u32)) -> bool {
+    width as f32 >= height as f32 * MIN_SPREAD_RATIO
+}
+
+/// Pages shown for the pages of a source of `sizes`, once rotated. Spreads are cut in two halves,
+/// in reading order: the right half comes first in right-to-left sources. Pages of unknown size are left whole
+pub fn split_spreads(sizes: &[Option<(u32, u32)>], right_to_left: bool) -> Vec<ReaderPage> {
+    let halves = if right_to_left {
+        [Half::Right, Half::Left]
+    } else {
+        [Half::Left, Half::Right]
+    };
+    let mut pages = Vec::with_capacity(sizes.len());
+    for (page, size) in sizes.iter().enumerate() {
+        if size.is_some_and(is_spread) {
+            pages.extend(halves.map(|half| ReaderPage {
+                page,
+                half: Some(half),
+            }));
+        } else {
+            pages.push(ReaderPage::whole(page));
+        }
+    }
+    pages
+}
@@ -0,0 +1,296 @@
+use crate::core::config::{FiapoConfig, LogConfig};
+use crate::core::paths::AppPaths;
+use log::{Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+/// Log files are rotated when they reach this size
+pub const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// Rotated files that are kept, from fiapo.log.1 (newest) to fiapo.log.5
+pub const MAX_LOG_FILES: usize = 5;
+/// Lines kept in memory for the diagnostics page and bug reports
+const RECENT_LINES: usize = 500;
+
+static LOGGER: OnceLock<FiapoLogger> = OnceLock::new();
+
+/// Logs to the terminal, to a rotating file and to memory.\
+/// The inner loggers are rebuilt when the filters of the config change.
+struct FiapoLogger {
+    /// (terminal, file and memory)
+    loggers: RwLock<(env_logger::Logger, env_logger::Logger)>,
+    sink: Arc<Mutex<LogSink>>,
+}
+
+impl Log for FiapoLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match self.loggers.read() {
+            Ok(loggers) => loggers.0.enabled(metadata),
+            Err(_) => false,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if let Ok(loggers) = self.loggers.read() {
+            loggers.0.log(record);
+            loggers.1.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut sink) = self.sink.lock()
+            && let Some(file) = &mut sink.file
+        {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Where the file logger writes to
+#[derive(Debug, Default)]
+struct LogSink {
+    file: Option<RotatingFile>,
+    recent: VecDeque<String>,
+}
+
+/// `Write` handle of the sink, since env_logger takes ownership of its target
+struct SinkWriter(Arc<Mutex<LogSink>>);
+
+impl Write for SinkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Ok(mut sink) = self.0.lock() else {
+            return Ok(buf.len());
+        };
+        if let Some(file) = &mut sink.file {
+            // Failing to write the log shouldn't break the app
+            let _ = file.write(buf);
+        }
+        for line in String::from_utf8_lossy(buf).lines() {
+            if sink.recent.len() == RECENT_LINES {
+                sink.recent.pop_front();
+            }
+            sink.recent.push_back(line.to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Log file that is moved to `<name>.1` when it grows over `max_size`, shifting the older ones up to `<name>.<max_files>`
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // The oldest file is overwritten
+        for n in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// e.g. fiapo.log.1
+pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{}", n));
+    PathBuf::from(path)
+}
+
+/// Start logging to the terminal and, if it can be opened, to `log_file`.\
+/// `$RUST_LOG` takes priority over the filters of the config.
+pub fn init(log_file: &Path, config: &LogConfig) {
+    let file = match RotatingFile::open(log_file, MAX_LOG_SIZE, MAX_LOG_FILES) {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!(
+                "Could not open the log file `{}`: {}",
+                log_file.display(),
+                e
+            );
+            None
+        }
+    };
+    let sink = Arc::new(Mutex::new(LogSink {
+        file,
+        recent: VecDeque::new(),
+    }));
+    let loggers = build_loggers(config, &sink);
+    let max_level = loggers.0.filter();
+    let logger = FiapoLogger {
+        loggers: RwLock::new(loggers),
+        sink,
+    };
+
+    if LOGGER.set(logger).is_err() {
+        return;
+    }
+    if let Some(logger) = LOGGER.get()
+        && log::set_logger(logger).is_ok()
+    {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Apply new filters to the running logger
+pub fn set_filters(config: &LogConfig) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+    let loggers = build_loggers(config, &logger.sink);
+    let max_level = loggers.0.filter();
+    if let Ok(mut current) = logger.loggers.write() {
+        *current = loggers;
+        log::set_max_level(max_level);
+    }
+}
+
+fn build_loggers(
+    config: &LogConfig,
+    sink: &Arc<Mutex<LogSink>>,
+) -> (env_logger::Logger, env_logger::Logger) {
+    let filters = env::var("RUST_LOG").unwrap_or_else(|_| config.filter_spec());
+
+    let terminal = env_logger::Builder::new()
+        .parse_filters(&filters)
+        .format(|buf, record| {
+            let warn_style = buf.default_level_style(record.level());
+            writeln!(
+                buf,
+                "{warn_style}[{}]:{warn_style:#} {}",
+                record.level(),
+                record.args()
+            )
+        })
+        .build();
+
+    let file = env_logger::Builder::new()
+        .parse_filters(&filters)
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "{} [{}] {}: {}",
+                buf.timestamp_seconds(),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        })
+        .target(env_logger::Target::Pipe(Box::new(SinkWriter(Arc::clone(
+            sink,
+        )))))
+        .build();
+
+    (terminal, file)
+}
+
+/// Lines logged since the app started, up to the last `RECENT_LINES`
+pub fn recent_lines() -> Vec<String> {
+    LOGGER
+        .get()
+        .and_then(|logger| Some(logger.sink.lock().ok()?.recent.iter().cloned().collect()))
+        .unwrap_or_default()
+}
+
+/// Text to paste in a bug report: versions, paths, the config without secrets and the recent logs.\
+/// `toolkit` describes the GUI toolkit, e.g. "GTK 4.14.2".
+pub fn bug_report(
+    config: &FiapoConfig,
+    paths: &AppPaths,
+    toolkit: &str,
+    logs: &[String],
+) -> String {
+    let mut config = config.clone();
+    let redacted = Some("<redacted>".to_string());
+    for secret in [
+        &mut config.opds.password,
+        &mut config.providers.mangadex_username,
+        &mut config.providers.mangadex_password,
+        &mut config.providers.anilist_token,
+    ] {
+        if secret.is_some() {
+            *secret = redacted.clone();
+        }
+    }
+    let config = toml::to_string(&config)
+        .unwrap_or_else(|e| format!("Could not serialize the config: {}", e));
+
+    format!(
+        "### Fiapo {}
+
+- OS: {} ({})
+- Toolkit: {}
+- Portable: {}
+- Config: {}
+- Data: {}
+
+#### Config
+```toml
+{}
+```
+
+#### Logs
+```
+{}
+```
+",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH,
+        toolkit,
+        paths.portable,
+        paths.config_file.display(),
+        paths.data_dir.display(),
+        config.trim_end(),
+        logs.join("\n"),
+    )
+}
@@ -1,4 +1,20 @@
+pub mod comic_info;
 pub mod config;
+pub mod crop;
+pub mod error;
+pub mod export;
+pub mod filters;
 pub mod image;
+pub mod keybindings;
+pub mod layout;
+pub mod logging;
+pub mod outline;
+pub mod pace;
+pub mod paths;
+pub mod pdf;
 pub mod reader;
+pub mod scanner;
+pub mod sorting;
 pub mod test;
+pub mod theme;
+pub mod thumbnail;
@@ -0,0 +1,535 @@
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::ops::Range;
+
+/// How deep PDF objects can be nested before the page labels are given up on
+const MAX_OBJECT_DEPTH: usize = 32;
+
+/// An entry of the outline (bookmarks) of a PDF
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineItem {
+    pub title: String,
+    /// 0-based page of the file the entry points to
+    pub page: usize,
+    /// 0 for top-level entries, 1 for their children, and so on
+    pub level: usize,
+}
+
+/// A chapter of a single-file volume, found in its outline
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineChapter {
+    pub title: String,
+    /// 0-based pages of the file
+    pub pages: Range<usize>,
+}
+
+/// Outline and page labels of a PDF. Both are empty for files that have none
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableOfContents {
+    pub outline: Vec<OutlineItem>,
+    pub page_labels: PageLabels,
+}
+
+impl TableOfContents {
+    /// Label of a 0-based page of the file, e.g. "iv" or "A-3", if the PDF has page labels
+    pub fn label(&self, page: usize) -> Option<String> {
+        self.page_labels.label(page)
+    }
+
+    /// Split a file of `page_count` pages into chapters, one per entry of the first outline level with several entries.\
+    /// Pages before the first entry, like the cover, go to the first chapter.
+    /// Returns nothing if the outline doesn't have at least two chapters.
+    pub fn chapters(&self, page_count: usize) -> Vec<OutlineChapter> {
+        let max_level = self.outline.iter().map(|item| item.level).max();
+        let Some(mut entries) = (0..=max_level.unwrap_or(0))
+            .map(|level| {
+                self.outline
+                    .iter()
+                    .filter(|item| item.level == level && item.page < page_count)
+                    .collect::<Vec<_>>()
+            })
+            .find(|entries| entries.len() >= 2)
+        else {
+            return Vec::new();
+        };
+        entries.sort_by_key(|item| item.page);
+        entries.dedup_by_key(|item| item.page);
+        if entries.len() < 2 {
+            return Vec::new();
+        }
+
+        let mut chapters: Vec<OutlineChapter> = Vec::new();
+        for (i, item) in entries.iter().enumerate() {
+            let start = if i == 0 { 0 } else { item.page };
+            let end = entries.get(i + 1).map_or(page_count, |next| next.page);
+            chapters.push(OutlineChapter {
+                title: item.title.clone(),
+                pages: start..end,
+            });
+        }
+        chapters
+    }
+}
+
+/// Read the outline from the XML written by `pdftohtml -xml`.\
+/// Entries are `<item page="N">` elements, 1-based, and nested `<outline>` elements hold the children of the entry before them.
+pub fn parse_pdftohtml_outline(xml: &str) -> Result<Vec<OutlineItem>, quick_xml::Error> {
+    // Only the outline, at the end of the document, is needed. The text of the pages before it isn't always valid XML
+    let (Some(start), Some(end)) = (xml.find("<outline>"), xml.rfind("</outline>")) else {
+        return Ok(Vec::new());
+    };
+    let mut reader = Reader::from_str(&xml[start..end + "</outline>".len()]);
+    reader.config_mut().trim_text(true);
+
+    let mut items: Vec<OutlineItem> = Vec::new();
+    let mut depth: usize = 0;
+    // Page and title of the `<item>` being read
+    let mut current: Option<(Option<usize>, String)> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.name().as_ref() {
+                b"outline" => depth += 1,
+                b"item" => {
+                    let page = element
+                        .try_get_attribute("page")?
+                        .and_then(|page| page.unescape_value().ok()?.trim().parse::<usize>().ok())
+                        .and_then(|page| page.checked_sub(1));
+                    current = Some((page, String::new()));
+                }
+                _ => {}
+            },
+            Event::Text(text) => {
+                if let Some((_, title)) = &mut current {
+                    title.push_str(&text.unescape()?);
+                }
+            }
+            Event::End(element) => match element.name().as_ref() {
+                b"outline" => depth = depth.saturating_sub(1),
+                b"item" => {
+                    // Entries that point to no page only group their children
+                    if let Some((Some(page), title)) = current.take() {
+                        items.push(OutlineItem {
+                            title: title.trim().to_string(),
+                            page,
+                            level: depth.saturating_sub(1),
+                        });
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+/// Numbering style of a range of page labels, from its `/S` entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelStyle {
+    /// Only the prefix
+    #[default]
+    None,
+    /// 1, 2, 3
+    Decimal,
+    /// I, II, III
+    UpperRoman,
+    /// i, ii, iii
+    LowerRoman,
+    /// A to Z, then AA to ZZ
+    UpperLetters,
+    /// a to z, then aa to zz
+    LowerLetters,
+}
+
+/// Pages labelled the same way, from `first_page` to the first page of the next range
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelRange {
+    /// 0-based page of the file
+    pub first_page: usize,
+    pub style: LabelStyle,
+    pub prefix: String,
+    /// Number of the first page of the range
+    pub start: usize,
+}
+
+/// Page labels of a PDF, e.g. roman numbers for the preface, then "1", "2"...\
+/// See "Page Labels" in the PDF reference.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageLabels {
+    ranges: Vec<LabelRange>,
+}
+
+impl PageLabels {
+    pub fn new(mut ranges: Vec<LabelRange>) -> Self {
+        ranges.sort_by_key(|range| range.first_page);
+        Self { ranges }
+    }
+
+    /// Label of a 0-based page. Pages before the first range have none
+    pub fn label(&self, page: usize) -> Option<String> {
+        let range = self
+            .ranges
+            .iter()
+            .rev()
+            .find(|range| range.first_page <= page)?;
+        let number = range.start + page - range.first_page;
+        let number = match range.style {
+            LabelStyle::None => String::new(),
+            LabelStyle::Decimal => number.to_string(),
+            LabelStyle::UpperRoman => to_roman(number),
+            LabelStyle::LowerRoman => to_roman(number).to_lowercase(),
+            LabelStyle::UpperLetters => to_letters(number),
+            LabelStyle::LowerLetters => to_letters(number).to_lowercase(),
+        };
+        Some(format!("{}{}", range.prefix, number))
+    }
+
+    /// Read the `/PageLabels` of the document catalog.\
+    /// Only objects written in plain text are found. Labels in compressed object streams, or in encrypted PDFs, are left out.
+    pub fn parse(pdf: &[u8]) -> Option<Self> {
+        if find_last(pdf, b"/Encrypt").is_some() {
+            return None;
+        }
+        // Files that were edited have a catalog per revision, and the last one wins
+        let position = find_last(pdf, b"/PageLabels")? + b"/PageLabels".len();
+        let tree = Parser::new(pdf, position).parse_object(0)?;
+        let mut ranges: Vec<LabelRange> = Vec::new();
+        collect_label_ranges(pdf, &tree, &mut ranges, 0);
+        Some(Self::new(ranges))
+    }
+}
+
+/// Walk a number tree, which maps page indexes to label dictionaries
+fn collect_label_ranges(pdf: &[u8], tree: &Object, ranges: &mut Vec<LabelRange>, depth: usize) {
+    if depth > MAX_OBJECT_DEPTH {
+        return;
+    }
+    let Some(tree) = resolve(pdf, tree) else {
+        return;
+    };
+    if let Some(Object::Array(nums)) = tree.get("Nums").and_then(|nums| resolve(pdf, nums)) {
+        for pair in nums.chunks_exact(2) {
+            let (Object::Number(first_page), Some(label)) = (&pair[0], resolve(pdf, &pair[1]))
+            else {
+                continue;
+            };
+            let style = match label.get("S") {
+                Some(Object::Name(style)) => match style.as_str() {
+                    "D" => LabelStyle::Decimal,
+                    "R" => LabelStyle::UpperRoman,
+                    "r" => LabelStyle::LowerRoman,
+                    "A" => LabelStyle::UpperLetters,
+                    "a" => LabelStyle::LowerLetters,
+                    _ => LabelStyle::None,
+                },
+                _ => LabelStyle::None,
+            };
+            let prefix = match label.get("P").and_then(|prefix| resolve(pdf, prefix)) {
+                Some(Object::String(prefix)) => decode_text(&prefix),
+                _ => String::new(),
+            };
+            let start = match label.get("St") {
+                Some(Object::Number(start)) if *start >= 1.0 => *start as usize,
+                _ => 1,
+            };
+            ranges.push(LabelRange {
+                first_page: *first_page as usize,
+                style,
+                prefix,
+                start,
+            });
+        }
+    }
+    if let Some(Object::Array(kids)) = tree.get("Kids").and_then(|kids| resolve(pdf, kids)) {
+        for kid in &kids {
+            collect_label_ranges(pdf, kid, ranges, depth + 1);
+        }
+    }
+}
+
+fn to_roman(mut number: usize) -> String {
+    if number == 0 || number >= 4000 {
+        return number.to_string();
+    }
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            roman.push_str(numeral);
+            number -= value;
+        }
+    }
+    roman
+}
+
+/// 1 is "A", 26 is "Z", 27 is "AA" and 53 is "AAA"
+fn to_letters(number: usize) -> String {
+    if number == 0 {
+        return String::new();
+    }
+    let letter = (b'A' + ((number - 1) % 26) as u8) as char;
+    letter.to_string().repeat((number - 1) / 26 + 1)
+}
+
+/// Text strings are UTF-16 if they start with a byte order mark, or PDFDocEncoding, which is close enough to Latin-1
+fn decode_text(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => String::from_utf16_lossy(
+            &utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        None => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+fn find_last(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+/// Follow an indirect reference to the object it points to. Other objects are returned as they are
+fn resolve(pdf: &[u8], object: &Object) -> Option<Object> {
+    let Object::Reference(number) = object else {
+        return Some(object.clone());
+    };
+    let header = format!("{} 0 obj", number);
+    let header = header.as_bytes();
+    // The last definition wins, like the catalog. The number must not be the end of a bigger one, e.g. "12" in "112 0 obj"
+    let position = pdf
+        .windows(header.len())
+        .enumerate()
+        .filter(|(i, window)| *window == header && (*i == 0 || !pdf[i - 1].is_ascii_digit()))
+        .map(|(i, _)| i)
+        .next_back()?;
+    Parser::new(pdf, position + header.len()).parse_object(0)
+}
+
+/// The few kinds of PDF objects page labels are made of
+#[derive(Debug, Clone, PartialEq)]
+enum Object {
+    Number(f64),
+    Name(String),
+    String(Vec<u8>),
+    Array(Vec<Object>),
+    Dictionary(Vec<(String, Object)>),
+    /// Object number of an indirect reference like "12 0 R"
+    Reference(u32),
+    /// Booleans, null, and anything else that isn't needed
+    Other,
+}
+
+impl Object {
+    /// Value of a dictionary entry, without the leading slash of its name
+    fn get(&self, key: &str) -> Option<&Object> {
+        let Object::Dictionary(entries) = self else {
+            return None;
+        };
+        entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Reads PDF objects from raw file data, starting at `position`
+struct Parser<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        Self { data, position }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if byte == b'%' {
+                while self
+                    .peek()
+                    .is_some_and(|byte| byte != b'\n' && byte != b'\r')
+                {
+                    self.position += 1;
+                }
+            } else if byte.is_ascii_whitespace() || byte == 0 {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Option<Object> {
+        if depth > MAX_OBJECT_DEPTH {
+            return None;
+        }
+        self.skip_whitespace();
+        match self.peek()? {
+            b'<' if self.data.get(self.position + 1) == Some(&b'<') => {
+                self.position += 2;
+                let mut entries: Vec<(String, Object)> = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.data[self.position..].starts_with(b">>") {
+                        self.position += 2;
+                        return Some(Object::Dictionary(entries));
+                    }
+                    let Object::Name(key) = self.parse_object(depth + 1)? else {
+                        return None;
+                    };
+                    let value = self.parse_object(depth + 1)?;
+                    entries.push((key, value));
+                }
+            }
+            b'<' => {
+                self.position += 1;
+                let start = self.position;
+                while self.peek()? != b'>' {
+                    self.position += 1;
+                }
+                let hex: Vec<u8> = self.data[start..self.position]
+                    .iter()
+                    .copied()
+                    .filter(u8::is_ascii_hexdigit)
+                    .collect();
+                self.position += 1;
+                let bytes = hex
+                    .chunks(2)
+                    .map(|pair| {
+                        // An odd digit at the end is followed by an implied 0
+                        let pair = [pair[0], *pair.get(1).unwrap_or(&b'0')];
+                        u8::from_str_radix(std::str::from_utf8(&pair).ok()?, 16).ok()
+                    })
+                    .collect::<Option<Vec<u8>>>()?;
+                Some(Object::String(bytes))
+            }
+            b'[' => {
+                self.position += 1;
+                let mut items: Vec<Object> = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek()? == b']' {
+                        self.position += 1;
+                        return Some(Object::Array(items));
+                    }
+                    items.push(self.parse_object(depth + 1)?);
+                }
+            }
+            b'(' => self.parse_literal_string().map(Object::String),
+            b'/' => {
+                self.position += 1;
+                Some(Object::Name(self.read_token()))
+            }
+            byte if byte.is_ascii_digit() || matches!(byte, b'-' | b'+' | b'.') => {
+                let number: f64 = self.read_token().parse().ok()?;
+                // "12 0 R" is a reference, not two numbers
+                let after_number = self.position;
+                self.skip_whitespace();
+                if self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                    let generation = self.read_token();
+                    self.skip_whitespace();
+                    if generation.parse::<u32>().is_ok() && self.peek() == Some(b'R') {
+                        self.position += 1;
+                        return Some(Object::Reference(number as u32));
+                    }
+                }
+                self.position = after_number;
+                Some(Object::Number(number))
+            }
+            _ => {
+                let token = self.read_token();
+                if token.is_empty() {
+                    return None;
+                }
+                Some(Object::Other)
+            }
+        }
+    }
+
+    /// Read until the next whitespace or delimiter
+    fn read_token(&mut self) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(|byte| {
+            !byte.is_ascii_whitespace() && !b"()<>[]{}/%".contains(&byte) && byte != 0
+        }) {
+            self.position += 1;
+        }
+        String::from_utf8_lossy(&self.data[start..self.position]).to_string()
+    }
+
+    /// A string in parentheses, which can hold balanced parentheses and backslash escapes
+    fn parse_literal_string(&mut self) -> Option<Vec<u8>> {
+        self.position += 1;
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut nesting = 0;
+        loop {
+            let byte = self.peek()?;
+            self.position += 1;
+            match byte {
+                b'(' => {
+                    nesting += 1;
+                    bytes.push(byte);
+                }
+                b')' if nesting == 0 => return Some(bytes),
+                b')' => {
+                    nesting -= 1;
+                    bytes.push(byte);
+                }
+                b'\\' => {
+                    let escaped = self.peek()?;
+                    self.position += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0C),
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + (digit - b'0') as u32;
+                                        self.position += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            bytes.push(value as u8);
+                        }
+                        // A backslash at the end of a line continues the string on the next one
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.position += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        other => bytes.push(other),
+                    }
+                }
+                _ => bytes.push(byte),
+            }
+        }
+    }
+}
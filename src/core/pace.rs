@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Time per page assumed until a few pages were read
+const DEFAULT_TIME_PER_PAGE: Duration = Duration::from_secs(15);
+/// Pages shown for longer than this are ignored, since the reader probably walked away
+const MAX_TIME_PER_PAGE: Duration = Duration::from_secs(180);
+/// Only the most recent pages count, so the estimate follows the pace of the current chapter
+const SAMPLES: usize = 20;
+
+/// How long the reader takes to read a page, to estimate the time left in a chapter
+#[derive(Debug, Default, Clone)]
+pub struct ReadingPace {
+    samples: VecDeque<Duration>,
+}
+
+impl ReadingPace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the time a page was shown before moving to the next one
+    pub fn record(&mut self, time: Duration) {
+        if time > MAX_TIME_PER_PAGE {
+            return;
+        }
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(time);
+    }
+
+    pub fn time_per_page(&self) -> Duration {
+        if self.samples.is_empty() {
+            return DEFAULT_TIME_PER_PAGE;
+        }
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+
+    /// Time to read `pages` more pages
+    pub fn estimate(&self, pages: usize) -> Duration {
+        self.time_per_page() * pages as u32
+    }
+}
+
+/// e.g. "< 1 min left", "12 min left" or "1 h 5 min left"
+pub fn format_time_left(time: Duration) -> String {
+    let minutes = (time.as_secs() + 30) / 60;
+    match minutes {
+        0 => "< 1 min left".to_string(),
+        1..60 => format!("{} min left", minutes),
+        _ => format!("{} h {} min left", minutes / 60, minutes % 60),
+    }
+}
@@ -0,0 +1,136 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Directory next to the executable that turns on portable mode. Everything is kept in it
+pub const PORTABLE_DIR: &str = "fiapo-data";
+/// Environment variable with the path of the config file. The `--config` flag takes priority over it
+pub const CONFIG_ENV: &str = "FIAPO_CONFIG";
+
+/// Where Fiapo keeps its files, following the XDG base directory spec
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AppPaths {
+    /// The config file. It may not exist
+    pub config_file: PathBuf,
+    /// Files of the user that sit next to the config, like style.css
+    pub config_dir: PathBuf,
+    /// Library database and downloads
+    pub data_dir: PathBuf,
+    /// Files that can be generated again, like thumbnails
+    pub cache_dir: PathBuf,
+    /// Logs
+    pub state_dir: PathBuf,
+    pub portable: bool,
+}
+
+impl AppPaths {
+    /// Resolve the paths from the environment.\
+    /// `config_override` is the value of the `--config` flag.
+    pub fn resolve(config_override: Option<PathBuf>) -> Option<Self> {
+        let portable_root = env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join(PORTABLE_DIR)))
+            .filter(|dir| dir.is_dir());
+        Self::from_env(portable_root, config_override, |name| {
+            env::var_os(name).map(PathBuf::from)
+        })
+    }
+
+    /// Resolve the paths with `var` as the environment.\
+    /// Returns `None` if neither `$HOME` nor the XDG variables are set, outside of portable mode.
+    pub fn from_env(
+        portable_root: Option<PathBuf>,
+        config_override: Option<PathBuf>,
+        var: impl Fn(&str) -> Option<PathBuf>,
+    ) -> Option<Self> {
+        let mut paths = match portable_root {
+            Some(root) => Self {
+                config_file: root.join("fiapo.toml"),
+                config_dir: root.clone(),
+                data_dir: root.clone(),
+                cache_dir: root.join("cache"),
+                state_dir: root.clone(),
+                portable: true,
+            },
+            None => {
+                // The spec says relative paths are invalid and should be ignored
+                let home = var("HOME").filter(|home| home.is_absolute());
+                let base_dir = |name: &str, fallback: &str| {
+                    var(name)
+                        .filter(|dir| dir.is_absolute())
+                        .or_else(|| Some(home.as_ref()?.join(fallback)))
+                        .map(|dir| dir.join("fiapo"))
+                };
+                let config_dir = base_dir("XDG_CONFIG_HOME", ".config")?;
+                Self {
+                    config_file: config_dir.join("fiapo.toml"),
+                    config_dir,
+                    data_dir: base_dir("XDG_DATA_HOME", ".local/share")?,
+                    cache_dir: base_dir("XDG_CACHE_HOME", ".cache")?,
+                    state_dir: base_dir("XDG_STATE_HOME", ".local/state")?,
+                    portable: false,
+                }
+            }
+        };
+
+        let config_file = config_override
+            .or_else(|| var(CONFIG_ENV).filter(|config_file| !config_file.as_os_str().is_empty()));
+        if let Some(config_file) = config_file {
+            let config_file = std::path::absolute(&config_file).unwrap_or(config_file);
+            if let Some(config_dir) = config_file.parent() {
+                paths.config_dir = config_dir.to_path_buf();
+            }
+            paths.config_file = config_file;
+        }
+
+        Some(paths)
+    }
+
+    pub fn library_file(&self) -> PathBuf {
+        self.data_dir.join("library.db")
+    }
+
+    /// Master key of the saved PDF passwords. See `server::keyring`
+    pub fn keyring_key_file(&self) -> PathBuf {
+        self.data_dir.join("keyring.key")
+    }
+
+    pub fn downloads_dir(&self) -> PathBuf {
+        self.data_dir.join("downloads")
+    }
+
+    /// Covers of the library sources. See `core::thumbnail`
+    pub fn thumbnails_dir(&self) -> PathBuf {
+        self.cache_dir.join("thumbnails")
+    }
+
+    /// Current log file. Older ones are next to it, see `core::logging`
+    pub fn log_file(&self) -> PathBuf {
+        self.state_dir.join("fiapo.log")
+    }
+
+    /// Styles of the user, loaded on top of the theme
+    pub fn user_css_file(&self) -> PathBuf {
+        self.config_dir.join("style.css")
+    }
+
+    /// Remove `--config <PATH>` or `--config=<PATH>` from the arguments and return the path
+    pub fn take_config_arg(args: &mut Vec<String>) -> Result<Option<PathBuf>, String> {
+        let Some(index) = args
+            .iter()
+            .position(|arg| arg == "--config" || arg.starts_with("--config="))
+        else {
+            return Ok(None);
+        };
+
+        let arg = args.remove(index);
+        let path = match arg.strip_prefix("--config=") {
+            Some(path) => path.to_string(),
+            None if index < args.len() => args.remove(index),
+            None => return Err("`--config` needs a path".to_string()),
+        };
+        if path.is_empty() {
+            return Err("`--config` needs a path".to_string());
+        }
+        Ok(Some(PathBuf::from(path)))
+    }
+}
@@ -0,0 +1,115 @@
+//! Backends that read and render PDFs. See `set_backend`
+#[cfg(feature = "pdfium")]
+mod pdfium;
+mod poppler;
+
+use crate::core::error::SourceError;
+use crate::core::outline::OutlineItem;
+use image::DynamicImage;
+use log::warn;
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, PoisonError, RwLock};
+
+#[cfg(feature = "pdfium")]
+pub use pdfium::Pdfium;
+pub use poppler::Poppler;
+
+/// Reads and renders PDFs
+pub trait PdfBackend: Debug + Send + Sync {
+    /// Open a PDF. `password` is only needed if it's encrypted with a user password
+    fn open(
+        &self,
+        path: &Path,
+        password: Option<&str>,
+    ) -> Result<Box<dyn PdfDocument>, SourceError>;
+
+    /// Read the outline (bookmarks) of a PDF. It's empty if the PDF has none
+    fn read_outline(
+        &self,
+        path: &Path,
+        password: Option<&str>,
+    ) -> Result<Vec<OutlineItem>, SourceError>;
+}
+
+/// A PDF opened by a `PdfBackend`. Sources keep it while they're being read, and may be moved to another thread
+pub trait PdfDocument: Debug + Send {
+    fn page_count(&self) -> usize;
+
+    /// Render a 0-based page at `dpi`
+    fn render(&self, page: usize, dpi: u32) -> Result<DynamicImage, SourceError>;
+
+    /// Width and height of every page in points, as they're rendered
+    fn page_sizes(&self) -> Result<Vec<(f32, f32)>, SourceError>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PdfBackendKind {
+    /// The poppler tools, which must be installed. Each page is rendered by a new process
+    #[default]
+    Poppler,
+    /// The pdfium library, loaded in the process. Only available if built with the `pdfium` feature
+    Pdfium,
+}
+impl PdfBackendKind {
+    pub const ALL: [PdfBackendKind; 2] = [PdfBackendKind::Poppler, PdfBackendKind::Pdfium];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "poppler" => Some(PdfBackendKind::Poppler),
+            "pdfium" => Some(PdfBackendKind::Pdfium),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PdfBackendKind::Poppler => "poppler",
+            PdfBackendKind::Pdfium => "pdfium",
+        }
+    }
+
+    /// Create the backend, or an error if it isn't available in this build or can't be loaded
+    pub fn create(&self) -> anyhow::Result<Arc<dyn PdfBackend>> {
+        match self {
+            PdfBackendKind::Poppler => Ok(Arc::new(Poppler)),
+            #[cfg(feature = "pdfium")]
+            PdfBackendKind::Pdfium => Ok(Arc::new(Pdfium::load()?)),
+            #[cfg(not(feature = "pdfium"))]
+            PdfBackendKind::Pdfium => Err(anyhow::anyhow!(
+                "fiapo was built without the `pdfium` feature"
+            )),
+        }
+    }
+}
+
+/// Backend used by every source. PDFs are opened again by path in other threads, e.g. for thumbnails,
+/// so it's set once for the whole process like `reader::unlock_pdf` passwords
+static BACKEND: LazyLock<RwLock<(PdfBackendKind, Arc<dyn PdfBackend>)>> =
+    LazyLock::new(|| RwLock::new((PdfBackendKind::Poppler, Arc::new(Poppler))));
+
+/// Use the `kind` backend for the PDFs opened from now on. Falls back to poppler if it's not available.
+/// Returns the backend in use.
+pub fn set_backend(kind: PdfBackendKind) -> PdfBackendKind {
+    let mut current = BACKEND.write().unwrap_or_else(PoisonError::into_inner);
+    if current.0 == kind {
+        return kind;
+    }
+    *current = match kind.create() {
+        Ok(backend) => (kind, backend),
+        Err(e) => {
+            warn!(
+                "Could not use the {} PDF backend: {}. Using poppler...",
+                kind.name(),
+                e
+            );
+            (PdfBackendKind::Poppler, Arc::new(Poppler))
+        }
+    };
+    current.0
+}
+
+/// The backend set by `set_backend`, poppler by default
+pub fn backend() -> Arc<dyn PdfBackend> {
+    Arc::clone(&BACKEND.read().unwrap_or_else(PoisonError::into_inner).1)
+}
@@ -0,0 +1,190 @@
+use super::{PdfBackend, PdfDocument};
+use crate::core::error::SourceError;
+use crate::core::outline::OutlineItem;
+use image::DynamicImage;
+use pdfium_render::prelude::{
+    PdfBookmark, PdfDocument as PdfiumPdf, PdfPageIndex, PdfRenderConfig, PdfiumError,
+    PdfiumInternalError,
+};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// How deep the outline is read, in case a broken PDF loops
+const MAX_OUTLINE_DEPTH: usize = 32;
+/// How many bookmarks are read, in case the siblings of a broken PDF loop
+const MAX_OUTLINE_BOOKMARKS: usize = 10_000;
+/// Points per inch, the unit of PDF page sizes
+const POINTS_PER_INCH: f32 = 72.0;
+
+/// The library is initialized once for the whole process, and kept until it exits
+static LIBRARY: OnceLock<Result<pdfium_render::prelude::Pdfium, String>> = OnceLock::new();
+
+/// Renders PDFs with the pdfium library, in the process. Pages are rendered one at a time, since
+/// pdfium isn't thread-safe and pdfium-render serializes every call
+#[derive(Debug)]
+pub struct Pdfium {
+    library: &'static pdfium_render::prelude::Pdfium,
+}
+
+impl Pdfium {
+    /// Load the library from the directory of the executable, or else from the system libraries
+    pub fn load() -> anyhow::Result<Self> {
+        let library = LIBRARY.get_or_init(|| {
+            let next_to_executable = std::env::current_exe()
+                .ok()
+                .and_then(|executable| Some(executable.parent()?.to_path_buf()))
+                .map(|dir| {
+                    pdfium_render::prelude::Pdfium::pdfium_platform_library_name_at_path(&dir)
+                });
+            next_to_executable
+                .filter(|path| path.is_file())
+                .map(pdfium_render::prelude::Pdfium::bind_to_library)
+                .unwrap_or_else(pdfium_render::prelude::Pdfium::bind_to_system_library)
+                .map(pdfium_render::prelude::Pdfium::new)
+                .map_err(|e| e.to_string())
+        });
+        match library {
+            Ok(library) => Ok(Self { library }),
+            Err(e) => Err(anyhow::anyhow!("could not load pdfium: {}", e)),
+        }
+    }
+
+    /// pdfium-render ties documents opened from a file to the lifetime of their password, so encrypted PDFs
+    /// are read into memory instead, and the password is only used to open them
+    fn load_pdf(
+        &self,
+        path: &Path,
+        password: Option<&str>,
+    ) -> Result<PdfiumPdf<'static>, SourceError> {
+        let document = match password {
+            None => self.library.load_pdf_from_file(path, None),
+            Some(password) => {
+                let data = std::fs::read(path).map_err(|e| SourceError::open(path, e))?;
+                self.library.load_pdf_from_byte_vec(data, Some(password))
+            }
+        };
+        document.map_err(|e| open_error(path, e))
+    }
+}
+
+impl PdfBackend for Pdfium {
+    fn open(
+        &self,
+        path: &Path,
+        password: Option<&str>,
+    ) -> Result<Box<dyn PdfDocument>, SourceError> {
+        Ok(Box::new(PdfiumDocument {
+            document: self.load_pdf(path, password)?,
+            path: path.to_path_buf(),
+        }))
+    }
+
+    fn read_outline(
+        &self,
+        path: &Path,
+        password: Option<&str>,
+    ) -> Result<Vec<OutlineItem>, SourceError> {
+        let document = self.load_pdf(path, password)?;
+        let mut outline = Vec::new();
+        if let Some(root) = document.bookmarks().root() {
+            read_bookmarks(root, 0, &mut 0, &mut outline);
+        }
+        Ok(outline)
+    }
+}
+
+struct PdfiumDocument {
+    document: PdfiumPdf<'static>,
+    path: PathBuf,
+}
+impl PdfDocument for PdfiumDocument {
+    fn page_count(&self) -> usize {
+        self.document.pages().len() as usize
+    }
+
+    fn render(&self, page: usize, dpi: u32) -> Result<DynamicImage, SourceError> {
+        let index = PdfPageIndex::try_from(page)
+            .map_err(|_| SourceError::render(&self.path, page, "no such page"))?;
+        let config = PdfRenderConfig::new().scale_page_by_factor(dpi as f32 / POINTS_PER_INCH);
+        let image = self
+            .document
+            .pages()
+            .get(index)
+            .and_then(|page| Ok(page.render_with_config(&config)?.as_image()))
+            .map_err(|e| SourceError::render(&self.path, page, e))?;
+        // Same as the JPEGs of poppler. Pages have no transparency, and the JPEG encoder refuses RGBA
+        Ok(DynamicImage::ImageRgb8(image.into_rgb8()))
+    }
+
+    /// Sizes from pdfium take the rotation of the pages into account
+    fn page_sizes(&self) -> Result<Vec<(f32, f32)>, SourceError> {
+        Ok(self
+            .document
+            .pages()
+            .iter()
+            .map(|page| (page.width().value, page.height().value))
+            .collect())
+    }
+}
+impl Debug for PdfiumDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PdfiumDocument")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+fn open_error(path: &Path, error: PdfiumError) -> SourceError {
+    match error {
+        PdfiumError::IoError(e) => SourceError::open(path, e),
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+            SourceError::encrypted(path, "a password is needed, or it's wrong")
+        }
+        e => SourceError::parse(path, e),
+    }
+}
+
+/// Add `bookmark` and its siblings to `outline`, each followed by its children.
+/// `visited` counts the bookmarks read so far
+fn read_bookmarks(
+    bookmark: PdfBookmark,
+    level: usize,
+    visited: &mut usize,
+    outline: &mut Vec<OutlineItem>,
+) {
+    if level >= MAX_OUTLINE_DEPTH {
+        return;
+    }
+    let mut next = Some(bookmark);
+    while let Some(bookmark) = next {
+        if *visited >= MAX_OUTLINE_BOOKMARKS {
+            return;
+        }
+        *visited += 1;
+        // Bookmarks either point to a destination, or run a "go to" action
+        let page = bookmark
+            .destination()
+            .and_then(|destination| destination.page_index().ok())
+            .or_else(|| {
+                bookmark
+                    .action()?
+                    .as_local_destination_action()?
+                    .destination()
+                    .ok()?
+                    .page_index()
+                    .ok()
+            });
+        if let (Some(title), Some(page)) = (bookmark.title(), page) {
+            outline.push(OutlineItem {
+                title: title.trim().to_string(),
+                page: page as usize,
+                level,
+            });
+        }
+        if let Some(child) = bookmark.first_child() {
+            read_bookmarks(child, level + 1, visited, outline);
+        }
+        next = bookmark.next_sibling();
+    }
+}
@@ -0,0 +1,181 @@
+use super::{PdfBackend, PdfBackendKind, PdfDocument};
+use crate::core::error::SourceError;
+use crate::core::outline::{self, OutlineItem};
+use image::DynamicImage;
+use pdf2image::{DPI, Password, RenderOptionsBuilder};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+/// Renders PDFs with the poppler tools, through pdf2image. Every page is a new `pdftoppm` process.\
+/// The tools only take passwords on their command line, where other users of the computer can read them,
+/// so PDFs with a user password are opened with pdfium instead, and can't be opened without it
+#[derive(Debug, Default)]
+pub struct Poppler;
+
+impl PdfBackend for Poppler {
+    fn open(
+        &self,
+        path: &Path,
+        password: Option<&str>,
+    ) -> Result<Box<dyn PdfDocument>, SourceError> {
+        let pdf = match pdf2image::PDF::from_file(path) {
+            Ok(pdf) => pdf,
+            Err(pdf2image::PDF2ImageError::Io(e)) => return Err(SourceError::open(path, e)),
+            // pdfinfo prints nothing without the right user password
+            Err(pdf2image::PDF2ImageError::UnableToExtractPageCount) if is_locked(path) => {
+                return locked_backend(path)?.open(path, password);
+            }
+            Err(e) => return Err(SourceError::parse(path, e)),
+        };
+        Ok(Box::new(PopplerDocument {
+            pdf,
+            path: path.to_path_buf(),
+        }))
+    }
+
+    fn read_outline(
+        &self,
+        path: &Path,
+        password: Option<&str>,
+    ) -> Result<Vec<OutlineItem>, SourceError> {
+        if password.is_some() {
+            return locked_backend(path)?.read_outline(path, password);
+        }
+        let mut args: Vec<String> = [
+            "-xml", "-stdout", "-i", "-q", "-nodrm", "-f", "1", "-l", "1",
+        ]
+        .map(String::from)
+        .to_vec();
+        args.push(path.to_string_lossy().to_string());
+        let xml = run_poppler("pdftohtml", &args).map_err(|e| SourceError::parse(path, e))?;
+        outline::parse_pdftohtml_outline(&String::from_utf8_lossy(&xml))
+            .map_err(|e| SourceError::parse(path, e))
+    }
+}
+
+/// A PDF opened by poppler. It may be encrypted with an owner password, which only restricts editing and printing
+struct PopplerDocument {
+    pdf: pdf2image::PDF,
+    path: PathBuf,
+}
+impl PdfDocument for PopplerDocument {
+    fn page_count(&self) -> usize {
+        self.pdf.page_count() as usize
+    }
+
+    /// Render a 0-based page, but pdf2image uses 1-based indexes
+    fn render(&self, page: usize, dpi: u32) -> Result<DynamicImage, SourceError> {
+        let mut options = RenderOptionsBuilder::default();
+        options.resolution(DPI::Uniform(dpi));
+        // Owner passwords don't stop reading, but pdf2image refuses to render encrypted PDFs without a password
+        if self.pdf.is_encrypted() {
+            options.password(Password::User(String::new()));
+        }
+        let options = options
+            .build()
+            .map_err(|e| SourceError::render(&self.path, page, e))?;
+        self.pdf
+            .render(pdf2image::Pages::Single(1 + page as u32), options)
+            .map_err(|e| SourceError::render(&self.path, page, e))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| SourceError::render(&self.path, page, "poppler returned no image"))
+    }
+
+    fn page_sizes(&self) -> Result<Vec<(f32, f32)>, SourceError> {
+        let args = [
+            "-f".to_string(),
+            "1".to_string(),
+            "-l".to_string(),
+            self.page_count().to_string(),
+            self.path.to_string_lossy().to_string(),
+        ];
+        let output =
+            run_poppler("pdfinfo", &args).map_err(|e| SourceError::parse(&self.path, e))?;
+        Ok(parse_page_sizes(&String::from_utf8_lossy(&output)))
+    }
+}
+impl Debug for PopplerDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PopplerDocument")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// Sizes of the pages listed by `pdfinfo -f 1 -l N`, e.g. "Page    1 size: 595.276 x 841.89 pts (A4)".
+/// Pages rotated sideways by the PDF are rendered sideways, so their width and height are swapped
+fn parse_page_sizes(info: &str) -> Vec<(f32, f32)> {
+    let mut sizes: Vec<(f32, f32)> = Vec::new();
+    for line in info.lines() {
+        let Some(line) = line.strip_prefix("Page ") else {
+            continue;
+        };
+        let mut words = line.split_whitespace();
+        let Some(page) = words
+            .next()
+            .and_then(|page| page.parse::<usize>().ok())
+            .and_then(|page| page.checked_sub(1))
+        else {
+            continue;
+        };
+        match words.next() {
+            Some("size:") if page == sizes.len() => {
+                let width = words.next().and_then(|width| width.parse().ok());
+                let height = words.nth(1).and_then(|height| height.parse().ok());
+                if let (Some(width), Some(height)) = (width, height) {
+                    sizes.push((width, height));
+                }
+            }
+            Some("rot:") => {
+                if matches!(words.next(), Some("90" | "270"))
+                    && let Some((width, height)) = sizes.get_mut(page)
+                {
+                    std::mem::swap(width, height);
+                }
+            }
+            _ => {}
+        }
+    }
+    sizes
+}
+
+/// Whether the PDF is encrypted with a user password, which pdfinfo refuses without it
+fn is_locked(path: &Path) -> bool {
+    let args = [path.to_string_lossy().to_string()];
+    matches!(run_poppler("pdfinfo", &args), Err(e) if e.contains("Incorrect password"))
+}
+
+/// Poppler isn't given passwords, since they'd be visible in the command line of its tools,
+/// so PDFs encrypted with a user password are opened with pdfium
+fn locked_backend(path: &Path) -> Result<Arc<dyn PdfBackend>, SourceError> {
+    PdfBackendKind::Pdfium.create().map_err(|e| {
+        SourceError::unsupported(
+            path,
+            format!(
+                "password-protected PDFs can only be opened with pdfium, but {}",
+                e
+            ),
+        )
+    })
+}
+
+/// Run a poppler tool. Returns its standard output, or its error message
+fn run_poppler(tool: &str, args: &[String]) -> Result<Vec<u8>, String> {
+    // Same lookup as pdf2image
+    let executable = match std::env::var_os("PDF2IMAGE_POPPLER_PATH") {
+        Some(dir) => PathBuf::from(dir).join(tool),
+        None => PathBuf::from(tool),
+    };
+    let output = Command::new(&executable)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run {}: {}", tool, e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(output.stdout)
+}
@@ -1,36 +1,156 @@
-use image::DynamicImage;
-use log::error;
-use pdf2image::RenderOptionsBuilder;
-use std::collections::VecDeque;
+use crate::core::comic_info::{ComicInfo, Manga};
+use crate::core::crop::{AutoCrop, CropBox};
+use crate::core::error::SourceError;
+use crate::core::filters::ImageFilters;
+use crate::core::layout::{self, Half, PageView, ReaderPage, Rotation};
+use crate::core::outline::{PageLabels, TableOfContents};
+use crate::core::pdf::{self, PdfDocument};
+use crate::core::sorting::{self, SortKey};
+use image::{DynamicImage, ImageReader};
+use log::{error, warn};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsStr;
 use std::fmt::Debug;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock, PoisonError};
+use zip::ZipArchive;
 
-/// How many extra pages will be store at both ends of the current page\
+/// Extensions of the images that will be read as pages from archives
+pub const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "webp", "gif"];
+
+/// Default of how many extra pages will be store at both ends of the current page\
 /// For example, a value of '2' means that the page store will hold up to 5 pages: previous 2, current and next 2.
 const EXTRA_PAGES_AT_ENDS: usize = 2;
+/// Resolution of the pages read in the reader, and of the pages exported to PDF
+pub const PAGE_DPI: u32 = 150;
+/// Resolution of the page thumbnails. A4 pages come out about 350 pixels tall
+const THUMBNAIL_DPI: u32 = 30;
+/// Bytes read from the start of the images of archives to find their size, without reading all of them.
+/// Metadata like EXIF can come before the size in JPEGs
+const IMAGE_HEADER_BYTES: u64 = 256 * 1024;
+
+/// A volume of a series, holding its chapters in reading order. See `docs/README.md`
+#[derive(Debug, Default)]
+pub struct Volume {
+    /// Volume number from the metadata, `None` for chapters that aren't in a known volume
+    number: Option<i32>,
+    chapters: Vec<Source>,
+}
+impl Volume {
+    pub fn new(number: Option<i32>, chapters: Vec<Source>) -> Self {
+        Self { number, chapters }
+    }
+
+    /// Group chapters, in reading order, into volumes. Consecutive chapters with the same volume number share a volume
+    pub fn group(chapters: impl IntoIterator<Item = (Option<i32>, Source)>) -> Vec<Volume> {
+        let mut volumes: Vec<Volume> = Vec::new();
+        for (number, chapter) in chapters {
+            match volumes.last_mut() {
+                Some(volume) if volume.number == number => volume.chapters.push(chapter),
+                _ => volumes.push(Volume::new(number, vec![chapter])),
+            }
+        }
+        volumes
+    }
+
+    pub fn get_number(&self) -> Option<i32> {
+        self.number
+    }
+
+    pub fn get_chapters(&self) -> &[Source] {
+        &self.chapters
+    }
+}
+
+/// Shown in place of a page between two chapters, after the last page of `finished`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChapterTransition {
+    /// Index of the chapter that ends here, counting the chapters of every volume
+    pub finished: usize,
+    /// Index of the chapter that comes after it, `None` after the last chapter
+    pub next: Option<usize>,
+}
 
 #[derive(Default)]
 pub struct Server {
-    sources: Option<Vec<Source>>,
-    source_count: usize,
-    /// Index of the current source
+    volumes: Vec<Volume>,
+    chapter_count: usize,
+    /// Index of the current chapter, counting the chapters of every volume
     current_source: usize,
+    /// Pages shown for each chapter: its pages, or the halves of its spreads in split mode
+    layouts: Vec<Vec<ReaderPage>>,
+    /// Pages shown, counting the pages of every chapter
     page_count: usize,
-    /// Holds a chunk of DynamicImages to be used by the reader.\
-    /// A chunk is 5 pages by default (previous two, current and next two).
-    page_store: VecDeque<DynamicImage>,
-    current_page_in_store: usize,
+    /// Index of the current page, counting the pages of every chapter
+    current_page: usize,
+    /// Set while the transition after the current chapter is shown instead of the current page
+    transition: Option<ChapterTransition>,
+    /// Holds a chunk of DynamicImages to be used by the reader. They're shared with the textures that show them.\
+    /// A chunk is 5 pages by default (previous two, current and next two). Pages that failed to render hold the error.
+    page_store: VecDeque<Result<StoredPage, SourceError>>,
+    /// Index of the first page in the store
+    store_start: usize,
+    /// How many extra pages will be stored at both ends of the current page. See `EXTRA_PAGES_AT_ENDS`
+    extra_pages: usize,
+    /// Reading direction. Manga are read right to left, which is also the default
+    right_to_left: bool,
+    /// Adjustments applied to the pages as they're rendered
+    filters: ImageFilters,
+    /// Detection of the margins cropped off the pages, `None` if the automatic crop is off
+    auto_crop: Option<AutoCrop>,
+    /// Crop boxes detected by `auto_crop`, so each page is only looked at once
+    detected_crops: HashMap<FilePage, CropBox>,
+    /// Crop boxes set by hand, used instead of the detected ones even if the automatic crop is off
+    crop_overrides: HashMap<FilePage, CropBox>,
+    /// Rotations set by hand
+    rotations: HashMap<FilePage, Rotation>,
+    /// Cut spreads in two pages. See `ReaderConfig::split_wide_pages`
+    split_wide_pages: bool,
+    /// Size of the pages of each chapter, read in split mode once the reader gets near it. See `measure_chapters_around`
+    page_sizes: Vec<Option<PageSizes>>,
+}
+
+/// Width and height of each page of a chapter, `None` for pages whose size can't be read
+type PageSizes = Vec<Option<(u32, u32)>>;
+
+/// A file and a 0-based page of it. Crop boxes are kept by file page, since the chapters of a file can change
+pub type FilePage = (PathBuf, usize);
+
+/// A page in the store of the `Server`
+#[derive(Debug, Clone)]
+struct StoredPage {
+    /// The page as it was rendered, so the crop and filters can change without rendering it again
+    original: Arc<DynamicImage>,
+    file_page: FilePage,
+    /// Half of the page that's shown, if it's a spread that was split
+    half: Option<Half>,
+    /// The page rotated, cropped and with the filters applied. Same as `original` if there's nothing to do
+    shown: Arc<DynamicImage>,
 }
 /// Custom debug implementation that ignores 'page_store' so it doesn't print a wall of bytes
 impl Debug for Server {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Server")
-            .field("sources", &self.sources)
-            .field("source_count", &self.source_count)
+            .field("volumes", &self.volumes)
+            .field("chapter_count", &self.chapter_count)
             .field("current_source", &self.current_source)
+            .field("layouts", &self.layouts.len())
             .field("page_count", &self.page_count)
+            .field("current_page", &self.current_page)
+            .field("transition", &self.transition)
             .field("page_store", &self.page_store.len())
-            .field("current_page_in_store", &self.current_page_in_store)
+            .field("store_start", &self.store_start)
+            .field("extra_pages", &self.extra_pages)
+            .field("right_to_left", &self.right_to_left)
+            .field("filters", &self.filters)
+            .field("auto_crop", &self.auto_crop)
+            .field("detected_crops", &self.detected_crops.len())
+            .field("crop_overrides", &self.crop_overrides.len())
+            .field("rotations", &self.rotations.len())
+            .field("split_wide_pages", &self.split_wide_pages)
             .finish()
     }
 }
@@ -38,183 +158,702 @@ impl Debug for Server {
 impl Server {
     pub fn new() -> Self {
         Self {
-            sources: None,
-            source_count: 0,
+            volumes: Vec::new(),
+            chapter_count: 0,
             current_source: 0,
+            layouts: Vec::new(),
             page_count: 0,
+            current_page: 0,
+            transition: None,
             page_store: VecDeque::with_capacity(EXTRA_PAGES_AT_ENDS * 2 + 1),
-            current_page_in_store: 0,
+            store_start: 0,
+            extra_pages: EXTRA_PAGES_AT_ENDS,
+            right_to_left: true,
+            filters: ImageFilters::default(),
+            auto_crop: None,
+            detected_crops: HashMap::new(),
+            crop_overrides: HashMap::new(),
+            rotations: HashMap::new(),
+            split_wide_pages: false,
+            page_sizes: Vec::new(),
         }
     }
 
-    pub fn set_sources(&mut self, sources: Vec<Source>, page_count: usize) {
-        self.source_count = sources.len();
-        self.sources = Some(sources);
+    pub fn set_volumes(&mut self, volumes: Vec<Volume>) {
+        self.volumes = volumes;
+        let right_to_left = self
+            .get_chapters()
+            .filter_map(Source::get_comic_info)
+            .find(|info| info.manga != Manga::Unknown)
+            .map(ComicInfo::is_right_to_left)
+            .unwrap_or(true);
+        self.right_to_left = right_to_left;
+        self.chapter_count = self.get_chapters().count();
+        self.page_sizes.clear();
+        self.update_layouts();
         self.current_source = 0;
-        self.page_count = page_count;
+        self.current_page = 0;
+        self.transition = None;
         self.page_store.clear();
+        self.store_start = 0;
+        self.measure_chapters_around(0, 0);
 
         // Render first chunk of pages
-        self.render_chunk_for_page(0, 0);
+        self.render_chunk_for_page(0);
+    }
+
+    /// Set how many pages are kept rendered before and after the current page
+    pub fn set_preloaded_pages(&mut self, pages: usize) {
+        self.extra_pages = pages;
     }
 
-    // TODO: revise this
-    pub fn get_prev_page(&mut self) -> Option<&DynamicImage> {
-        let current_page = self.current_page_in_store;
+    pub fn get_filters(&self) -> &ImageFilters {
+        &self.filters
+    }
+
+    /// Apply `filters` to the pages. The pages in the store are filtered again without rendering them.\
+    /// Returns whether the filters changed.
+    pub fn set_filters(&mut self, filters: ImageFilters) -> bool {
+        if self.filters == filters {
+            return false;
+        }
+        self.filters = filters;
+        self.process_store();
+        true
+    }
 
-        if current_page == 2 && self.page_store.len() == 3 {
-            self.current_page_in_store -= 1;
+    /// Turn the automatic crop on or off. The pages in the store are cropped again without rendering them.\
+    /// Returns whether the settings changed.
+    pub fn set_auto_crop(&mut self, auto_crop: Option<AutoCrop>) -> bool {
+        if self.auto_crop == auto_crop {
+            return false;
         }
+        self.auto_crop = auto_crop;
+        self.detected_crops.clear();
+        self.process_store();
+        true
+    }
 
-        self.page_store.get(current_page)
+    /// Set the crop boxes set by hand, e.g. from the library. Call before `set_volumes`
+    pub fn set_crop_overrides(&mut self, overrides: HashMap<FilePage, CropBox>) {
+        self.crop_overrides = overrides;
+        self.process_store();
     }
 
-    // TODO: revise this
-    pub fn get_next_page(&mut self) -> Option<&DynamicImage> {
-        let current_page = self.current_page_in_store;
+    /// Show the halves of spreads as pages of their own. The reader stays on the current page.\
+    /// Returns whether the setting changed.
+    pub fn set_split_wide_pages(&mut self, split: bool) -> bool {
+        if self.split_wide_pages == split {
+            return false;
+        }
+        self.split_wide_pages = split;
+        self.relayout();
+        true
+    }
 
-        if current_page < 2 && self.page_store.len() > current_page + 1 {
-            self.current_page_in_store += 1;
+    /// Set the rotations set by hand, e.g. from the library. Call before `set_volumes`
+    pub fn set_rotations(&mut self, rotations: HashMap<FilePage, Rotation>) {
+        self.rotations = rotations;
+    }
+
+    /// Rotate the current page by `rotation`, on top of the rotation it has.
+    /// Returns the new rotation of the page, `None` if there's no page
+    pub fn rotate_current_page(&mut self, rotation: Rotation) -> Option<Rotation> {
+        let file_page = self.current_file_page()?;
+        let rotation = self.rotation_of(&file_page).then(rotation);
+        if rotation == Rotation::None {
+            self.rotations.remove(&file_page);
+        } else {
+            self.rotations.insert(file_page.clone(), rotation);
+        }
+        // A page turned sideways may become a spread, or stop being one
+        if self.split_wide_pages {
+            self.relayout();
+        } else {
+            self.process_store();
         }
+        Some(rotation)
+    }
 
-        println!("{}", current_page);
-        self.page_store.get(current_page)
+    fn rotation_of(&self, file_page: &FilePage) -> Rotation {
+        self.rotations.get(file_page).copied().unwrap_or_default()
     }
 
-    // TODO: revise this
-    fn _render_prev_page(&mut self) {
-        if self.sources.is_some() {
-            let current_source = self.sources.as_mut().unwrap().get_mut(self.current_source);
-            if current_source.is_some() {
-                // Pop the page at the end of the store:
-                self.page_store.pop_back();
+    /// How the pages of the current source are shown, one for each page of the reader
+    pub fn get_current_source_views(&self) -> Vec<PageView> {
+        let (Some(source), Some(layout)) = (
+            self.get_current_source(),
+            self.layouts.get(self.current_source),
+        ) else {
+            return Vec::new();
+        };
+        let first_page = source.get_file_pages().start;
+        layout
+            .iter()
+            .map(|page| {
+                let file_page = first_page + page.page;
+                PageView {
+                    file_page,
+                    rotation: self.rotation_of(&(source.get_path().clone(), file_page)),
+                    half: page.half,
+                }
+            })
+            .collect()
+    }
+
+    /// Lay out the pages of every chapter again, keeping the reader on the current page
+    fn relayout(&mut self) {
+        let current = self.reader_page(self.current_page);
+        self.update_layouts();
+        self.page_store.clear();
+        self.store_start = 0;
+        if self.page_count == 0 {
+            return;
+        }
+        self.current_page = current
+            .and_then(|(chapter, page)| self.find_reader_page(chapter, page))
+            .unwrap_or(0);
+        self.current_page = self.measure_chapters_around(self.current_source, self.current_page);
+        self.render_chunk_for_page(self.current_page);
+    }
 
-                let _page = current_source.unwrap()._render_prev_page();
+    /// In split mode, read the page sizes of `chapter` and the chapters next to it if they weren't read yet,
+    /// and lay them out again. Reading the sizes of every chapter at once would freeze the reader on long volumes,
+    /// so chapters further away are shown whole until the reader gets near them.
+    /// Returns the index of `page` once laid out again, and may empty the store if its pages moved
+    fn measure_chapters_around(&mut self, chapter: usize, page: usize) -> usize {
+        if !self.split_wide_pages {
+            return page;
+        }
+        let current = self.reader_page(page);
+        self.page_sizes.resize(self.chapter_count, None);
+        let mut first_measured: Option<usize> = None;
+        for chapter in chapter.saturating_sub(1)..(chapter + 2).min(self.chapter_count) {
+            if self.page_sizes[chapter].is_some() {
+                continue;
             }
+            let sizes = self
+                .chapters_mut()
+                .nth(chapter)
+                .map(Source::page_sizes)
+                .unwrap_or_default();
+            self.page_sizes[chapter] = Some(sizes);
+            first_measured.get_or_insert(chapter);
+        }
+        let Some(first_measured) = first_measured else {
+            return page;
+        };
+
+        // Pages before the first chapter that was measured keep their index
+        let moved_from = self.source_start(first_measured).unwrap_or(0);
+        self.update_layouts();
+        if self.store_start + self.page_store.len() > moved_from {
+            self.page_store.clear();
+            self.store_start = 0;
         }
+        current
+            .and_then(|(chapter, page)| self.find_reader_page(chapter, page))
+            .unwrap_or(page)
     }
 
-    // TODO: revise this
-    fn _render_next_page(&mut self) {
-        if self.sources.is_some() {
-            let current_source = self.sources.as_mut().unwrap().get_mut(self.current_source);
-            if current_source.is_some() {
-                // Pop the page at the start of the store:
-                self.page_store.pop_front();
-
-                let page = current_source.unwrap().render_next_page();
-                if page.is_some() {
-                    // push next page from the current source to the end of the store
-                    self.page_store.push_back(page.unwrap());
-                } else {
-                    // no pages left to render in the current source, so we try the next...
-                    if self.current_source < self.source_count {
-                        self.current_source += 1;
-
-                        let current_source =
-                            self.sources.as_mut().unwrap().get_mut(self.current_source);
-                        if current_source.is_some() {
-                            let page = current_source.unwrap().render_next_page();
-                            if page.is_some() {
-                                self.page_store.push_back(page.unwrap());
-                            }
-                            // We are not loading PDFs with pages < 1
-                        }
-                    }
-                    // else {
-                    //     // No more pages to render
-                    //     self.current_page_in_store += 1;
-                    // }
+    /// Set the pages shown for each chapter and count them
+    fn update_layouts(&mut self) {
+        let layouts: Vec<Vec<ReaderPage>> = self
+            .get_chapters()
+            .enumerate()
+            .map(|(chapter, source)| self.layout(chapter, source))
+            .collect();
+        self.page_count = layouts.iter().map(Vec::len).sum();
+        self.layouts = layouts;
+    }
+
+    fn layout(&self, chapter: usize, source: &Source) -> Vec<ReaderPage> {
+        let sizes = self
+            .page_sizes
+            .get(chapter)
+            .and_then(Option::as_ref)
+            .filter(|_| self.split_wide_pages);
+        let Some(sizes) = sizes else {
+            return (0..source.get_page_count())
+                .map(ReaderPage::whole)
+                .collect();
+        };
+        let first_page = source.get_file_pages().start;
+        let sizes: Vec<Option<(u32, u32)>> = sizes
+            .iter()
+            .enumerate()
+            .map(|(page, size)| {
+                let rotation = self.rotation_of(&(source.get_path().clone(), first_page + page));
+                size.map(|size| rotation.size(size))
+            })
+            .collect();
+        layout::split_spreads(&sizes, self.right_to_left)
+    }
+
+    /// Crop box of the current page: the one set by hand, or the detected one. `None` for pages that aren't rendered
+    pub fn get_current_crop(&self) -> Option<CropBox> {
+        let page = self.current_page_result()?.as_ref().ok()?;
+        let crop = self.known_crop(&page.file_page).unwrap_or(CropBox::NONE);
+        Some(crop.rotated(self.rotation_of(&page.file_page)))
+    }
+
+    /// Set the crop of the current page by hand, as it's shown, or go back to the automatic crop with `None`.\
+    /// Crops are kept in the orientation of the file, so they still fit once the page is rotated again.
+    /// Returns the crop that's kept, to save it
+    pub fn set_current_crop(&mut self, crop: Option<CropBox>) -> Option<CropBox> {
+        let file_page = self.current_file_page()?;
+        let crop = crop.map(|crop| crop.rotated(self.rotation_of(&file_page).inverse()));
+        match crop {
+            Some(crop) => self.crop_overrides.insert(file_page, crop),
+            None => self.crop_overrides.remove(&file_page),
+        };
+        self.process_store();
+        crop
+    }
+
+    fn current_file_page(&self) -> Option<FilePage> {
+        let (source, page) = self.reader_page(self.current_page)?;
+        let source = self.get_chapter(source)?;
+        Some((
+            source.get_path().clone(),
+            source.get_file_pages().start + page.page,
+        ))
+    }
+
+    fn known_crop(&self, file_page: &FilePage) -> Option<CropBox> {
+        self.crop_overrides
+            .get(file_page)
+            .or_else(|| self.detected_crops.get(file_page))
+            .copied()
+    }
+
+    /// Crop box of a page, detecting it if it's not known yet
+    fn crop_for(&mut self, file_page: &FilePage, image: &DynamicImage) -> CropBox {
+        if let Some(crop) = self.known_crop(file_page) {
+            return crop;
+        }
+        let Some(auto_crop) = self.auto_crop else {
+            return CropBox::NONE;
+        };
+        let crop = auto_crop.detect(image);
+        self.detected_crops.insert(file_page.clone(), crop);
+        crop
+    }
+
+    /// Crop and rotate a rendered page, cut the half that's shown and apply the filters to it.\
+    /// Crops are in the orientation of the file, so they're applied before the rotation
+    fn process_page(
+        &mut self,
+        original: Arc<DynamicImage>,
+        file_page: FilePage,
+        half: Option<Half>,
+    ) -> StoredPage {
+        let crop = self.crop_for(&file_page, &original);
+        let rotation = self.rotation_of(&file_page);
+        let shown = if crop.is_empty()
+            && rotation == Rotation::None
+            && half.is_none()
+            && self.filters.is_identity()
+        {
+            Arc::clone(&original)
+        } else {
+            let mut image = if crop.is_empty() {
+                original.as_ref().clone()
+            } else {
+                crop.apply(&original)
+            };
+            if rotation != Rotation::None {
+                image = rotation.apply(&image);
+            }
+            if let Some(half) = half {
+                image = half.apply(&image);
+            }
+            Arc::new(self.filters.apply(image))
+        };
+        StoredPage {
+            original,
+            file_page,
+            half,
+            shown,
+        }
+    }
+
+    /// Crop and filter the pages in the store again, after their settings changed
+    fn process_store(&mut self) {
+        let store = std::mem::take(&mut self.page_store);
+        self.page_store = store
+            .into_iter()
+            .map(|page| {
+                page.map(|page| self.process_page(page.original, page.file_page, page.half))
+            })
+            .collect();
+    }
+
+    /// Chapters of every volume, in reading order
+    pub fn get_chapters(&self) -> impl Iterator<Item = &Source> {
+        self.volumes
+            .iter()
+            .flat_map(|volume| volume.chapters.iter())
+    }
+
+    pub fn get_chapter(&self, chapter: usize) -> Option<&Source> {
+        self.get_chapters().nth(chapter)
+    }
+
+    pub fn get_current_source(&self) -> Option<&Source> {
+        self.get_chapter(self.current_source)
+    }
+
+    /// Volume of the current chapter
+    pub fn get_current_volume(&self) -> Option<&Volume> {
+        let mut chapter = self.current_source;
+        for volume in &self.volumes {
+            if chapter < volume.get_chapters().len() {
+                return Some(volume);
+            }
+            chapter -= volume.get_chapters().len();
+        }
+        None
+    }
+
+    /// The transition shown instead of the current page, if any
+    pub fn get_transition(&self) -> Option<ChapterTransition> {
+        self.transition
+    }
+
+    pub fn is_right_to_left(&self) -> bool {
+        self.right_to_left
+    }
+
+    pub fn get_page_count(&self) -> usize {
+        self.page_count
+    }
+
+    /// 0-based index of the current page, counting the pages of every source
+    pub fn get_current_page_index(&self) -> usize {
+        self.current_page
+    }
+
+    /// Pages of the current source, counting the pages of every source
+    pub fn get_current_source_pages(&self) -> Range<usize> {
+        let start = self.source_start(self.current_source).unwrap_or(0);
+        let count = self.layouts.get(self.current_source).map_or(0, Vec::len);
+        start..start + count
+    }
+
+    /// 0-based index of the current page in the current source. Both halves of a spread are the same page
+    pub fn get_page_in_source(&self) -> usize {
+        self.reader_page(self.current_page)
+            .map_or(0, |(_, page)| page.page)
+    }
+
+    /// The current page, shared with the store so it can be shown without copying it
+    pub fn get_current_page(&self) -> Option<&Arc<DynamicImage>> {
+        Some(&self.current_page_result()?.as_ref().ok()?.shown)
+    }
+
+    /// Why the current page couldn't be rendered, if it failed
+    pub fn get_current_page_error(&self) -> Option<&SourceError> {
+        self.current_page_result()?.as_ref().err()
+    }
+
+    fn current_page_result(&self) -> Option<&Result<StoredPage, SourceError>> {
+        self.page_store
+            .get(self.current_page.checked_sub(self.store_start)?)
+    }
+
+    /// Render again the pages in the store that failed, e.g. after the file was fixed or came back.\
+    /// Returns whether the current page can be shown now.
+    pub fn retry_failed_pages(&mut self) -> bool {
+        // Drop the PDF objects, in case the file changed since they were opened
+        for source in self.chapters_mut() {
+            source.release();
+        }
+        for i in 0..self.page_store.len() {
+            if self.page_store[i].is_err() {
+                let image = self.render_page(self.store_start + i);
+                if let Err(e) = &image {
+                    error!("{}", e);
                 }
+                self.page_store[i] = image;
             }
         }
+        self.get_current_page().is_some()
     }
 
-    // TODO: revise this
-    // TODO: TEST: create and store the page store in the Reader and keep only a mutabe reference to it in the server... Or not, cause the state of the reader won't be saved, probably
-    fn render_chunk_for_page(&mut self, source: usize, page: usize) {
-        // First chunk
-        if source == 0 && page == 0 {
-            for _i in 0..(EXTRA_PAGES_AT_ENDS + 1) {
-                let current_source = self.sources.as_mut().unwrap().get_mut(self.current_source);
-                if current_source.is_some() {
-                    let page = current_source.unwrap().render_next_page();
-                    if page.is_some() {
-                        // push next page from the current source to the end of the store
-                        self.page_store.push_back(page.unwrap());
-                    } else {
-                        // no pages left to render in the current source, so we try the next...
-                        if self.current_source < self.source_count {
-                            self.current_source += 1;
-
-                            let current_source =
-                                self.sources.as_mut().unwrap().get_mut(self.current_source);
-                            if current_source.is_some() {
-                                let page = current_source.unwrap().render_next_page();
-                                if page.is_some() {
-                                    self.page_store.push_back(page.unwrap());
-                                }
-                                // We are not loading PDFs with pages < 1
-                            }
-                        }
-                    }
+    /// Move to `page` and render the pages around it. Returns `false` if there's no such page.
+    pub fn go_to_page(&mut self, page: usize) -> bool {
+        let Some((source, _)) = self.locate(page) else {
+            return false;
+        };
+        // The page can move once the spreads of its chapter are known
+        let page = self.measure_chapters_around(source, page);
+        self.current_source = source;
+        self.current_page = page;
+        self.transition = None;
+        self.render_chunk_for_page(page);
+        true
+    }
+
+    /// Index in the reader of a 0-based page of the file at `path`, e.g. from its outline.
+    /// Returns `None` if no chapter holds that page
+    pub fn find_file_page(&self, path: &Path, page: usize) -> Option<usize> {
+        let chapter = self.get_chapters().position(|source| {
+            source.get_path() == path && source.get_file_pages().contains(&page)
+        })?;
+        let first_page = self.get_chapter(chapter)?.get_file_pages().start;
+        self.find_reader_page(chapter, ReaderPage::whole(page - first_page))
+    }
+
+    /// Index in the reader of a page of `chapter`. Halves that aren't there, or spreads that aren't split anymore,
+    /// are found by their page
+    fn find_reader_page(&self, chapter: usize, page: ReaderPage) -> Option<usize> {
+        let layout = self.layouts.get(chapter)?;
+        let index = layout
+            .iter()
+            .position(|reader_page| *reader_page == page)
+            .or_else(|| {
+                layout
+                    .iter()
+                    .position(|reader_page| reader_page.page == page.page)
+            })?;
+        Some(self.source_start(chapter)? + index)
+    }
+
+    /// Move to the next page. The last page of a chapter is followed by its transition
+    pub fn next_page(&mut self) -> bool {
+        if let Some(transition) = self.transition {
+            return transition
+                .next
+                .and_then(|chapter| self.source_start(chapter))
+                .is_some_and(|page| self.go_to_page(page));
+        }
+        if self.page_count == 0 {
+            return false;
+        }
+        if self.current_page + 1 == self.get_current_source_pages().end {
+            self.transition = Some(self.transition_after(self.current_source));
+            return true;
+        }
+        self.go_to_page(self.current_page + 1)
+    }
+
+    /// Move to the previous page. The first page of a chapter is preceded by the transition of the previous one
+    pub fn prev_page(&mut self) -> bool {
+        if self.transition.take().is_some() {
+            return true;
+        }
+        if self.current_page == 0 {
+            return false;
+        }
+        let at_chapter_start = self.current_page == self.get_current_source_pages().start;
+        let moved = self.go_to_page(self.current_page - 1);
+        if moved && at_chapter_start {
+            self.transition = Some(self.transition_after(self.current_source));
+        }
+        moved
+    }
+
+    /// Move to the first page of the next source
+    pub fn next_source(&mut self) -> bool {
+        self.source_start(self.current_source + 1)
+            .is_some_and(|page| self.go_to_page(page))
+    }
+
+    /// Move to the first page of the previous source
+    pub fn prev_source(&mut self) -> bool {
+        self.current_source
+            .checked_sub(1)
+            .and_then(|source| self.source_start(source))
+            .is_some_and(|page| self.go_to_page(page))
+    }
+
+    fn transition_after(&self, chapter: usize) -> ChapterTransition {
+        ChapterTransition {
+            finished: chapter,
+            next: (chapter + 1 < self.chapter_count).then_some(chapter + 1),
+        }
+    }
+
+    /// Index of the first page of `source`
+    fn source_start(&self, source: usize) -> Option<usize> {
+        if source >= self.chapter_count {
+            return None;
+        }
+        Some(self.layouts.iter().take(source).map(Vec::len).sum())
+    }
+
+    /// Find which source a page belongs to. Returns (source, index of the page in the layout of that source).
+    fn locate(&self, mut page: usize) -> Option<(usize, usize)> {
+        for (i, layout) in self.layouts.iter().enumerate() {
+            if page < layout.len() {
+                return Some((i, page));
+            }
+            page -= layout.len();
+        }
+        None
+    }
+
+    /// Find which source a page belongs to, and which page of that source it shows
+    fn reader_page(&self, page: usize) -> Option<(usize, ReaderPage)> {
+        let (source, index) = self.locate(page)?;
+        Some((source, *self.layouts.get(source)?.get(index)?))
+    }
+
+    fn chapters_mut(&mut self) -> impl Iterator<Item = &mut Source> {
+        self.volumes
+            .iter_mut()
+            .flat_map(|volume| volume.chapters.iter_mut())
+    }
+
+    fn render_page(&mut self, page: usize) -> Result<StoredPage, SourceError> {
+        let Some((chapter, reader_page)) = self.reader_page(page) else {
+            // Past the last page, so there's no file to blame
+            return Err(SourceError::render(Path::new(""), page, "no such page"));
+        };
+        let Some(source) = self.get_chapter(chapter) else {
+            return Err(SourceError::render(Path::new(""), page, "no such page"));
+        };
+        let file_page = (
+            source.get_path().clone(),
+            source.get_file_pages().start + reader_page.page,
+        );
+        // Both halves of a spread come from the same render
+        let rendered = self
+            .page_store
+            .iter()
+            .flatten()
+            .find(|stored| stored.file_page == file_page)
+            .map(|stored| Arc::clone(&stored.original));
+        let original = match rendered {
+            Some(original) => original,
+            None => match self.chapters_mut().nth(chapter) {
+                Some(source) => Arc::new(source.render_page(reader_page.page)?),
+                None => return Err(SourceError::render(Path::new(""), page, "no such page")),
+            },
+        };
+        Ok(self.process_page(original, file_page, reader_page.half))
+    }
+
+    /// Fill the store with the pages around `page`, keeping the ones that were already rendered
+    fn render_chunk_for_page(&mut self, page: usize) {
+        let start = page.saturating_sub(self.extra_pages);
+        let end = (page + self.extra_pages + 1).min(self.page_count);
+
+        let mut old_store: Vec<Option<Result<StoredPage, SourceError>>> =
+            std::mem::take(&mut self.page_store)
+                .into_iter()
+                .map(Some)
+                .collect();
+        let old_start = self.store_start;
+        for index in start..end {
+            let cached = index
+                .checked_sub(old_start)
+                .and_then(|i| old_store.get_mut(i))
+                .and_then(Option::take);
+            let image = cached.unwrap_or_else(|| {
+                let image = self.render_page(index);
+                if let Err(e) = &image {
+                    error!("{}", e);
                 }
+                image
+            });
+            self.page_store.push_back(image);
+        }
+        self.store_start = start;
+
+        // Free PDF objects once they're not needed anymore
+        let first_source = self.locate(start).map_or(0, |(source, _)| source);
+        let last_source = self
+            .locate(end.saturating_sub(1))
+            .map_or(0, |(source, _)| source);
+        for (i, source) in self.chapters_mut().enumerate() {
+            if i < first_source || i > last_source {
+                source.release();
             }
         }
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub enum SourceType {
     #[default]
     Pdf,
+    /// A single image, read as a source of one page
     ImageSequence,
     Directory,
+    /// CBZ/ZIP archive of images
+    Archive,
 }
 
-/// Wrapper struct that implements fmt::Debug for pdf2image::PDF
-struct PDFWithDebug {
-    #[allow(dead_code)]
-    pdf: pdf2image::PDF,
+/// Passwords of the encrypted PDFs unlocked in this session. Sources are opened again by path,
+/// e.g. to render thumbnails in another thread, so the password is looked up by path too.
+static PDF_PASSWORDS: LazyLock<Mutex<HashMap<PathBuf, String>>> = LazyLock::new(Default::default);
+
+/// Check `password` against an encrypted PDF and keep it for the rest of the session
+pub fn unlock_pdf(path: &Path, password: &str) -> Result<(), SourceError> {
+    pdf::backend().open(path, Some(password))?;
+    PDF_PASSWORDS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(path.to_path_buf(), password.to_string());
+    Ok(())
 }
-impl PDFWithDebug {
-    pub fn new(path: &PathBuf) -> Self {
-        let pdf = pdf2image::PDF::from_file(&path).unwrap();
-        Self { pdf }
-    }
 
-    pub fn page_count(&self) -> usize {
-        self.pdf.page_count() as usize
-    }
+fn pdf_password(path: &Path) -> Option<String> {
+    PDF_PASSWORDS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(path)
+        .cloned()
+}
 
-    pub fn get_pdf(&mut self) -> &pdf2image::PDF {
-        &self.pdf
-    }
+/// Read the outline and the page labels of a PDF, with the password it was unlocked with if it's encrypted
+fn read_table_of_contents(path: &Path) -> Result<TableOfContents, SourceError> {
+    let outline = pdf::backend().read_outline(path, pdf_password(path).as_deref())?;
+    let data = fs::read(path).map_err(|e| SourceError::open(path, e))?;
+    Ok(TableOfContents {
+        outline,
+        page_labels: PageLabels::parse(&data).unwrap_or_default(),
+    })
 }
-impl Debug for PDFWithDebug {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("PDFWithDebug").finish()
-    }
+
+/// Open a PDF with the backend in use, and the password it was unlocked with if it's encrypted
+fn open_pdf(path: &Path) -> Result<Box<dyn PdfDocument>, SourceError> {
+    pdf::backend().open(path, pdf_password(path).as_deref())
 }
 
 #[derive(Debug, Default)]
 pub struct Source {
-    _source_type: SourceType,
+    source_type: SourceType,
     /// Page of the source.\
     ///     source_type == PDF, then path to the PDF file;\
-    ///     source_type == ImageSequence, then path to the parent dir;\
+    ///     source_type == ImageSequence, then path to the image;\
     ///     source_type == MangaDex, then url to the chapter
     path: PathBuf,
     /// Holds a PDF object if source_type == PDF
-    pdf_object: Option<PDFWithDebug>,
-    /// Index of the current page of the source.\
-    /// This value is 0-based indexed, but both pdf2img and the front-end uses 1-based indexes.
-    current_page: usize,
+    pdf_object: Option<Box<dyn PdfDocument>>,
+    /// Names of the image entries if source_type == Archive, sorted naturally by name
+    archive_pages: Vec<String>,
+    /// Image files if source_type == Directory, sorted naturally by name, or the image if source_type == ImageSequence
+    directory_pages: Vec<PathBuf>,
+    /// Metadata read from the ComicInfo.xml of archives
+    comic_info: Option<ComicInfo>,
+    /// Title of a section of the file, e.g. a chapter of a PDF volume. See `Source::section`
+    title: Option<String>,
+    /// 0-based page of the file where the source starts. Only sections start after the first page
+    first_page: usize,
     page_count: usize,
+    /// Outline and page labels of PDFs, read the first time they're needed
+    table_of_contents: OnceLock<TableOfContents>,
 }
+/// Sources are sorted in reading order. See `sorting::SortKey`
 impl Ord for Source {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.path.file_name().cmp(&other.path.file_name())
+        self.sort_key()
+            .cmp(&other.sort_key())
+            .then_with(|| self.path.cmp(&other.path))
+            .then_with(|| self.first_page.cmp(&other.first_page))
     }
 }
 impl PartialOrd for Source {
@@ -224,97 +863,369 @@ impl PartialOrd for Source {
 }
 impl PartialEq for Source {
     fn eq(&self, other: &Self) -> bool {
-        self.path.file_name() == other.path.file_name()
+        self.path == other.path && self.first_page == other.first_page
     }
 }
 impl Eq for Source {}
 impl Source {
+    /// Open a source, or an empty one if it can't be read. The error is logged.\
+    /// Use `Source::open` to handle the error.
     pub fn new(source_type: SourceType, path: PathBuf, keep_pdf_object: bool) -> Self {
-        let mut pdf_object: Option<PDFWithDebug> = None;
-        let mut page_count: usize = 0;
+        Self::open(source_type, path.clone(), keep_pdf_object).unwrap_or_else(|e| {
+            error!("{}", e);
+            Self {
+                path,
+                ..Default::default()
+            }
+        })
+    }
+
+    /// Open a source, reading its page count and metadata
+    pub fn open(
+        source_type: SourceType,
+        path: PathBuf,
+        keep_pdf_object: bool,
+    ) -> Result<Self, SourceError> {
+        let mut pdf_object: Option<Box<dyn PdfDocument>> = None;
+        let page_count: usize;
+        let mut archive_pages: Vec<String> = Vec::new();
+        let mut directory_pages: Vec<PathBuf> = Vec::new();
+        let mut comic_info: Option<ComicInfo> = None;
         match source_type {
             SourceType::Pdf => {
-                let pdf = PDFWithDebug::new(&path);
+                let pdf = open_pdf(&path)?;
                 page_count = pdf.page_count();
                 if keep_pdf_object {
                     pdf_object = Some(pdf);
                 }
             }
-            _ => {}
+            SourceType::Archive => {
+                archive_pages = list_archive_pages(&path)?;
+                page_count = archive_pages.len();
+                comic_info = match ComicInfo::read_from_archive(&path) {
+                    Ok(info) => info,
+                    Err(e) => {
+                        warn!(
+                            "Failed to read ComicInfo.xml from `{}`: {}",
+                            path.display(),
+                            e
+                        );
+                        None
+                    }
+                };
+            }
+            SourceType::Directory => {
+                directory_pages = list_directory_pages(&path)?;
+                page_count = directory_pages.len();
+            }
+            SourceType::ImageSequence => {
+                fs::metadata(&path).map_err(|e| SourceError::open(&path, e))?;
+                directory_pages = vec![path.clone()];
+                page_count = 1;
+            }
         }
 
-        Self {
-            _source_type: source_type,
+        Ok(Self {
+            source_type,
             path,
             pdf_object,
-            current_page: 0,
+            archive_pages,
+            directory_pages,
+            comic_info,
+            title: None,
+            first_page: 0,
             page_count,
+            table_of_contents: OnceLock::new(),
+        })
+    }
+
+    /// A part of the source, e.g. a chapter of a PDF volume. `pages` are 0-based pages of this source.\
+    /// The PDF object isn't shared, so the section opens the file again when a page is rendered.
+    pub fn section(&self, title: &str, pages: Range<usize>) -> Source {
+        let end = pages.end.min(self.page_count);
+        let start = pages.start.min(end);
+        Source {
+            source_type: self.source_type.clone(),
+            path: self.path.clone(),
+            pdf_object: None,
+            archive_pages: self.archive_pages.clone(),
+            directory_pages: self.directory_pages.clone(),
+            comic_info: self.comic_info.clone(),
+            title: Some(title.to_string()),
+            first_page: self.first_page + start,
+            page_count: end - start,
+            table_of_contents: self.table_of_contents.clone(),
+        }
+    }
+
+    /// Guess the type of a source from its path. Files are recognized by their extension
+    pub fn source_type_for_path(path: &Path) -> Result<SourceType, SourceError> {
+        if !path.is_file() {
+            return Ok(SourceType::Directory);
+        }
+        match path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("pdf") => Ok(SourceType::Pdf),
+            Some("cbz") | Some("zip") => Ok(SourceType::Archive),
+            _ if is_image(path) => Ok(SourceType::ImageSequence),
+            _ => Err(SourceError::unsupported(path, "unsupported file type")),
         }
     }
 
-    pub fn get_pdf_object(&mut self) {
-        self.pdf_object = Some(PDFWithDebug::new(&self.path))
+    pub fn get_pdf_object(&mut self) -> Result<(), SourceError> {
+        self.pdf_object = Some(open_pdf(&self.path)?);
+        Ok(())
     }
 
     pub fn get_page_count(&self) -> usize {
         self.page_count
     }
 
-    fn render_current_page(&mut self) -> Option<DynamicImage> {
-        if self.pdf_object.is_none() {
-            self.get_pdf_object();
-        }
-        if let Some(pdf) = self.pdf_object.as_mut() {
-            match pdf.get_pdf().render(
-                pdf2image::Pages::Single(1 + self.current_page as u32),
-                RenderOptionsBuilder::default().build().ok()?,
-            ) {
-                Ok(vec_img) => {
-                    if !vec_img.is_empty() {
-                        return Some(vec_img[0].to_owned());
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to render DynamicImage: {}", e);
-                }
+    /// 0-based pages of the file in this source. They're all of them, unless it's a section
+    pub fn get_file_pages(&self) -> Range<usize> {
+        self.first_page..self.first_page + self.page_count
+    }
+
+    /// Outline and page labels of the PDF. They're read once, and are empty for other sources or if they can't be read
+    pub fn table_of_contents(&self) -> &TableOfContents {
+        self.table_of_contents.get_or_init(|| {
+            if self.source_type != SourceType::Pdf {
+                return TableOfContents::default();
             }
+            read_table_of_contents(&self.path).unwrap_or_else(|e| {
+                warn!("{}", e);
+                TableOfContents::default()
+            })
+        })
+    }
+
+    /// Page label of a 0-based page of the source, e.g. "iv", if the PDF has page labels
+    pub fn get_page_label(&self, page: usize) -> Option<String> {
+        self.table_of_contents().label(self.first_page + page)
+    }
+
+    pub fn get_source_type(&self) -> &SourceType {
+        &self.source_type
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_comic_info(&self) -> Option<&ComicInfo> {
+        self.comic_info.as_ref()
+    }
+
+    /// Reading order of the source, from its ComicInfo.xml numbers or its file name
+    pub fn sort_key(&self) -> SortKey {
+        let info = self.comic_info.as_ref();
+        SortKey::from_path(&self.path).with_metadata(
+            info.and_then(|info| info.volume),
+            info.and_then(|info| info.number.as_deref()),
+        )
+    }
+
+    /// Title of the section, or from the ComicInfo.xml, or the name of the file
+    pub fn get_title(&self) -> String {
+        self.title
+            .clone()
+            .or_else(|| self.comic_info.as_ref().and_then(|info| info.title.clone()))
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| {
+                self.path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+    }
+
+    /// 0-based page to use as the cover: the `FrontCover` of the ComicInfo.xml, or an image named "cover", or the first page
+    pub fn get_cover_page(&self) -> usize {
+        let front_cover = self
+            .comic_info
+            .as_ref()
+            .and_then(|info| info.pages.as_ref())
+            .and_then(|pages| {
+                pages
+                    .pages
+                    .iter()
+                    .find(|page| page.page_type.as_deref() == Some("FrontCover"))
+            })
+            .map(|page| page.image);
+        let is_cover = |path: &Path| {
+            path.file_stem()
+                .is_some_and(|stem| stem.eq_ignore_ascii_case("cover"))
+        };
+        let cover_image = match self.source_type {
+            SourceType::Archive => self
+                .archive_pages
+                .iter()
+                .position(|name| is_cover(Path::new(name))),
+            SourceType::Directory => self.directory_pages.iter().position(|path| is_cover(path)),
+            _ => None,
+        };
+        // Both are pages of the file, which may start before this section
+        front_cover
+            .filter(|page| *page < self.first_page + self.page_count)
+            .or(cover_image)
+            .and_then(|page| page.checked_sub(self.first_page))
+            .filter(|page| *page < self.page_count)
+            .unwrap_or(0)
+    }
+
+    /// Render a 0-based page
+    pub fn render_page(&mut self, page: usize) -> Result<DynamicImage, SourceError> {
+        self.render_page_at(page, PAGE_DPI)
+    }
+
+    /// Render a page scaled down to `height`. PDFs are rendered at a low resolution, which is much faster
+    pub fn render_thumbnail(
+        &mut self,
+        page: usize,
+        height: u32,
+    ) -> Result<DynamicImage, SourceError> {
+        let image = self.render_page_at(page, THUMBNAIL_DPI)?;
+        if image.height() <= height {
+            return Ok(image);
         }
-        None
+        Ok(image.thumbnail(u32::MAX, height))
     }
 
-    pub fn _render_prev_page(&mut self) -> Option<DynamicImage> {
-        if self.current_page > 0 {
-            match self.render_current_page() {
-                Some(page) => {
-                    self.current_page -= 1;
-                    return Some(page);
-                }
-                None => {
-                    error!("Error rendering DynamicImage");
-                }
-            }
-        } else {
-            // Free PDF object once it's not needed anymore
-            self.pdf_object = None;
+    fn render_page_at(&mut self, page: usize, dpi: u32) -> Result<DynamicImage, SourceError> {
+        if page >= self.page_count {
+            return Err(SourceError::render(&self.path, page, "no such page"));
         }
-        None
+        // From here on, pages of the file. They're also what errors report, since that's what the user can look up
+        let page = self.first_page + page;
+        if self.source_type == SourceType::Archive {
+            return self.render_archive_page(page);
+        }
+        if matches!(
+            self.source_type,
+            SourceType::Directory | SourceType::ImageSequence
+        ) {
+            let path = &self.directory_pages[page];
+            return image::open(path).map_err(|e| match e {
+                image::ImageError::IoError(e) => SourceError::open(path, e),
+                e => SourceError::decode(&self.path, page, e),
+            });
+        }
+        if self.pdf_object.is_none() {
+            self.get_pdf_object()?;
+        }
+        let Some(pdf) = self.pdf_object.as_ref() else {
+            return Err(SourceError::render(&self.path, page, "PDF is not open"));
+        };
+        pdf.render(page, dpi)
     }
 
-    pub fn render_next_page(&mut self) -> Option<DynamicImage> {
-        if self.current_page + 1 < self.page_count {
-            match self.render_current_page() {
-                Some(page) => {
-                    self.current_page += 1;
-                    return Some(page);
-                }
-                None => {
-                    error!("Error rendering DynamicImage");
-                }
-            }
-        } else {
-            // Free PDF object once it's not needed anymore
-            self.pdf_object = None;
+    /// Width and height of every page, read without rendering them. `None` for pages whose size can't be read
+    pub fn page_sizes(&mut self) -> Vec<Option<(u32, u32)>> {
+        let sizes = match self.source_type {
+            SourceType::Pdf => self.pdf_page_sizes(),
+            SourceType::Archive => self.archive_page_sizes(),
+            SourceType::Directory | SourceType::ImageSequence => Ok(self.directory_pages
+                [self.get_file_pages()]
+            .iter()
+            .map(|path| image::image_dimensions(path).ok())
+            .collect()),
+        };
+        let mut sizes = sizes.unwrap_or_else(|e| {
+            warn!("Could not read the size of the pages. {}", e);
+            Vec::new()
+        });
+        sizes.resize(self.page_count, None);
+        sizes
+    }
+
+    fn pdf_page_sizes(&mut self) -> Result<Vec<Option<(u32, u32)>>, SourceError> {
+        if self.pdf_object.is_none() {
+            self.get_pdf_object()?;
         }
-        None
+        let Some(pdf) = self.pdf_object.as_ref() else {
+            return Err(SourceError::open(&self.path, "PDF is not open"));
+        };
+        Ok(pdf
+            .page_sizes()?
+            .into_iter()
+            .skip(self.first_page)
+            .take(self.page_count)
+            .map(|(width, height)| Some((width.round() as u32, height.round() as u32)))
+            .collect())
+    }
+
+    fn archive_page_sizes(&self) -> Result<Vec<Option<(u32, u32)>>, SourceError> {
+        let file = fs::File::open(&self.path).map_err(|e| SourceError::open(&self.path, e))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| SourceError::parse(&self.path, e))?;
+        Ok(self.archive_pages[self.get_file_pages()]
+            .iter()
+            .map(|name| {
+                let mut header: Vec<u8> = Vec::new();
+                archive
+                    .by_name(name)
+                    .ok()?
+                    .take(IMAGE_HEADER_BYTES)
+                    .read_to_end(&mut header)
+                    .ok()?;
+                ImageReader::new(Cursor::new(header))
+                    .with_guessed_format()
+                    .ok()?
+                    .into_dimensions()
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Free the PDF object. It will be loaded again if a page needs to be rendered
+    pub fn release(&mut self) {
+        self.pdf_object = None;
+    }
+
+    fn render_archive_page(&self, page: usize) -> Result<DynamicImage, SourceError> {
+        let name = &self.archive_pages[page];
+        let file = fs::File::open(&self.path).map_err(|e| SourceError::open(&self.path, e))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| SourceError::parse(&self.path, e))?;
+        let mut bytes: Vec<u8> = Vec::new();
+        archive
+            .by_name(name)
+            .and_then(|mut entry| Ok(entry.read_to_end(&mut bytes)?))
+            .map_err(|e| SourceError::parse(&self.path, format!("{}: {}", name, e)))?;
+        image::load_from_memory(&bytes)
+            .map_err(|e| SourceError::decode(&self.path, page, format!("{}: {}", name, e)))
     }
 }
+
+/// List the images directly inside a directory, sorted naturally by name
+pub fn list_directory_pages(path: &Path) -> Result<Vec<PathBuf>, SourceError> {
+    let entries = fs::read_dir(path).map_err(|e| SourceError::open(path, e))?;
+    let mut pages: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_image(path))
+        .collect();
+    pages.sort_by(|a, b| sorting::natural_cmp_paths(a, b));
+    Ok(pages)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// List the image entries of an archive, sorted naturally by name
+fn list_archive_pages(path: &Path) -> Result<Vec<String>, SourceError> {
+    let file = fs::File::open(path).map_err(|e| SourceError::open(path, e))?;
+    let archive = ZipArchive::new(file).map_err(|e| SourceError::parse(path, e))?;
+    let mut pages: Vec<String> = archive
+        .file_names()
+        .filter(|name| is_image(Path::new(name)))
+        .map(str::to_string)
+        .collect();
+    pages.sort_by(|a, b| sorting::natural_cmp(a, b));
+    Ok(pages)
+}
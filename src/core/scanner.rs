@@ -0,0 +1,81 @@
+use crate::core::reader::IMAGE_EXTENSIONS;
+use crate::core::sorting;
+use log::warn;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files that can be imported as sources
+pub const SUPPORTED_EXTENSIONS: [&str; 3] = ["pdf", "cbz", "zip"];
+
+pub fn is_supported_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Directories with images directly inside are read as a single source, one page per image
+pub fn is_image_folder(path: &Path) -> bool {
+    path.is_dir()
+        && fs::read_dir(path).is_ok_and(|entries| {
+            entries.filter_map(Result::ok).any(|entry| {
+                let path = entry.path();
+                path.is_file()
+                    && path
+                        .extension()
+                        .and_then(OsStr::to_str)
+                        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            })
+        })
+}
+
+/// Collect every supported file and image folder in `paths`, walking directories recursively.\
+/// Files inside a directory are returned sorted naturally by path, after the directory itself if it's an image folder.
+/// Hidden files and directories are skipped, and so are single images, which can be opened but aren't chapters.
+pub fn scan_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            scan_dir(path, &mut files);
+        } else if is_supported_file(path) {
+            files.push(path.clone());
+        } else {
+            warn!("Skipping unsupported file: {}", path.display());
+        }
+    }
+    files
+}
+
+fn scan_dir(dir: &Path, files: &mut Vec<PathBuf>) {
+    if is_image_folder(dir) {
+        files.push(dir.to_path_buf());
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Could not read directory `{}`: {}", dir.display(), e);
+            return;
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            !path
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|name| name.starts_with('.'))
+        })
+        .collect();
+    paths.sort_by(|a, b| sorting::natural_cmp_paths(a, b));
+
+    for path in paths {
+        if path.is_dir() {
+            scan_dir(&path, files);
+        } else if is_supported_file(&path) {
+            files.push(path);
+        }
+    }
+}
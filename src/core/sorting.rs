@@ -0,0 +1,201 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// Words before a volume number, e.g. "v03", "Vol. 3" or "Volume 3"
+const VOLUME_MARKERS: [&str; 4] = ["v", "vol", "volume", "tome"];
+/// Words before a chapter number, e.g. "c021.5", "Ch. 12" or "Chapter 12"
+const CHAPTER_MARKERS: [&str; 6] = ["c", "ch", "chap", "chapter", "ep", "episode"];
+
+/// Volume and chapter numbers found in a file name. Extra chapters have decimal numbers, e.g. 21.5
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ChapterNumbers {
+    pub volume: Option<f64>,
+    pub chapter: Option<f64>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Number(f64),
+}
+
+/// Find the volume and chapter numbers in a file name, without its extension.\
+/// Numbers after a marker like "v", "vol.", "c" or "ch." are used first. Without a chapter marker,
+/// the last number that isn't a volume number or inside brackets is taken as the chapter, e.g. "One Piece 1001".
+pub fn parse_numbers(name: &str) -> ChapterNumbers {
+    let name = name.to_lowercase();
+    let tokens = tokenize(&name);
+
+    let mut numbers = ChapterNumbers::default();
+    let mut last_number: Option<f64> = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1)) {
+            (Token::Word(word), Some(Token::Number(number))) if VOLUME_MARKERS.contains(word) => {
+                numbers.volume = numbers.volume.or(Some(*number));
+                i += 2;
+            }
+            (Token::Word(word), Some(Token::Number(number))) if CHAPTER_MARKERS.contains(word) => {
+                numbers.chapter = numbers.chapter.or(Some(*number));
+                i += 2;
+            }
+            (Token::Number(number), _) => {
+                last_number = Some(*number);
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    numbers.chapter = numbers.chapter.or(last_number);
+    numbers
+}
+
+/// Split a name into words and numbers, skipping separators and anything inside brackets
+fn tokenize(name: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut depth: usize = 0;
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        let start = i;
+        if c.is_ascii_digit() {
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            // Decimal part of extra chapters, e.g. "21.5"
+            if i + 1 < bytes.len() && bytes[i] == b'.' && bytes[i + 1].is_ascii_digit() {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if depth == 0
+                && let Ok(number) = name[start..i].parse()
+            {
+                tokens.push(Token::Number(number));
+            }
+        } else if c.is_ascii_alphabetic() {
+            while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            if depth == 0 {
+                tokens.push(Token::Word(&name[start..i]));
+            }
+        } else {
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// Compare names the way people read them: "Chapter 2" comes before "Chapter 10".\
+/// Runs of digits are compared by value and everything else ignoring case.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    while let (Some(&a_char), Some(&b_char)) = (a_chars.peek(), b_chars.peek()) {
+        let ordering = if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+            let a_digits = take_digits(&mut a_chars);
+            let b_digits = take_digits(&mut b_chars);
+            let a_value = a_digits.trim_start_matches('0');
+            let b_value = b_digits.trim_start_matches('0');
+            a_value
+                .len()
+                .cmp(&b_value.len())
+                .then_with(|| a_value.cmp(b_value))
+        } else {
+            a_chars.next();
+            b_chars.next();
+            a_char.to_lowercase().cmp(b_char.to_lowercase())
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a_chars
+        .peek()
+        .is_some()
+        .cmp(&b_chars.peek().is_some())
+        // Names that only differ in case or leading zeros still need an order
+        .then_with(|| a.cmp(b))
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+pub fn natural_cmp_paths(a: &Path, b: &Path) -> Ordering {
+    natural_cmp(&a.to_string_lossy(), &b.to_string_lossy())
+}
+
+/// Reading order of a chapter: by volume, then chapter number, then name.\
+/// Numbers come from the metadata if there's any, or from the file name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    volume: Option<f64>,
+    chapter: Option<f64>,
+    name: String,
+}
+
+impl SortKey {
+    /// `path` is a chapter file or folder. Its name without the extension is parsed
+    pub fn from_path(path: &Path) -> Self {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let numbers = parse_numbers(&name);
+        Self {
+            volume: numbers.volume,
+            chapter: numbers.chapter,
+            name,
+        }
+    }
+
+    /// Override the numbers found in the name with the ones from the metadata, e.g. ComicInfo.xml.\
+    /// `chapter` is a string since chapter numbers can be like "10.5".
+    pub fn with_metadata(mut self, volume: Option<i32>, chapter: Option<&str>) -> Self {
+        if let Some(volume) = volume {
+            self.volume = Some(volume as f64);
+        }
+        if let Some(chapter) = chapter.and_then(|chapter| chapter.trim().parse().ok()) {
+            self.chapter = Some(chapter);
+        }
+        self
+    }
+}
+
+impl Eq for SortKey {}
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Chapters without a volume are usually the newest ones, not collected in a volume yet
+        cmp_numbers(self.volume, other.volume)
+            .then_with(|| cmp_numbers(self.chapter, other.chapter))
+            .then_with(|| natural_cmp(&self.name, &other.name))
+    }
+}
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Missing numbers go last
+fn cmp_numbers(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
@@ -0,0 +1,119 @@
+use crate::core::config::FiapoConfig;
+use log::warn;
+
+/// Built-in color schemes. The config colors are applied on top of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemePreset {
+    Dark,
+    Light,
+    /// Pure black background, for OLED screens
+    Amoled,
+}
+
+impl ThemePreset {
+    pub const ALL: [ThemePreset; 3] = [ThemePreset::Dark, ThemePreset::Light, ThemePreset::Amoled];
+
+    /// Name used in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThemePreset::Dark => "dark",
+            ThemePreset::Light => "light",
+            ThemePreset::Amoled => "amoled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ThemePreset::ALL
+            .into_iter()
+            .find(|preset| preset.name() == name.trim().to_lowercase())
+    }
+
+    pub fn palette(&self) -> Palette {
+        match self {
+            ThemePreset::Dark => Palette {
+                text: "#FFFFFF",
+                primary: "#DE7F7F",
+                accent: "#009999",
+                background_dark: "#111416",
+                background_light: "#222629",
+            },
+            ThemePreset::Light => Palette {
+                text: "#1B1D1F",
+                primary: "#C25555",
+                accent: "#007777",
+                background_dark: "#F3F4F5",
+                background_light: "#E1E3E6",
+            },
+            ThemePreset::Amoled => Palette {
+                text: "#FFFFFF",
+                primary: "#DE7F7F",
+                accent: "#00B3B3",
+                background_dark: "#000000",
+                background_light: "#141414",
+            },
+        }
+    }
+}
+
+/// Colors of a preset, one per CSS variable of `resources/styles/vars.css`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub text: &'static str,
+    pub primary: &'static str,
+    pub accent: &'static str,
+    pub background_dark: &'static str,
+    pub background_light: &'static str,
+}
+
+/// CSS that overrides the variables of `vars.css` with the theme of the config
+pub fn theme_css(config: &FiapoConfig) -> String {
+    let preset = ThemePreset::from_name(&config.theme).unwrap_or_else(|| {
+        warn!("Unknown theme `{}`. Using the dark theme...", config.theme);
+        ThemePreset::Dark
+    });
+    let palette = preset.palette();
+
+    let text = valid_color(config.text_color.as_deref()).unwrap_or(palette.text);
+    let (background_dark, background_light) = match valid_color(config.background_color.as_deref())
+    {
+        // Lighter backgrounds are derived from the custom one, so cards and panels still stand out
+        Some(background) => (
+            background.to_string(),
+            format!("mix({}, {}, 0.1)", background, text),
+        ),
+        None => (
+            palette.background_dark.to_string(),
+            palette.background_light.to_string(),
+        ),
+    };
+
+    format!(
+        ":root {{
+    --text: {};
+    --dim-text: alpha(var(--text), 0.5);
+    --primary: {};
+    --accent: {};
+    --background-dark: {};
+    --background-light: {};
+}}
+",
+        text, palette.primary, palette.accent, background_dark, background_light
+    )
+}
+
+/// Hex colors ("#RGB", "#RRGGBB" or "#RRGGBBAA") and color names ("white") are accepted.
+/// Anything else is ignored, so the config can't inject CSS
+fn valid_color(color: Option<&str>) -> Option<&str> {
+    let color = color?.trim();
+    let is_valid = match color.strip_prefix('#') {
+        Some(hex) => {
+            matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+    };
+    if !is_valid {
+        warn!("Invalid color `{}`. Using the one of the theme...", color);
+        return None;
+    }
+    Some(color)
+}
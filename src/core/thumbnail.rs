@@ -0,0 +1,66 @@
+use crate::core::reader::Source;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use log::debug;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Size of the covers of the library cards
+pub const THUMBNAIL_WIDTH: u32 = 170;
+pub const THUMBNAIL_HEIGHT: u32 = 220;
+const THUMBNAIL_QUALITY: u8 = 85;
+
+/// Rendering a whole page takes a lot of memory, so thumbnails are generated one at a time
+static GENERATING: Mutex<()> = Mutex::new(());
+
+/// Where the thumbnail of a source is cached in `thumbnails_dir`.\
+/// The name changes with the size and modification time of the source, so edited files get new thumbnails.
+pub fn thumbnail_path(thumbnails_dir: &Path, source_path: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    source_path.hash(&mut hasher);
+    if let Ok(metadata) = fs::metadata(source_path) {
+        metadata.len().hash(&mut hasher);
+        metadata.modified().ok().hash(&mut hasher);
+    }
+    // The hash isn't stable across Rust versions, which only means thumbnails are generated again
+    thumbnails_dir.join(format!("{:016x}.jpg", hasher.finish()))
+}
+
+/// Return the cached thumbnail of the source at `source_path`, generating it from its cover page if needed
+pub fn get_or_generate(thumbnails_dir: &Path, source_path: &Path) -> anyhow::Result<PathBuf> {
+    let path = thumbnail_path(thumbnails_dir, source_path);
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let _lock = GENERATING.lock();
+    // Another thread may have generated it while this one waited
+    if path.exists() {
+        return Ok(path);
+    }
+    debug!("Generating thumbnail for {}...", source_path.display());
+    let mut source = Source::new(
+        Source::source_type_for_path(source_path)?,
+        source_path.to_path_buf(),
+        false,
+    );
+    if source.get_page_count() == 0 {
+        return Err(anyhow::anyhow!("`{}` has no pages", source_path.display()));
+    }
+    let page = source.get_cover_page();
+    let cover = source.render_page(page)?;
+    let thumbnail = cover
+        .resize_to_fill(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, FilterType::Triangle)
+        .to_rgb8();
+
+    fs::create_dir_all(thumbnails_dir)?;
+    // Written next to the final file and moved, so a crash doesn't leave half a thumbnail in the cache
+    let temp_path = path.with_extension("jpg.tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    thumbnail.write_with_encoder(JpegEncoder::new_with_quality(&mut file, THUMBNAIL_QUALITY))?;
+    fs::rename(&temp_path, &path)?;
+    Ok(path)
+}
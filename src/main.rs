@@ -1,49 +1,113 @@
-use env_logger::Env;
 use gtk::prelude::*;
 use gtk::{Application, gio, glib};
 use gtk4 as gtk;
 use std::cell::RefCell;
-use std::io::Write;
+use std::fs;
+use std::path::PathBuf;
 use std::rc::Rc;
 
 mod app;
+mod cli;
 mod core;
 mod server;
 mod ui;
 use app::FiapoController;
+use core::config::{FiapoConfig, LogConfig};
+use core::logging;
+use core::paths::AppPaths;
+use log::info;
 
 const APP_ID: &str = "github.uiriansan.fiapo";
-const CONFIG_FILE: &str = "~/.config/fiapo/fiapo.toml";
-const CSS_FILE: &str = "../resources/styles/main.css";
 
 #[tokio::main]
 async fn main() -> glib::ExitCode {
-    init_logger();
+    let mut args: Vec<String> = std::env::args().collect();
+    let config_override = match AppPaths::take_config_arg(&mut args) {
+        Ok(config_override) => config_override,
+        Err(e) => {
+            eprintln!("{}", e);
+            return glib::ExitCode::FAILURE;
+        }
+    };
+    let Some(paths) = AppPaths::resolve(config_override) else {
+        eprintln!(
+            "Could not resolve the config and data directories. Set `$HOME` or the XDG variables"
+        );
+        return glib::ExitCode::FAILURE;
+    };
+    init_logger(&paths);
+
+    if let Some(exit_code) = cli::run(&args, &paths).await {
+        return exit_code;
+    }
 
     gio::resources_register_include!("resources.gresource").expect("Failed to load gresources");
 
-    let application = Application::builder().application_id(APP_ID).build();
+    let application = Application::builder()
+        .application_id(APP_ID)
+        .flags(gio::ApplicationFlags::HANDLES_OPEN)
+        .build();
+
+    // GApplication forwards later launches to the running instance, so there's only one controller per instance
+    let controller: Rc<RefCell<Option<Rc<RefCell<FiapoController>>>>> = Rc::default();
 
-    application.connect_activate(|app| {
-        let controller = Rc::new(RefCell::new(FiapoController::new(app)));
-        controller.borrow_mut().load_config(CONFIG_FILE);
-        controller.borrow().load_css(CSS_FILE);
-        FiapoController::build_ui(controller);
+    application.connect_activate({
+        let controller = Rc::clone(&controller);
+        let paths = paths.clone();
+        move |app| {
+            get_or_build_controller(app, &paths, &controller)
+                .borrow()
+                .get_window()
+                .present();
+        }
     });
-    application.run()
+    application.connect_open({
+        let controller = Rc::clone(&controller);
+        move |app, files, _hint| {
+            let controller = get_or_build_controller(app, &paths, &controller);
+            let file_paths: Vec<PathBuf> = files.iter().filter_map(|file| file.path()).collect();
+            FiapoController::import_and_read(Rc::clone(&controller), &file_paths);
+            controller.borrow().get_window().present();
+        }
+    });
+    // `--config` was already taken out, since GApplication doesn't know about it
+    application.run_with_args(&args)
+}
+
+fn get_or_build_controller(
+    app: &Application,
+    paths: &AppPaths,
+    slot: &RefCell<Option<Rc<RefCell<FiapoController>>>>,
+) -> Rc<RefCell<FiapoController>> {
+    if let Some(controller) = slot.borrow().as_ref() {
+        return Rc::clone(controller);
+    }
+
+    let controller = Rc::new(RefCell::new(FiapoController::new(app, paths.clone())));
+    controller.borrow_mut().load_config();
+    controller.borrow().load_css();
+    FiapoController::watch_config(&controller);
+    controller.borrow_mut().load_library();
+    FiapoController::watch_library(&controller);
+    controller.borrow_mut().start_opds_server();
+    FiapoController::build_ui(Rc::clone(&controller));
+
+    *slot.borrow_mut() = Some(Rc::clone(&controller));
+    controller
 }
 
-fn init_logger() {
-    // TODO: Save to a file. Looks like 'env_logger' can't do it.
-    env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
-        .format(|buf, record| {
-            let warn_style = buf.default_level_style(record.level());
-            writeln!(
-                buf,
-                "{warn_style}[{}]:{warn_style:#} {}",
-                record.level(),
-                record.args()
-            )
-        })
-        .init();
+/// Log to the terminal and to a file in the state directory, with the levels of the config
+fn init_logger(paths: &AppPaths) {
+    // The config is loaded again by the controller, which reports its problems once logging works
+    let log_config = fs::read_to_string(&paths.config_file)
+        .map(|contents| FiapoConfig::from_toml(&contents).0.log)
+        .unwrap_or_else(|_| LogConfig::new());
+    logging::init(&paths.log_file(), &log_config);
+
+    if paths.portable {
+        info!(
+            "Portable mode. Keeping files in {}...",
+            paths.data_dir.display()
+        );
+    }
 }
@@ -0,0 +1,929 @@
+use crate::core::comic_info::{ComicInfo, Manga};
+use crate::core::crop::CropBox;
+use crate::core::layout::Rotation;
+use crate::core::outline::OutlineChapter;
+use crate::core::reader::{Source, SourceType};
+use crate::core::sorting::{self, SortKey};
+use log::{info, warn};
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior, params};
+use serde::Serialize;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Schema of the library database. See `docs/db_er.png`.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS Source (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    type          INTEGER NOT NULL,
+    source        INTEGER NOT NULL DEFAULT 0,
+    title         TEXT NOT NULL,
+    author        TEXT,
+    path          TEXT NOT NULL UNIQUE,
+    page_count    INTEGER NOT NULL DEFAULT 0,
+    progress      INTEGER NOT NULL DEFAULT 0,
+    addition_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_read     TIMESTAMP,
+    anilist_id    BIGINT,
+    mangadex_id   BIGINT,
+    language      TEXT,
+    favourite     BOOLEAN NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS Volume (
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_id INTEGER NOT NULL REFERENCES Source(id) ON DELETE CASCADE,
+    title     TEXT,
+    path      TEXT
+);
+CREATE TABLE IF NOT EXISTS Chapter (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    volume_id  INTEGER NOT NULL REFERENCES Volume(id) ON DELETE CASCADE,
+    title      TEXT,
+    page_count INTEGER NOT NULL DEFAULT 0,
+    path       TEXT UNIQUE,
+    downloaded BOOLEAN NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS Page (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    chapter_id INTEGER NOT NULL REFERENCES Chapter(id) ON DELETE CASCADE,
+    path       TEXT,
+    bookmark   BOOLEAN NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS Genres (
+    id   INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS SourceGenres (
+    source_id INTEGER NOT NULL REFERENCES Source(id) ON DELETE CASCADE,
+    genre_id  INTEGER NOT NULL REFERENCES Genres(id) ON DELETE CASCADE,
+    PRIMARY KEY (source_id, genre_id)
+);
+";
+
+/// Schema changes applied on top of `SCHEMA`, in order.\
+/// `PRAGMA user_version` holds how many of them were already applied to the database.
+const MIGRATIONS: &[&str] = &[
+    // ComicInfo.xml metadata
+    "ALTER TABLE Source ADD COLUMN right_to_left BOOLEAN NOT NULL DEFAULT 1;
+     ALTER TABLE Source ADD COLUMN summary TEXT;
+     ALTER TABLE Volume ADD COLUMN number INTEGER;
+     ALTER TABLE Chapter ADD COLUMN number TEXT;",
+    // Queue of MangaDex chapters to download
+    "CREATE TABLE DownloadQueue (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        mangadex_id   TEXT NOT NULL UNIQUE,
+        status        TEXT NOT NULL DEFAULT 'queued',
+        error         TEXT,
+        path          TEXT,
+        addition_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );",
+    // Pages are identified by their 0-based index in the chapter, since archives and PDFs have no page paths
+    "ALTER TABLE Page ADD COLUMN number INTEGER;
+     CREATE UNIQUE INDEX Page_chapter_number ON Page (chapter_id, number);",
+    // Files that were moved or deleted are kept, so their progress and bookmarks come back with them
+    "ALTER TABLE Chapter ADD COLUMN missing BOOLEAN NOT NULL DEFAULT 0;",
+    // Chapters read to the end. `Source.progress` is the page count of those chapters
+    "ALTER TABLE Chapter ADD COLUMN read BOOLEAN NOT NULL DEFAULT 0;",
+    // Passwords of encrypted PDFs, encrypted with the master key. See `server::keyring`
+    "CREATE TABLE PdfPassword (
+        path     TEXT PRIMARY KEY,
+        password BLOB NOT NULL
+    );",
+    // A file can hold several chapters, e.g. a PDF volume split by its outline, so paths aren't unique anymore.
+    // SQLite can't drop the constraint, so the table is rebuilt
+    "CREATE TABLE Chapter_new (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        volume_id  INTEGER NOT NULL REFERENCES Volume(id) ON DELETE CASCADE,
+        title      TEXT,
+        page_count INTEGER NOT NULL DEFAULT 0,
+        path       TEXT,
+        downloaded BOOLEAN NOT NULL DEFAULT 0,
+        number     TEXT,
+        missing    BOOLEAN NOT NULL DEFAULT 0,
+        read       BOOLEAN NOT NULL DEFAULT 0,
+        first_page INTEGER NOT NULL DEFAULT 0,
+        last_page  INTEGER
+     );
+     INSERT INTO Chapter_new (id, volume_id, title, page_count, path, downloaded, number, missing, read)
+        SELECT id, volume_id, title, page_count, path, downloaded, number, missing, read FROM Chapter;
+     DROP TABLE Chapter;
+     ALTER TABLE Chapter_new RENAME TO Chapter;
+     CREATE UNIQUE INDEX Chapter_path_first_page ON Chapter (path, first_page);",
+    // Crop of pages set by hand, as shares of the page. NULL for pages cropped automatically
+    "ALTER TABLE Page ADD COLUMN crop_left REAL;
+     ALTER TABLE Page ADD COLUMN crop_top REAL;
+     ALTER TABLE Page ADD COLUMN crop_right REAL;
+     ALTER TABLE Page ADD COLUMN crop_bottom REAL;",
+    // Rotation of pages set by hand, in degrees clockwise
+    "ALTER TABLE Page ADD COLUMN rotation INTEGER NOT NULL DEFAULT 0;",
+];
+
+/// How long a connection waits for the others to finish writing, since the GUI, the watcher, the OPDS server
+/// and the CLI can all have the library open at once
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timestamps are stored with `CURRENT_TIMESTAMP`, but OPDS and friends want RFC 3339
+const TIMESTAMP_FORMAT: &str = "'%Y-%m-%dT%H:%M:%SZ'";
+
+/// A row of the `Source` table. Not to be confused with `core::reader::Source`, which is a single readable file.
+#[derive(Clone, Debug, Default, Serialize)]
+#[allow(dead_code)]
+pub struct SourceRecord {
+    pub id: i64,
+    pub title: String,
+    pub author: Option<String>,
+    pub path: PathBuf,
+    pub page_count: usize,
+    pub addition_date: String,
+    pub right_to_left: bool,
+    pub language: Option<String>,
+    pub summary: Option<String>,
+    /// Pages read
+    pub progress: usize,
+    pub last_read: Option<String>,
+    /// Chapters whose files were moved or deleted
+    pub missing_chapters: usize,
+    /// First chapter that can be read, used to generate the cover
+    pub cover_path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[allow(dead_code)]
+pub struct ChapterRecord {
+    pub id: i64,
+    pub source_id: i64,
+    pub volume_id: i64,
+    pub title: String,
+    pub path: PathBuf,
+    pub page_count: usize,
+    pub addition_date: String,
+    /// Chapter number from the metadata, e.g. "10.5"
+    pub number: Option<String>,
+    pub volume_number: Option<i32>,
+    /// The file was moved or deleted. See `server::watcher`
+    pub missing: bool,
+    /// The chapter was read to the end
+    pub read: bool,
+    /// 0-based pages of the file in this chapter, inclusive. `last_page` is `None` if the chapter is the whole file
+    pub first_page: usize,
+    pub last_page: Option<usize>,
+}
+impl ChapterRecord {
+    /// Pages of the file in this chapter, if the file holds several chapters. See `Source::section`
+    pub fn pages(&self) -> Option<Range<usize>> {
+        self.last_page
+            .map(|last_page| self.first_page..last_page + 1)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DownloadRecord {
+    pub id: i64,
+    pub mangadex_id: String,
+    /// "queued", "done" or "failed"
+    pub status: String,
+    pub error: Option<String>,
+    pub path: Option<PathBuf>,
+    pub addition_date: String,
+}
+
+/// Result of `Library::check_integrity`
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct IntegrityReport {
+    /// Output of `PRAGMA integrity_check`, "ok" if the database is fine
+    pub integrity: Vec<String>,
+    /// Rows that violate foreign keys, as "table(rowid) -> parent"
+    pub foreign_key_violations: Vec<String>,
+    /// Files of chapters that are gone, each listed once
+    pub missing_files: Vec<PathBuf>,
+}
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.integrity == ["ok"]
+            && self.foreign_key_violations.is_empty()
+            && self.missing_files.is_empty()
+    }
+}
+
+/// Local library of imported sources.\
+/// Each thread should open its own `Library`, since `rusqlite::Connection` can't be shared.
+#[derive(Debug)]
+pub struct Library {
+    connection: Connection,
+    path: PathBuf,
+}
+impl Library {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        info!("Opening library from {}...", path.display());
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        // Readers don't block the writer in WAL mode
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        connection.execute_batch(SCHEMA)?;
+        // Foreign keys are turned on afterwards, or migrations that rebuild a table would cascade into its children
+        Library::migrate(&connection)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+
+        Ok(Self {
+            connection,
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn migrate(connection: &Connection) -> anyhow::Result<()> {
+        // The version is read in a write transaction, so connections opened at the same time don't apply a migration twice
+        let transaction = Transaction::new_unchecked(connection, TransactionBehavior::Immediate)?;
+        let version: usize =
+            transaction.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))? as usize;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            info!("Migrating library to version {}...", i + 1);
+            transaction.execute_batch(&format!(
+                "{} PRAGMA user_version = {};",
+                migration,
+                i + 1
+            ))?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Current time formatted like the timestamps returned by the other queries
+    pub fn current_timestamp(&self) -> anyhow::Result<String> {
+        Ok(self.connection.query_row(
+            &format!("SELECT strftime({}, 'now')", TIMESTAMP_FORMAT),
+            [],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Add a file to the library, creating the parent Source and a placeholder Volume if needed.\
+    /// Files are grouped into Sources by their parent directory.
+    /// If `comic_info` is given, it's used to fill the Source, Volume and Chapter records, even if the file was already imported.
+    /// Returns the id of the Chapter.
+    pub fn add_file(
+        &self,
+        path: &Path,
+        source_type: &SourceType,
+        page_count: usize,
+        comic_info: Option<&ComicInfo>,
+    ) -> anyhow::Result<i64> {
+        let chapter_id = self.insert_file(path, source_type, page_count)?;
+        if let Some(info) = comic_info {
+            self.apply_comic_info(chapter_id, info)?;
+        }
+        Ok(chapter_id)
+    }
+
+    fn insert_file(
+        &self,
+        path: &Path,
+        source_type: &SourceType,
+        page_count: usize,
+    ) -> anyhow::Result<i64> {
+        let str_path = path.to_string_lossy();
+        // Another connection could add the same file between the check and the insert
+        let transaction =
+            Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        if let Some(chapter_id) = self.get_chapter_id(path, 0)? {
+            return Ok(chapter_id);
+        }
+
+        let parent = path.parent().unwrap_or(path);
+        let source_id = self.get_or_create_source(parent, source_type)?;
+        let volume_id = self.get_or_create_placeholder_volume(source_id)?;
+        let title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| str_path.to_string());
+
+        self.connection.execute(
+            "INSERT INTO Chapter (volume_id, title, page_count, path, downloaded) VALUES (?1, ?2, ?3, ?4, 1)",
+            params![volume_id, title, page_count as i64, str_path],
+        )?;
+        let chapter_id = self.connection.last_insert_rowid();
+        self.connection.execute(
+            "UPDATE Source SET page_count = page_count + ?1 WHERE id = ?2",
+            params![page_count as i64, source_id],
+        )?;
+        transaction.commit()?;
+
+        Ok(chapter_id)
+    }
+
+    /// Add a loaded `Source` to the library. See `add_file`.\
+    /// A new PDF whose outline has several chapters becomes a volume of those chapters. Returns the id of the first one.
+    pub fn import_source(&self, source: &Source) -> anyhow::Result<i64> {
+        let is_new = self.get_chapter_id(source.get_path(), 0)?.is_none();
+        let chapter_id = self.add_file(
+            source.get_path(),
+            source.get_source_type(),
+            source.get_page_count(),
+            source.get_comic_info(),
+        )?;
+        if is_new && *source.get_source_type() == SourceType::Pdf {
+            let chapters = source.table_of_contents().chapters(source.get_page_count());
+            if !chapters.is_empty() {
+                self.split_into_chapters(chapter_id, &chapters)?;
+            }
+        }
+        Ok(chapter_id)
+    }
+
+    /// Turn the chapter of a whole file into a volume of `chapters`, which cover every page of the file.\
+    /// The first chapter keeps the id of the whole file.
+    fn split_into_chapters(
+        &self,
+        chapter_id: i64,
+        chapters: &[OutlineChapter],
+    ) -> anyhow::Result<()> {
+        let chapter = self
+            .get_chapter(chapter_id)?
+            .ok_or_else(|| anyhow::anyhow!("Unknown chapter {}", chapter_id))?;
+        let name = chapter
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let number = sorting::parse_numbers(&name)
+            .volume
+            .map(|volume| volume as i32);
+        let str_path = chapter.path.to_string_lossy();
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT INTO Volume (source_id, title, path, number) VALUES (?1, ?2, ?3, ?4)",
+            params![chapter.source_id, name, str_path, number],
+        )?;
+        let volume_id = transaction.last_insert_rowid();
+        for (i, outline_chapter) in chapters.iter().enumerate() {
+            let pages = &outline_chapter.pages;
+            let last_page = pages.end.saturating_sub(1) as i64;
+            if i == 0 {
+                transaction.execute(
+                    "UPDATE Chapter SET volume_id = ?1, title = ?2, page_count = ?3, first_page = ?4, last_page = ?5 WHERE id = ?6",
+                    params![volume_id, outline_chapter.title, pages.len() as i64, pages.start as i64, last_page, chapter_id],
+                )?;
+            } else {
+                transaction.execute(
+                    "INSERT INTO Chapter (volume_id, title, page_count, path, downloaded, first_page, last_page) VALUES (?1, ?2, ?3, ?4, 1, ?5, ?6)",
+                    params![volume_id, outline_chapter.title, pages.len() as i64, str_path, pages.start as i64, last_page],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        info!(
+            "Split `{}` into {} chapters from its outline",
+            chapter.path.display(),
+            chapters.len()
+        );
+        Ok(())
+    }
+
+    /// Chapter of a file starting at `first_page`, which is 0 unless the file holds several chapters
+    pub fn get_chapter_id(&self, path: &Path, first_page: usize) -> anyhow::Result<Option<i64>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT id FROM Chapter WHERE path = ?1 AND first_page = ?2",
+                params![path.to_string_lossy(), first_page as i64],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Toggle the bookmark of a page. `page` is 0-based.\
+    /// Returns whether the page is bookmarked now.
+    pub fn toggle_bookmark(&self, chapter_id: i64, page: usize) -> anyhow::Result<bool> {
+        self.connection.execute(
+            "INSERT INTO Page (chapter_id, number, bookmark) VALUES (?1, ?2, 1)
+             ON CONFLICT (chapter_id, number) DO UPDATE SET bookmark = NOT bookmark",
+            params![chapter_id, page as i64],
+        )?;
+        Ok(self.connection.query_row(
+            "SELECT bookmark FROM Page WHERE chapter_id = ?1 AND number = ?2",
+            params![chapter_id, page as i64],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Set the crop of a page by hand, or go back to the automatic crop with `None`. `page` is 0-based
+    pub fn set_page_crop(
+        &self,
+        chapter_id: i64,
+        page: usize,
+        crop: Option<CropBox>,
+    ) -> anyhow::Result<()> {
+        let sides = crop.map(|crop| [crop.left, crop.top, crop.right, crop.bottom]);
+        let [left, top, right, bottom] = sides.map_or([None; 4], |sides| sides.map(Some));
+        self.connection.execute(
+            "INSERT INTO Page (chapter_id, number, crop_left, crop_top, crop_right, crop_bottom)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (chapter_id, number) DO UPDATE SET
+                crop_left = excluded.crop_left, crop_top = excluded.crop_top,
+                crop_right = excluded.crop_right, crop_bottom = excluded.crop_bottom",
+            params![chapter_id, page as i64, left, top, right, bottom],
+        )?;
+        Ok(())
+    }
+
+    /// Pages of a chapter cropped by hand, as (0-based page, crop)
+    pub fn get_page_crops(&self, chapter_id: i64) -> anyhow::Result<Vec<(usize, CropBox)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT number, crop_left, crop_top, crop_right, crop_bottom FROM Page
+             WHERE chapter_id = ?1 AND number IS NOT NULL AND crop_left IS NOT NULL",
+        )?;
+        let crops = statement
+            .query_map(params![chapter_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as usize,
+                    CropBox::new(
+                        row.get::<_, f64>(1)? as f32,
+                        row.get::<_, f64>(2)? as f32,
+                        row.get::<_, f64>(3)? as f32,
+                        row.get::<_, f64>(4)? as f32,
+                    ),
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(crops)
+    }
+
+    /// Set the rotation of a page by hand. `page` is 0-based
+    pub fn set_page_rotation(
+        &self,
+        chapter_id: i64,
+        page: usize,
+        rotation: Rotation,
+    ) -> anyhow::Result<()> {
+        self.connection.execute(
+            "INSERT INTO Page (chapter_id, number, rotation) VALUES (?1, ?2, ?3)
+             ON CONFLICT (chapter_id, number) DO UPDATE SET rotation = excluded.rotation",
+            params![chapter_id, page as i64, rotation.degrees()],
+        )?;
+        Ok(())
+    }
+
+    /// Pages of a chapter rotated by hand, as (0-based page, rotation)
+    pub fn get_page_rotations(&self, chapter_id: i64) -> anyhow::Result<Vec<(usize, Rotation)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT number, rotation FROM Page
+             WHERE chapter_id = ?1 AND number IS NOT NULL AND rotation != 0",
+        )?;
+        let rows = statement.query_map(params![chapter_id], |row| {
+            Ok((row.get::<_, i64>(0)? as usize, row.get::<_, i64>(1)?))
+        })?;
+        let mut rotations = Vec::new();
+        for row in rows {
+            let (page, degrees) = row?;
+            match Rotation::from_degrees(degrees) {
+                Some(rotation) => rotations.push((page, rotation)),
+                None => warn!(
+                    "Ignoring the rotation of page {}: {} degrees",
+                    page + 1,
+                    degrees
+                ),
+            }
+        }
+        Ok(rotations)
+    }
+
+    /// Mark a chapter as read to the end, and update the progress and last read date of its Source
+    pub fn mark_chapter_read(&self, chapter_id: i64) -> anyhow::Result<()> {
+        self.connection.execute(
+            "UPDATE Chapter SET read = 1 WHERE id = ?1",
+            params![chapter_id],
+        )?;
+        self.connection.execute(
+            "UPDATE Source SET
+                progress = (SELECT COALESCE(SUM(Chapter.page_count), 0) FROM Chapter JOIN Volume ON Volume.id = Chapter.volume_id
+                            WHERE Volume.source_id = Source.id AND Chapter.read),
+                last_read = CURRENT_TIMESTAMP
+             WHERE id = (SELECT Volume.source_id FROM Chapter JOIN Volume ON Volume.id = Chapter.volume_id WHERE Chapter.id = ?1)",
+            params![chapter_id],
+        )?;
+        Ok(())
+    }
+
+    /// Password of an encrypted PDF, as sealed by `Keyring::seal`
+    pub fn get_pdf_password(&self, path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT password FROM PdfPassword WHERE path = ?1",
+                params![path.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn save_pdf_password(&self, path: &Path, sealed: &[u8]) -> anyhow::Result<()> {
+        self.connection.execute(
+            "INSERT INTO PdfPassword (path, password) VALUES (?1, ?2)
+             ON CONFLICT (path) DO UPDATE SET password = excluded.password",
+            params![path.to_string_lossy(), sealed],
+        )?;
+        Ok(())
+    }
+
+    pub fn forget_pdf_password(&self, path: &Path) -> anyhow::Result<()> {
+        self.connection.execute(
+            "DELETE FROM PdfPassword WHERE path = ?1",
+            params![path.to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    /// Fill the records of a chapter (and its Source and Volume) from ComicInfo.xml metadata
+    fn apply_comic_info(&self, chapter_id: i64, info: &ComicInfo) -> anyhow::Result<()> {
+        let source_id: i64 = self.connection.query_row(
+            "SELECT Volume.source_id FROM Chapter JOIN Volume ON Volume.id = Chapter.volume_id WHERE Chapter.id = ?1",
+            params![chapter_id],
+            |row| row.get(0),
+        )?;
+
+        let author = info.writer.as_ref().or(info.penciller.as_ref());
+        let right_to_left = match info.manga {
+            Manga::YesAndRightToLeft => Some(true),
+            Manga::Yes | Manga::No => Some(false),
+            Manga::Unknown => None,
+        };
+        self.connection.execute(
+            "UPDATE Source SET
+                title = COALESCE(?1, title),
+                author = COALESCE(?2, author),
+                language = COALESCE(?3, language),
+                summary = COALESCE(?4, summary),
+                right_to_left = COALESCE(?5, right_to_left)
+             WHERE id = ?6",
+            params![
+                info.series,
+                author,
+                info.language_iso,
+                info.summary,
+                right_to_left,
+                source_id
+            ],
+        )?;
+
+        for genre in info.genres() {
+            self.connection.execute(
+                "INSERT OR IGNORE INTO Genres (name) VALUES (?1)",
+                params![genre],
+            )?;
+            self.connection.execute(
+                "INSERT OR IGNORE INTO SourceGenres (source_id, genre_id) SELECT ?1, id FROM Genres WHERE name = ?2",
+                params![source_id, genre],
+            )?;
+        }
+
+        if let Some(volume) = info.volume {
+            let volume_id = match self
+                .connection
+                .query_row(
+                    "SELECT id FROM Volume WHERE source_id = ?1 AND number = ?2",
+                    params![source_id, volume],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?
+            {
+                Some(id) => id,
+                None => {
+                    self.connection.execute(
+                        "INSERT INTO Volume (source_id, title, number) VALUES (?1, ?2, ?3)",
+                        params![source_id, format!("Volume {}", volume), volume],
+                    )?;
+                    self.connection.last_insert_rowid()
+                }
+            };
+            self.connection.execute(
+                "UPDATE Chapter SET volume_id = ?1 WHERE id = ?2",
+                params![volume_id, chapter_id],
+            )?;
+        }
+
+        let title = info.title.clone().or_else(|| {
+            info.number
+                .as_ref()
+                .map(|number| format!("Chapter {}", number))
+        });
+        self.connection.execute(
+            "UPDATE Chapter SET title = COALESCE(?1, title), number = COALESCE(?2, number) WHERE id = ?3",
+            params![title, info.number, chapter_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Write the library metadata of a chapter back into its archive's ComicInfo.xml.\
+    /// Only fields the library knows are written, the other elements of the existing ComicInfo.xml are kept.
+    pub fn write_comic_info(&self, chapter_id: i64) -> anyhow::Result<()> {
+        let chapter = self
+            .get_chapter(chapter_id)?
+            .ok_or_else(|| anyhow::anyhow!("Unknown chapter {}", chapter_id))?;
+        let source = self
+            .get_source(chapter.source_id)?
+            .ok_or_else(|| anyhow::anyhow!("Unknown source {}", chapter.source_id))?;
+        let existing = ComicInfo::read_from_archive(&chapter.path)?.unwrap_or_default();
+        let genres = self.get_genres(chapter.source_id)?;
+
+        // Chapter titles are guessed from the file name, so they aren't written
+        let info = ComicInfo {
+            series: Some(source.title),
+            writer: source.author,
+            summary: source.summary,
+            language_iso: source.language,
+            volume: chapter.volume_number,
+            number: chapter.number,
+            page_count: Some(chapter.page_count),
+            genre: (!genres.is_empty()).then(|| genres.join(", ")),
+            manga: match (source.right_to_left, existing.manga) {
+                (true, _) => Manga::YesAndRightToLeft,
+                (false, Manga::YesAndRightToLeft) => Manga::Yes,
+                (false, manga) => manga,
+            },
+            ..Default::default()
+        };
+        info.write_to_archive(&chapter.path)
+    }
+
+    pub fn get_genres(&self, source_id: i64) -> anyhow::Result<Vec<String>> {
+        let mut statement = self.connection.prepare(
+            "SELECT Genres.name FROM Genres JOIN SourceGenres ON SourceGenres.genre_id = Genres.id WHERE SourceGenres.source_id = ?1 ORDER BY Genres.name",
+        )?;
+        let rows = statement.query_map(params![source_id], |row| row.get(0))?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    fn get_or_create_source(&self, dir: &Path, source_type: &SourceType) -> anyhow::Result<i64> {
+        let str_path = dir.to_string_lossy();
+        if let Some(id) = self
+            .connection
+            .query_row(
+                "SELECT id FROM Source WHERE path = ?1",
+                params![str_path],
+                |row| row.get(0),
+            )
+            .optional()?
+        {
+            return Ok(id);
+        }
+
+        let title = dir
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| str_path.to_string());
+        self.connection.execute(
+            "INSERT INTO Source (type, title, path) VALUES (?1, ?2, ?3)",
+            params![source_type_to_int(source_type), title, str_path],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn get_or_create_placeholder_volume(&self, source_id: i64) -> anyhow::Result<i64> {
+        if let Some(id) = self
+            .connection
+            .query_row(
+                "SELECT id FROM Volume WHERE source_id = ?1 AND number IS NULL ORDER BY id LIMIT 1",
+                params![source_id],
+                |row| row.get(0),
+            )
+            .optional()?
+        {
+            return Ok(id);
+        }
+
+        self.connection.execute(
+            "INSERT INTO Volume (source_id) VALUES (?1)",
+            params![source_id],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn list_sources(&self) -> anyhow::Result<Vec<SourceRecord>> {
+        self.query_sources("ORDER BY title COLLATE NOCASE", params![])
+    }
+
+    pub fn search_sources(&self, query: &str) -> anyhow::Result<Vec<SourceRecord>> {
+        // `%` and `_` typed by the user are matched as they are
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{}%", escaped);
+        self.query_sources(
+            "WHERE title LIKE ?1 ESCAPE '\\' OR author LIKE ?1 ESCAPE '\\' ORDER BY title COLLATE NOCASE",
+            params![pattern],
+        )
+    }
+
+    pub fn get_source(&self, id: i64) -> anyhow::Result<Option<SourceRecord>> {
+        Ok(self
+            .query_sources("WHERE id = ?1", params![id])?
+            .into_iter()
+            .next())
+    }
+
+    fn query_sources(
+        &self,
+        clause: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> anyhow::Result<Vec<SourceRecord>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT id, title, author, path, page_count, strftime({0}, addition_date), right_to_left, language, summary, progress, strftime({0}, last_read),
+                (SELECT COUNT(*) FROM Chapter JOIN Volume ON Volume.id = Chapter.volume_id WHERE Volume.source_id = Source.id AND Chapter.missing),
+                (SELECT Chapter.path FROM Chapter JOIN Volume ON Volume.id = Chapter.volume_id WHERE Volume.source_id = Source.id AND NOT Chapter.missing
+                 ORDER BY Volume.number, Volume.id, Chapter.path LIMIT 1)
+             FROM Source {1}",
+            TIMESTAMP_FORMAT, clause
+        ))?;
+        let rows = statement.query_map(params, |row| {
+            Ok(SourceRecord {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                author: row.get(2)?,
+                path: PathBuf::from(row.get::<_, String>(3)?),
+                page_count: row.get::<_, i64>(4)? as usize,
+                addition_date: row.get(5)?,
+                right_to_left: row.get(6)?,
+                language: row.get(7)?,
+                summary: row.get(8)?,
+                progress: row.get::<_, i64>(9)? as usize,
+                last_read: row.get(10)?,
+                missing_chapters: row.get::<_, i64>(11)? as usize,
+                cover_path: row.get::<_, Option<String>>(12)?.map(PathBuf::from),
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Chapters of a source in reading order. See `sorting::SortKey`
+    pub fn list_chapters(&self, source_id: i64) -> anyhow::Result<Vec<ChapterRecord>> {
+        let mut chapters = self.query_chapters(
+            "WHERE Volume.source_id = ?1 ORDER BY Volume.number, Volume.id, Chapter.path, Chapter.first_page",
+            params![source_id],
+        )?;
+        chapters.sort_by_cached_key(|chapter| {
+            (
+                SortKey::from_path(&chapter.path)
+                    .with_metadata(chapter.volume_number, chapter.number.as_deref()),
+                chapter.first_page,
+            )
+        });
+        Ok(chapters)
+    }
+
+    /// Most recently added chapters, newest first
+    pub fn recent_chapters(&self, limit: usize) -> anyhow::Result<Vec<ChapterRecord>> {
+        self.query_chapters("ORDER BY Chapter.id DESC LIMIT ?1", params![limit as i64])
+    }
+
+    pub fn all_chapters(&self) -> anyhow::Result<Vec<ChapterRecord>> {
+        self.query_chapters("", params![])
+    }
+
+    /// Mark a chapter whose file was moved or deleted, or unmark it when it's back.\
+    /// Returns whether the chapter changed.
+    pub fn set_chapter_missing(&self, chapter_id: i64, missing: bool) -> anyhow::Result<bool> {
+        let changed = self.connection.execute(
+            "UPDATE Chapter SET missing = ?1 WHERE id = ?2 AND missing != ?1",
+            params![missing, chapter_id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    pub fn get_chapter(&self, id: i64) -> anyhow::Result<Option<ChapterRecord>> {
+        Ok(self
+            .query_chapters("WHERE Chapter.id = ?1", params![id])?
+            .into_iter()
+            .next())
+    }
+
+    fn query_chapters(
+        &self,
+        clause: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> anyhow::Result<Vec<ChapterRecord>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT Chapter.id, Volume.source_id, Chapter.volume_id, Chapter.title, Chapter.path, Chapter.page_count, strftime({}, Source.addition_date), Chapter.number, Volume.number, Chapter.missing, Chapter.read, Chapter.first_page, Chapter.last_page
+             FROM Chapter
+             JOIN Volume ON Volume.id = Chapter.volume_id
+             JOIN Source ON Source.id = Volume.source_id {}",
+            TIMESTAMP_FORMAT, clause
+        ))?;
+        let rows = statement.query_map(params, |row| {
+            Ok(ChapterRecord {
+                id: row.get(0)?,
+                source_id: row.get(1)?,
+                volume_id: row.get(2)?,
+                title: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                path: PathBuf::from(row.get::<_, Option<String>>(4)?.unwrap_or_default()),
+                page_count: row.get::<_, i64>(5)? as usize,
+                addition_date: row.get(6)?,
+                number: row.get(7)?,
+                volume_number: row.get(8)?,
+                missing: row.get(9)?,
+                read: row.get(10)?,
+                first_page: row.get::<_, i64>(11)? as usize,
+                last_page: row.get::<_, Option<i64>>(12)?.map(|page| page as usize),
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Add a MangaDex chapter to the download queue. Chapters already in the queue are left as they are
+    pub fn queue_download(&self, mangadex_id: &str) -> anyhow::Result<()> {
+        self.connection.execute(
+            "INSERT OR IGNORE INTO DownloadQueue (mangadex_id) VALUES (?1)",
+            params![mangadex_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_downloads(&self) -> anyhow::Result<Vec<DownloadRecord>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT id, mangadex_id, status, error, path, strftime({}, addition_date) FROM DownloadQueue ORDER BY id",
+            TIMESTAMP_FORMAT
+        ))?;
+        let rows = statement.query_map([], |row| {
+            Ok(DownloadRecord {
+                id: row.get(0)?,
+                mangadex_id: row.get(1)?,
+                status: row.get(2)?,
+                error: row.get(3)?,
+                path: row.get::<_, Option<String>>(4)?.map(PathBuf::from),
+                addition_date: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn set_download_status(
+        &self,
+        id: i64,
+        status: &str,
+        error: Option<&str>,
+        path: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        self.connection.execute(
+            "UPDATE DownloadQueue SET status = ?1, error = ?2, path = COALESCE(?3, path) WHERE id = ?4",
+            params![status, error, path.map(|p| p.to_string_lossy()), id],
+        )?;
+        Ok(())
+    }
+
+    /// Check the database itself and whether the imported files still exist
+    pub fn check_integrity(&self) -> anyhow::Result<IntegrityReport> {
+        let mut statement = self.connection.prepare("PRAGMA integrity_check")?;
+        let integrity = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut statement = self.connection.prepare("PRAGMA foreign_key_check")?;
+        let foreign_key_violations = statement
+            .query_map([], |row| {
+                Ok(format!(
+                    "{}({}) -> {}",
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+                    row.get::<_, String>(2)?
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut missing_files: Vec<PathBuf> = self
+            .all_chapters()?
+            .into_iter()
+            .map(|chapter| chapter.path)
+            .filter(|path| !path.exists())
+            .collect();
+        // Chapters of a PDF volume share its file
+        missing_files.sort();
+        missing_files.dedup();
+
+        Ok(IntegrityReport {
+            integrity,
+            foreign_key_violations,
+            missing_files,
+        })
+    }
+}
+
+fn source_type_to_int(source_type: &SourceType) -> i64 {
+    match source_type {
+        SourceType::Pdf => 0,
+        SourceType::ImageSequence => 1,
+        SourceType::Directory => 2,
+        SourceType::Archive => 3,
+    }
+}
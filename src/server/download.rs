@@ -0,0 +1,133 @@
+use crate::core::comic_info::{ComicInfo, Manga};
+use crate::core::error::SourceError;
+use mangadex_api::utils::download::DownloadElement;
+use mangadex_api::v5::MangaDexClient;
+use mangadex_api::v5::schema::RelatedAttributes;
+use mangadex_api_types_rust::{Language, ReferenceExpansionResource, RelationshipType};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// Download a MangaDex chapter into `<downloads_dir>/<manga title>/` as a CBZ with a ComicInfo.xml.\
+/// `progress` is called with (pages done, total pages) after each page.
+/// Returns the path of the CBZ.
+pub async fn download_chapter(
+    chapter_id: Uuid,
+    downloads_dir: &Path,
+    progress: impl FnMut(usize, usize),
+) -> anyhow::Result<PathBuf> {
+    let client = MangaDexClient::default();
+    let url = PathBuf::from(format!("https://mangadex.org/chapter/{}", chapter_id));
+
+    let chapter = client
+        .chapter()
+        .id(chapter_id)
+        .get()
+        .includes(vec![ReferenceExpansionResource::Manga])
+        .send()
+        .await
+        .map_err(|e| SourceError::network(&url, e))?
+        .data;
+    let manga_title = chapter
+        .find_first_relationships(RelationshipType::Manga)
+        .and_then(|manga| match manga.attributes.clone() {
+            Some(RelatedAttributes::Manga(attributes)) => attributes
+                .title
+                .get(&Language::English)
+                .or_else(|| attributes.title.values().next())
+                .cloned(),
+            _ => None,
+        })
+        .unwrap_or_else(|| "Unknown manga".to_string());
+    let attributes = &chapter.attributes;
+
+    let info = ComicInfo {
+        title: attributes.title.clone().filter(|t| !t.is_empty()),
+        series: Some(manga_title.clone()),
+        number: attributes.chapter.clone(),
+        volume: attributes.volume.as_deref().and_then(|v| v.parse().ok()),
+        language_iso: Some(attributes.translated_language.code2().to_string()),
+        manga: Manga::YesAndRightToLeft,
+        ..Default::default()
+    };
+
+    let pages = client
+        .download()
+        .chapter(chapter_id)
+        .report(false)
+        .build()?
+        .download_element_vec()
+        .await
+        .map_err(|e| SourceError::network(&url, e))?;
+
+    let dir = downloads_dir.join(sanitize_file_name(&manga_title));
+    fs::create_dir_all(&dir)?;
+    let file_name = match &attributes.chapter {
+        Some(number) => format!("Ch. {}", number),
+        None => chapter_id.to_string(),
+    };
+    let path = dir.join(format!("{}.cbz", sanitize_file_name(&file_name)));
+
+    // Written next to the CBZ and moved there once done, so a failed download isn't scanned as a chapter
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".fiapo-tmp");
+    let temp_path = PathBuf::from(temp_path);
+    if let Err(e) = write_cbz(&temp_path, pages, info, &url, progress) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    fs::rename(&temp_path, &path)?;
+
+    Ok(path)
+}
+
+/// Write the downloaded pages to `path`, numbered in order, followed by the ComicInfo.xml
+fn write_cbz(
+    path: &Path,
+    pages: Vec<DownloadElement>,
+    info: ComicInfo,
+    url: &Path,
+    mut progress: impl FnMut(usize, usize),
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(fs::File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let total = pages.len();
+    for (i, (file_name, bytes)) in pages.into_iter().enumerate() {
+        let bytes =
+            bytes.map_err(|e| SourceError::network(url, format!("{}: {}", file_name, e)))?;
+        let extension = Path::new(&file_name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_else(|| "jpg".to_string());
+        zip.start_file(format!("{:03}.{}", i + 1, extension), options)?;
+        zip.write_all(&bytes)?;
+        progress(i + 1, total);
+    }
+    zip.start_file(
+        crate::core::comic_info::COMIC_INFO_FILE,
+        SimpleFileOptions::default(),
+    )?;
+    zip.write_all(
+        ComicInfo {
+            page_count: Some(total),
+            ..info
+        }
+        .to_xml()?
+        .as_bytes(),
+    )?;
+    zip.finish()?;
+    Ok(())
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
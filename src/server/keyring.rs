@@ -0,0 +1,100 @@
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt::Debug;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+/// Size of the master key, for AES-256
+const KEY_LEN: usize = 32;
+
+/// Encrypts the PDF passwords saved in the library with a master key.\
+/// The key is kept in its own file, readable only by the user, so a copy of the database alone doesn't give the passwords away.
+pub struct Keyring {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+impl Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyring").finish()
+    }
+}
+
+impl Keyring {
+    /// Load the master key from `key_file`, creating it the first time
+    pub fn open(key_file: &Path) -> anyhow::Result<Self> {
+        let rng = SystemRandom::new();
+        let key = match fs::read(key_file) {
+            Ok(key) => key,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let mut key = vec![0; KEY_LEN];
+                rng.fill(&mut key)
+                    .map_err(|_| anyhow::anyhow!("Could not generate a master key"))?;
+                write_key_file(key_file, &key)?;
+                key
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let key = UnboundKey::new(&AES_256_GCM, &key)
+            .map_err(|_| anyhow::anyhow!("Invalid master key in `{}`", key_file.display()))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng,
+        })
+    }
+
+    /// Encrypt the password of the file at `path`. Returns the nonce followed by the encrypted password.\
+    /// The path is authenticated too, so a password can't be moved to another file.
+    pub fn seal(&self, path: &Path, password: &str) -> anyhow::Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("Could not generate a nonce"))?;
+        let mut sealed = password.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(path.to_string_lossy().as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow::anyhow!("Could not encrypt the password"))?;
+        Ok([nonce.as_slice(), &sealed].concat())
+    }
+
+    /// Decrypt a password encrypted by `seal` for the same `path`
+    pub fn unseal(&self, path: &Path, sealed: &[u8]) -> anyhow::Result<String> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow::anyhow!("Saved password is too short"));
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow::anyhow!("Invalid nonce"))?;
+        let mut sealed = sealed.to_vec();
+        let password = self
+            .key
+            .open_in_place(
+                nonce,
+                Aad::from(path.to_string_lossy().as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| {
+                anyhow::anyhow!("Saved password can't be decrypted with this master key")
+            })?;
+        Ok(String::from_utf8(password.to_vec())?)
+    }
+}
+
+fn write_key_file(key_file: &Path, key: &[u8]) -> anyhow::Result<()> {
+    if let Some(dir) = key_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(key_file)?.write_all(key)?;
+    Ok(())
+}
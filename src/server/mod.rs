@@ -1,3 +1,9 @@
+pub mod database;
+pub mod download;
+pub mod keyring;
+pub mod opds;
+pub mod watcher;
+
 use mangadex_api::CDN_URL;
 use mangadex_api::v5::{MangaDexClient, schema::RelatedAttributes};
 use std::time::Duration;
@@ -0,0 +1,576 @@
+use crate::core::config::OpdsConfig;
+use crate::core::reader::{self, Source};
+use crate::server::database::{ChapterRecord, Library, SourceRecord};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use image::ImageFormat;
+use image::imageops::FilterType;
+use log::{error, info, warn};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use std::fmt;
+use std::fs;
+use std::io::{Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Request, Response, ResponseBox, Server};
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// How many entries the "recent" feed will list
+const RECENT_FEED_SIZE: usize = 50;
+
+const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+const PSE_STREAM_REL: &str = "http://vaemendis.net/opds-pse/stream";
+
+/// Small OPDS 1.2 server that exposes the local library, with OPDS-PSE page streaming.\
+/// Runs on its own thread and opens its own connection to the library.
+pub struct OpdsServer {
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+}
+impl std::fmt::Debug for OpdsServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpdsServer")
+            .field("address", &self.address())
+            .finish()
+    }
+}
+impl OpdsServer {
+    pub fn start(config: &OpdsConfig, library_path: PathBuf) -> anyhow::Result<Self> {
+        let server = Arc::new(
+            Server::http(&config.address)
+                .map_err(|e| anyhow::anyhow!("Could not bind to `{}`: {}", config.address, e))?,
+        );
+        // Validate the library before spawning the thread, so errors reach the caller
+        let library = Library::open(&library_path)?;
+        let password = match &config.password {
+            Some(password) => Some(Password::new(password)?),
+            None => None,
+        };
+
+        info!("Serving OPDS catalog at http://{}/opds", config.address);
+        let handle = thread::spawn({
+            let server = Arc::clone(&server);
+            move || {
+                let mut handler = OpdsHandler {
+                    library,
+                    password,
+                    open_source: None,
+                };
+                for request in server.incoming_requests() {
+                    handler.handle(request);
+                }
+            }
+        });
+
+        Ok(Self {
+            server,
+            handle: Some(handle),
+        })
+    }
+
+    /// Address the server listens on, with the port it was given if the config asked for port 0
+    pub fn address(&self) -> String {
+        self.server.server_addr().to_string()
+    }
+
+    pub fn stop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl Drop for OpdsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Password of the basic auth, compared without leaking how much of it a guess got right
+struct Password {
+    key: hmac::Key,
+    tag: hmac::Tag,
+}
+impl Password {
+    fn new(password: &str) -> anyhow::Result<Self> {
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .map_err(|_| anyhow::anyhow!("Could not generate a key for the OPDS password"))?;
+        let tag = hmac::sign(&key, password.as_bytes());
+        Ok(Self { key, tag })
+    }
+
+    /// HMAC tags are compared in constant time, and don't depend on the length of the password
+    fn matches(&self, given: &str) -> bool {
+        hmac::verify(&self.key, given.as_bytes(), self.tag.as_ref()).is_ok()
+    }
+}
+
+struct OpdsHandler {
+    library: Library,
+    password: Option<Password>,
+    /// Last source used for page streaming, so we don't reopen the file for every page
+    open_source: Option<(i64, Source)>,
+}
+impl OpdsHandler {
+    fn handle(&mut self, request: Request) {
+        if !self.is_authorized(&request) {
+            let response = Response::from_string("Unauthorized")
+                .with_status_code(401)
+                .with_header(header("WWW-Authenticate", "Basic realm=\"Fiapo\""));
+            let _ = request.respond(response);
+            return;
+        }
+
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        let result = match segments.as_slice() {
+            ["opds"] => self.root_feed(),
+            ["opds", "series"] => self.series_feed(),
+            ["opds", "series", id] => parse_id(id).and_then(|id| self.chapters_feed(id)),
+            ["opds", "recent"] => self.recent_feed(),
+            ["opds", "search"] => self.search_feed(&query_param(query, "q").unwrap_or_default()),
+            ["opds", "search.xml"] => Ok(self.search_description()),
+            ["opds", "download", id] => parse_id(id).and_then(|id| self.download(id)),
+            ["opds", "pages", id, page] => parse_id(id).and_then(|id| {
+                let page = page
+                    .parse()
+                    .map_err(|_| RequestError::BadRequest(format!("Invalid page `{}`", page)))?;
+                self.page(
+                    id,
+                    page,
+                    query_param(query, "width").and_then(|w| w.parse().ok()),
+                )
+            }),
+            _ => Err(RequestError::NotFound(format!("No route for `{}`", path)).into()),
+        };
+
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("OPDS request `{}` failed: {}", url, e);
+                // The details stay in the log, they can hold paths of the local files
+                let (status, body) = match e.downcast_ref::<RequestError>() {
+                    Some(RequestError::BadRequest(_)) => (400, "Bad request"),
+                    Some(RequestError::NotFound(_)) => (404, "Not found"),
+                    None => (500, "Internal server error"),
+                };
+                Response::from_string(body).with_status_code(status).boxed()
+            }
+        };
+        if let Err(e) = request.respond(response) {
+            error!("Failed to send OPDS response: {}", e);
+        }
+    }
+
+    /// Any username is accepted, only the password is checked
+    fn is_authorized(&self, request: &Request) -> bool {
+        let Some(password) = &self.password else {
+            return true;
+        };
+        request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .and_then(|h| h.value.as_str().strip_prefix("Basic "))
+            .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|credentials| {
+                credentials
+                    .split_once(':')
+                    .map(|(_, given)| password.matches(given))
+            })
+            .unwrap_or(false)
+    }
+
+    fn root_feed(&self) -> anyhow::Result<ResponseBox> {
+        let now = self.library.current_timestamp()?;
+        let entries = [
+            navigation_entry("series", "All series", &now, "/opds/series"),
+            navigation_entry("recent", "Recently added", &now, "/opds/recent"),
+        ]
+        .concat();
+        Ok(feed_response(
+            NAVIGATION_TYPE,
+            feed("root", "Fiapo", &now, "/opds", NAVIGATION_TYPE, &entries),
+        ))
+    }
+
+    fn series_feed(&self) -> anyhow::Result<ResponseBox> {
+        let entries = self.library.list_sources()?;
+        Ok(feed_response(
+            NAVIGATION_TYPE,
+            feed(
+                "series",
+                "All series",
+                &self.library.current_timestamp()?,
+                "/opds/series",
+                NAVIGATION_TYPE,
+                &series_entries(&entries),
+            ),
+        ))
+    }
+
+    fn chapters_feed(&self, source_id: i64) -> anyhow::Result<ResponseBox> {
+        let source = self
+            .library
+            .get_source(source_id)?
+            .ok_or_else(|| RequestError::NotFound(format!("Unknown series {}", source_id)))?;
+        let chapters = self.library.list_chapters(source_id)?;
+        Ok(feed_response(
+            ACQUISITION_TYPE,
+            feed(
+                &format!("series:{}", source_id),
+                &source.title,
+                &self.library.current_timestamp()?,
+                &format!("/opds/series/{}", source_id),
+                ACQUISITION_TYPE,
+                &chapter_entries(&chapters, source.author.as_deref()),
+            ),
+        ))
+    }
+
+    fn recent_feed(&self) -> anyhow::Result<ResponseBox> {
+        let chapters = self.library.recent_chapters(RECENT_FEED_SIZE)?;
+        Ok(feed_response(
+            ACQUISITION_TYPE,
+            feed(
+                "recent",
+                "Recently added",
+                &self.library.current_timestamp()?,
+                "/opds/recent",
+                ACQUISITION_TYPE,
+                &chapter_entries(&chapters, None),
+            ),
+        ))
+    }
+
+    fn search_feed(&self, query: &str) -> anyhow::Result<ResponseBox> {
+        let entries = self.library.search_sources(query)?;
+        Ok(feed_response(
+            NAVIGATION_TYPE,
+            feed(
+                "search",
+                &format!("Search: {}", query),
+                &self.library.current_timestamp()?,
+                "/opds/search",
+                NAVIGATION_TYPE,
+                &series_entries(&entries),
+            ),
+        ))
+    }
+
+    fn search_description(&self) -> ResponseBox {
+        let body = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>Fiapo</ShortName>
+  <Description>Search the Fiapo library</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <OutputEncoding>UTF-8</OutputEncoding>
+  <Url type="{}" template="/opds/search?q={{searchTerms}}"/>
+</OpenSearchDescription>"#,
+            NAVIGATION_TYPE
+        );
+        feed_response(OPENSEARCH_TYPE, body)
+    }
+
+    fn download(&self, chapter_id: i64) -> anyhow::Result<ResponseBox> {
+        let chapter = self.get_chapter(chapter_id)?;
+        let file = if chapter.path.is_dir() {
+            zip_directory(&chapter.path)?
+        } else {
+            fs::File::open(&chapter.path)?
+        };
+        Ok(Response::from_file(file)
+            .with_header(header("Content-Type", mime_type(&chapter.path)))
+            .boxed())
+    }
+
+    fn page(
+        &mut self,
+        chapter_id: i64,
+        page: usize,
+        max_width: Option<u32>,
+    ) -> anyhow::Result<ResponseBox> {
+        let source = match &mut self.open_source {
+            Some((id, source)) if *id == chapter_id => source,
+            _ => {
+                let chapter = self.get_chapter(chapter_id)?;
+                let source_type = Source::source_type_for_path(&chapter.path)?;
+                let mut source = Source::new(source_type, chapter.path.clone(), true);
+                if let Some(pages) = chapter.pages() {
+                    source = source.section(&chapter.title, pages);
+                }
+                &mut self.open_source.insert((chapter_id, source)).1
+            }
+        };
+
+        if page >= source.get_page_count() {
+            return Err(RequestError::NotFound(format!(
+                "No page {} in chapter {}",
+                page, chapter_id
+            ))
+            .into());
+        }
+        let mut image = source.render_page(page)?;
+        if let Some(width) = max_width
+            && width > 0
+            && image.width() > width
+        {
+            image = image.resize(width, u32::MAX, FilterType::Triangle);
+        }
+
+        let mut bytes: Vec<u8> = Vec::new();
+        image
+            .to_rgb8()
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg)?;
+        Ok(Response::from_data(bytes)
+            .with_header(header("Content-Type", "image/jpeg"))
+            .boxed())
+    }
+
+    fn get_chapter(&self, chapter_id: i64) -> anyhow::Result<ChapterRecord> {
+        self.library
+            .get_chapter(chapter_id)?
+            .ok_or_else(|| RequestError::NotFound(format!("Unknown chapter {}", chapter_id)).into())
+    }
+}
+
+/*
+ * Feed builders:
+ */
+fn feed(
+    id: &str,
+    title: &str,
+    updated: &str,
+    self_href: &str,
+    self_type: &str,
+    entries: &str,
+) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:opds="http://opds-spec.org/2010/catalog" xmlns:pse="http://vaemendis.net/opds-pse/ns">
+  <id>urn:fiapo:{}</id>
+  <title>{}</title>
+  <updated>{}</updated>
+  <link rel="self" href="{}" type="{}"/>
+  <link rel="start" href="/opds" type="{}"/>
+  <link rel="search" href="/opds/search.xml" type="{}"/>
+{}</feed>"#,
+        escape_xml(id),
+        escape_xml(title),
+        updated,
+        escape_xml(self_href),
+        self_type,
+        NAVIGATION_TYPE,
+        OPENSEARCH_TYPE,
+        entries
+    )
+}
+
+fn navigation_entry(id: &str, title: &str, updated: &str, href: &str) -> String {
+    format!(
+        r#"  <entry>
+    <id>urn:fiapo:{}</id>
+    <title>{}</title>
+    <updated>{}</updated>
+    <link rel="subsection" href="{}" type="{}"/>
+  </entry>
+"#,
+        escape_xml(id),
+        escape_xml(title),
+        updated,
+        escape_xml(href),
+        NAVIGATION_TYPE
+    )
+}
+
+fn series_entries(sources: &[SourceRecord]) -> String {
+    sources
+        .iter()
+        .map(|source| {
+            format!(
+                r#"  <entry>
+    <id>urn:fiapo:series:{}</id>
+    <title>{}</title>
+    <updated>{}</updated>
+    <content type="text">{} pages</content>
+    <link rel="subsection" href="/opds/series/{}" type="{}"/>
+  </entry>
+"#,
+                source.id,
+                escape_xml(&source.title),
+                source.addition_date,
+                source.page_count,
+                source.id,
+                ACQUISITION_TYPE
+            )
+        })
+        .collect()
+}
+
+fn chapter_entries(chapters: &[ChapterRecord], author: Option<&str>) -> String {
+    chapters
+        .iter()
+        .map(|chapter| {
+            let author = author
+                .map(|a| format!("    <author><name>{}</name></author>\n", escape_xml(a)))
+                .unwrap_or_default();
+            format!(
+                r#"  <entry>
+    <id>urn:fiapo:chapter:{id}</id>
+    <title>{title}</title>
+    <updated>{updated}</updated>
+{author}    <link rel="http://opds-spec.org/acquisition" href="/opds/download/{id}" type="{mime}"/>
+    <link rel="{pse}" href="/opds/pages/{id}/{{pageNumber}}?width={{maxWidth}}" type="image/jpeg" pse:count="{count}"/>
+  </entry>
+"#,
+                id = chapter.id,
+                title = escape_xml(&chapter.title),
+                updated = chapter.addition_date,
+                author = author,
+                mime = mime_type(&chapter.path),
+                pse = PSE_STREAM_REL,
+                count = chapter.page_count,
+            )
+        })
+        .collect()
+}
+
+fn feed_response(content_type: &str, body: String) -> ResponseBox {
+    Response::from_string(body)
+        .with_header(header(
+            "Content-Type",
+            &format!("{};charset=utf-8", content_type),
+        ))
+        .boxed()
+}
+
+/// Errors caused by the request rather than the server, answered with their own status code
+#[derive(Debug)]
+enum RequestError {
+    BadRequest(String),
+    NotFound(String),
+}
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::BadRequest(reason) | RequestError::NotFound(reason) => {
+                write!(f, "{}", reason)
+            }
+        }
+    }
+}
+impl std::error::Error for RequestError {}
+
+/*
+ * Helpers:
+ */
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("Invalid header")
+}
+
+fn parse_id(id: &str) -> anyhow::Result<i64> {
+    id.parse()
+        .map_err(|_| RequestError::BadRequest(format!("Invalid id `{}`", id)).into())
+}
+
+/// Pack the pages of an image folder into a CBZ, in an unnamed temporary file that's gone once the response is sent
+fn zip_directory(path: &Path) -> anyhow::Result<fs::File> {
+    let temp_path = std::env::temp_dir().join(format!(
+        "fiapo-opds-{}-{}.cbz",
+        std::process::id(),
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    ));
+    let file = fs::File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp_path)?;
+    // The open file stays readable after its name is removed
+    fs::remove_file(&temp_path)?;
+
+    let mut zip = ZipWriter::new(file);
+    // Images are already compressed
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for page in reader::list_directory_pages(path)? {
+        let name = page.file_name().unwrap_or_default().to_string_lossy();
+        zip.start_file(name, options)?;
+        zip.write_all(&fs::read(&page)?)?;
+    }
+    let mut file = zip.finish()?;
+    file.rewind()?;
+    Ok(file)
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    if path.is_dir() {
+        // Image folders are sent as CBZ, see `zip_directory`
+        return "application/vnd.comicbook+zip";
+    }
+    match path
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("pdf") => "application/pdf",
+        Some("cbz") => "application/vnd.comicbook+zip",
+        Some("zip") => "application/zip",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Get a percent-decoded value from a query string
+fn query_param(query: &str, key: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| percent_decode(value))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
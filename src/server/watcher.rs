@@ -0,0 +1,169 @@
+use crate::core::reader::Source;
+use crate::core::scanner;
+use crate::server::database::Library;
+use log::{info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Changes are reconciled once the folders have been quiet for this long, so files that are still being written aren't imported halfway
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Result of `rescan`
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RescanReport {
+    pub imported: Vec<PathBuf>,
+    /// Chapters whose files were moved or deleted
+    pub missing: Vec<PathBuf>,
+    /// Missing chapters whose files are back
+    pub restored: Vec<PathBuf>,
+    /// Files that could not be imported, with the reason
+    pub failed: Vec<(PathBuf, String)>,
+}
+impl RescanReport {
+    pub fn has_changes(&self) -> bool {
+        !self.imported.is_empty() || !self.missing.is_empty() || !self.restored.is_empty()
+    }
+
+    /// e.g. "2 imported, 1 missing"
+    pub fn summary(&self) -> String {
+        let counts = [
+            (self.imported.len(), "imported"),
+            (self.missing.len(), "missing"),
+            (self.restored.len(), "restored"),
+            (self.failed.len(), "failed"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, label)| format!("{} {}", count, label))
+            .collect();
+        if parts.is_empty() {
+            "No changes".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Import the new files and image folders of `folders`, and mark the chapters whose files are gone as missing.\
+/// Every chapter is checked, not only the ones in `folders`, since files imported by hand can be moved too.
+pub fn rescan(library: &Library, folders: &[PathBuf]) -> anyhow::Result<RescanReport> {
+    let mut report = RescanReport::default();
+    let folders: Vec<PathBuf> = folders
+        .iter()
+        .filter(|folder| {
+            let exists = folder.is_dir();
+            if !exists {
+                warn!("Library folder `{}` not found", folder.display());
+            }
+            exists
+        })
+        .cloned()
+        .collect();
+
+    for path in scanner::scan_paths(&folders) {
+        if library.get_chapter_id(&path, 0)?.is_some() {
+            continue;
+        }
+        let source = match Source::source_type_for_path(&path)
+            .and_then(|source_type| Source::open(source_type, path.clone(), false))
+        {
+            Ok(source) => source,
+            Err(e) => {
+                report.failed.push((path, e.reason().to_string()));
+                continue;
+            }
+        };
+        if source.get_page_count() == 0 {
+            report.failed.push((path, "No pages".to_string()));
+            continue;
+        }
+        match library.import_source(&source) {
+            Ok(_) => report.imported.push(path),
+            Err(e) => report.failed.push((path, e.to_string())),
+        }
+    }
+
+    for chapter in library.all_chapters()? {
+        let missing = !chapter.path.exists();
+        if library.set_chapter_missing(chapter.id, missing)? {
+            if missing {
+                report.missing.push(chapter.path);
+            } else {
+                report.restored.push(chapter.path);
+            }
+        }
+    }
+
+    for (path, e) in &report.failed {
+        warn!("Could not import `{}`: {}", path.display(), e);
+    }
+    info!("Library rescanned: {}", report.summary());
+    Ok(report)
+}
+
+/// Watches the library folders and rescans the library when they change. Dropping it stops watching
+#[derive(Debug)]
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+}
+impl LibraryWatcher {
+    /// Start watching `folders` recursively. The library is rescanned right away, to catch the changes made while the app was closed.\
+    /// `on_change` is called from a background thread with the result of each rescan that changed something.
+    pub fn start(
+        library_path: PathBuf,
+        folders: Vec<PathBuf>,
+        on_change: impl Fn(RescanReport) + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<()>();
+        let initial_rescan = sender.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if !event.kind.is_access() => {
+                    let _ = sender.send(());
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Error watching the library folders: {}", e),
+            })?;
+        for folder in &folders {
+            info!("Watching library folder {}...", folder.display());
+            if let Err(e) = watcher.watch(folder, RecursiveMode::Recursive) {
+                warn!("Could not watch `{}`: {}", folder.display(), e);
+            }
+        }
+        let _ = initial_rescan.send(());
+
+        thread::spawn(move || {
+            // `Library` can't be shared between threads, so this one has its own
+            let library = match Library::open(&library_path) {
+                Ok(library) => library,
+                Err(e) => {
+                    warn!("Could not open the library to watch its folders: {}", e);
+                    return;
+                }
+            };
+
+            // Ends when the watcher, and with it the sender, is dropped
+            while receiver.recv().is_ok() {
+                loop {
+                    match receiver.recv_timeout(SETTLE_TIME) {
+                        Ok(()) => continue,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                match rescan(&library, &folders) {
+                    Ok(report) if report.has_changes() => on_change(report),
+                    Ok(_) => {}
+                    Err(e) => warn!("Could not rescan the library: {}", e),
+                }
+            }
+        });
+
+        Ok(Self { _watcher: watcher })
+    }
+}
@@ -0,0 +1,46 @@
+use crate::core::keybindings::Action;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+type Handler = Rc<dyn Fn()>;
+
+/// Handlers for the actions of `core::keybindings`.\
+/// Screens register their handlers under the name of their stack child, and global handlers have no screen.
+/// Handlers of the visible screen take priority over global ones.
+#[derive(Default)]
+pub struct ActionRegistry {
+    /// Keyed by (screen, action)
+    handlers: HashMap<(Option<String>, Action), Handler>,
+}
+impl Debug for ActionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActionRegistry")
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+impl ActionRegistry {
+    /// Register a handler for `action`, replacing the previous one.\
+    /// `screen` is the name of the stack child the handler applies to, or `None` for every screen.
+    pub fn register(&mut self, screen: Option<&str>, action: Action, handler: impl Fn() + 'static) {
+        self.handlers
+            .insert((screen.map(str::to_string), action), Rc::new(handler));
+    }
+
+    /// Remove the handlers of `screen`, and whatever they hold, once it's gone
+    pub fn unregister_screen(&mut self, screen: &str) {
+        self.handlers
+            .retain(|(handler_screen, _), _| handler_screen.as_deref() != Some(screen));
+    }
+
+    /// Handler for `action` on `screen`, falling back to the global one.\
+    /// The handler is cloned out so the caller can release its borrows before running it.
+    pub fn get(&self, screen: Option<&str>, action: Action) -> Option<Handler> {
+        self.handlers
+            .get(&(screen.map(str::to_string), action))
+            .or_else(|| self.handlers.get(&(None, action)))
+            .cloned()
+    }
+}
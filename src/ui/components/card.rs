@@ -6,14 +6,20 @@ use gtk::subclass::{box_::BoxImpl, widget::WidgetImpl};
 use gtk::{gdk, glib};
 use gtk4 as gtk;
 use image::ImageReader;
+use log::warn;
 use std::cell::{OnceCell, RefCell};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::thread;
 
+use crate::core::thumbnail::{self, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
 use crate::server::MangadexSearchData;
+use crate::server::database::SourceRecord;
 
-const CARD_COVER_WIDTH: i32 = 170;
-const CARD_COVER_HEIGHT: i32 = 220;
+const CARD_COVER_WIDTH: i32 = THUMBNAIL_WIDTH as i32;
+const CARD_COVER_HEIGHT: i32 = THUMBNAIL_HEIGHT as i32;
+/// Shown when a cover can't be loaded
+const COVER_404_RESOURCE: &str = "/github/uiriansan/fiapo/images/cover_image_404.png";
 
 mod card_imp {
     use super::*;
@@ -23,6 +29,8 @@ mod card_imp {
         pub cover_picture: OnceCell<gtk::Picture>,
         pub title_label: OnceCell<gtk::Label>,
         pub author_label: OnceCell<gtk::Label>,
+        /// Source whose cover is being loaded. Cards are recycled, so covers of other sources that finish late are ignored
+        pub cover_source: RefCell<Option<PathBuf>>,
     }
     #[glib::object_subclass]
     impl ObjectSubclass for CardExt {
@@ -158,7 +166,9 @@ impl Card {
                         eprintln!("Failed to load texture from {}: {}", cover_url, e);
                         glib::MainContext::default().invoke(move || {
                             fragile::stack_token!(tok);
-                            cover_picture_clone.get(tok).set_visible(false);
+                            cover_picture_clone
+                                .get(tok)
+                                .set_resource(Some(COVER_404_RESOURCE));
                         });
                     }
                 }
@@ -234,6 +244,70 @@ impl Card {
         self.add_controller(click_controller);
     }
 
+    /// Fill the card with a local source from the library.\
+    /// Clicks are handled by the library grid, since it knows about the controller.
+    /// Thumbnails are generated in the background and cached in `thumbnails_dir`.
+    pub fn update_from_library(&self, record: &SourceRecord, thumbnails_dir: &Path) {
+        self.load_thumbnail(record.cover_path.clone(), thumbnails_dir);
+        if let Some(title_label) = self.get_title_label() {
+            title_label.set_text(&record.title);
+        }
+        if let Some(author_label) = self.get_author_label() {
+            author_label.set_text(record.author.as_deref().unwrap_or_default());
+        }
+        if record.missing_chapters > 0 {
+            self.add_css_class("manga-card-missing");
+            self.set_tooltip_text(Some(&format!(
+                "{}\n{} missing chapter(s). Were the files moved?",
+                record.title, record.missing_chapters
+            )));
+        } else {
+            self.remove_css_class("manga-card-missing");
+            self.set_tooltip_text(Some(&record.title));
+        }
+    }
+
+    fn load_thumbnail(&self, source_path: Option<PathBuf>, thumbnails_dir: &Path) {
+        let Some(cover_picture) = self.get_cover_picture() else {
+            return;
+        };
+        self.imp().cover_source.replace(source_path.clone());
+        let Some(source_path) = source_path else {
+            cover_picture.set_resource(Some(COVER_404_RESOURCE));
+            return;
+        };
+        // Don't show the cover of the previous source while this one loads
+        cover_picture.set_paintable(None::<&gdk::Paintable>);
+
+        let card = fragile::Sticky::new(self.downgrade());
+        let thumbnails_dir = thumbnails_dir.to_path_buf();
+        thread::spawn(move || {
+            let result = thumbnail::get_or_generate(&thumbnails_dir, &source_path);
+            if let Err(e) = &result {
+                warn!(
+                    "Failed to generate thumbnail for `{}`: {}",
+                    source_path.display(),
+                    e
+                );
+            }
+            glib::MainContext::default().invoke(move || {
+                fragile::stack_token!(tok);
+                let Some(card) = card.get(tok).upgrade() else {
+                    return;
+                };
+                if card.imp().cover_source.borrow().as_ref() != Some(&source_path) {
+                    return;
+                }
+                if let Some(cover_picture) = card.get_cover_picture() {
+                    match result {
+                        Ok(thumbnail_path) => cover_picture.set_filename(Some(&thumbnail_path)),
+                        Err(_) => cover_picture.set_resource(Some(COVER_404_RESOURCE)),
+                    }
+                }
+            });
+        });
+    }
+
     fn texture_from_url(url: String) -> Result<gdk::Texture, Box<dyn std::error::Error>> {
         let client = reqwest::blocking::Client::builder()
             .user_agent("github.uiriansan.fiapo")
@@ -250,8 +324,7 @@ impl Card {
             .decode()?;
         let scaled_image = image.thumbnail(CARD_COVER_WIDTH as u32, CARD_COVER_HEIGHT as u32);
 
-        let pixbuf = crate::core::image::dynamic_image_to_pixbuf(&scaled_image)?;
-        Ok(gdk::Texture::for_pixbuf(&pixbuf))
+        Ok(crate::core::image::texture_from_image(scaled_image))
     }
 }
 
@@ -284,3 +357,33 @@ impl MangadexSearchDataObject {
         self.imp().data.borrow().as_ref().unwrap().clone()
     }
 }
+
+mod library_data_imp {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    pub struct LibrarySourceObject {
+        pub data: RefCell<Option<SourceRecord>>,
+    }
+    #[glib::object_subclass]
+    impl ObjectSubclass for LibrarySourceObject {
+        const NAME: &'static str = "FiapoLibrarySourceObject";
+        type Type = super::LibrarySourceObject;
+    }
+
+    impl ObjectImpl for LibrarySourceObject {}
+}
+glib::wrapper! {
+    /// glib::Object wrapper around SourceRecord, so we can append library sources to the GridView's model.
+    pub struct LibrarySourceObject(ObjectSubclass<library_data_imp::LibrarySourceObject>);
+}
+impl LibrarySourceObject {
+    pub fn new(data: SourceRecord) -> Self {
+        let obj: Self = glib::Object::builder().build();
+        obj.imp().data.replace(Some(data));
+        obj
+    }
+    pub fn data(&self) -> SourceRecord {
+        self.imp().data.borrow().as_ref().unwrap().clone()
+    }
+}
@@ -0,0 +1,70 @@
+use gtk::prelude::*;
+use gtk::{Label, glib};
+use gtk4 as gtk;
+
+/// Shown between two chapters, in place of a page: "Finished X, next: Y"
+#[derive(Debug, Clone)]
+pub struct ChapterTransitionView {
+    container: gtk::Box,
+    finished: Label,
+    next: Label,
+    hint: Label,
+}
+impl Default for ChapterTransitionView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChapterTransitionView {
+    pub fn new() -> Self {
+        let finished = Label::new(None);
+        finished.set_wrap(true);
+        finished.set_justify(gtk::Justification::Center);
+        finished.add_css_class("chapter-transition-finished");
+        let next = Label::new(None);
+        next.set_wrap(true);
+        next.set_justify(gtk::Justification::Center);
+        next.add_css_class("chapter-transition-next");
+        let hint = Label::new(None);
+        hint.add_css_class("reader-hud-dim");
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 15);
+        container.set_halign(gtk::Align::Center);
+        container.set_valign(gtk::Align::Center);
+        container.add_css_class("chapter-transition");
+        container.append(&finished);
+        container.append(&next);
+        container.append(&hint);
+
+        Self {
+            container,
+            finished,
+            next,
+            hint,
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    /// `next` is `None` after the last chapter
+    pub fn update(&self, finished: &str, next: Option<&str>) {
+        self.finished.set_markup(&format!(
+            "Finished <b>{}</b>",
+            glib::markup_escape_text(finished)
+        ));
+        match next {
+            Some(next) => {
+                self.next
+                    .set_markup(&format!("Next: <b>{}</b>", glib::markup_escape_text(next)));
+                self.hint.set_text("Turn the page to keep reading");
+            }
+            None => {
+                self.next.set_text("That was the last chapter");
+                self.hint.set_text("");
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::core::crop::CropBox;
+use gtk::prelude::*;
+use gtk::{Button, Grid, Label, SpinButton, Window, glib};
+use gtk4 as gtk;
+use std::rc::Rc;
+
+/// Most that can be cropped off a side, in percent
+const MAX_SIDE: f64 = 45.0;
+
+/// Edit the crop of a page by hand, in percent of the page on each side.\
+/// `on_done` is called with the new crop, or with `None` to go back to the automatic crop. It's not called on cancel.
+pub fn edit_page_crop(
+    parent: &impl IsA<Window>,
+    page: usize,
+    crop: CropBox,
+    on_done: impl Fn(Option<CropBox>) + 'static,
+) {
+    let title = Label::new(Some(&format!("Crop of page {}", page + 1)));
+    title.add_css_class("crop-dialog-title");
+    title.set_halign(gtk::Align::Start);
+
+    let side_button = |value: f32| {
+        let button = SpinButton::with_range(0.0, MAX_SIDE, 0.5);
+        button.set_digits(1);
+        button.set_value((value * 100.0) as f64);
+        button
+    };
+    let left = side_button(crop.left);
+    let top = side_button(crop.top);
+    let right = side_button(crop.right);
+    let bottom = side_button(crop.bottom);
+    let sides = Grid::new();
+    sides.set_row_spacing(5);
+    sides.set_column_spacing(10);
+    for (row, (name, button)) in [
+        ("Left (%)", &left),
+        ("Top (%)", &top),
+        ("Right (%)", &right),
+        ("Bottom (%)", &bottom),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let label = Label::new(Some(name));
+        label.set_halign(gtk::Align::Start);
+        label.set_hexpand(true);
+        sides.attach(&label, 0, row as i32, 1, 1);
+        sides.attach(button, 1, row as i32, 1, 1);
+    }
+
+    let automatic_button = Button::with_label("Automatic");
+    automatic_button.set_tooltip_text(Some("Forget this crop and crop the page like the others"));
+    let cancel_button = Button::with_label("Cancel");
+    let apply_button = Button::with_label("Apply");
+    let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    buttons.set_halign(gtk::Align::End);
+    buttons.append(&automatic_button);
+    buttons.append(&cancel_button);
+    buttons.append(&apply_button);
+
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    container.add_css_class("crop-dialog");
+    container.append(&title);
+    container.append(&sides);
+    container.append(&buttons);
+
+    let window = Window::builder()
+        .title("Crop page")
+        .modal(true)
+        .transient_for(parent)
+        .default_width(300)
+        .resizable(false)
+        .child(&container)
+        .build();
+
+    let on_done = Rc::new(on_done);
+    cancel_button.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        move |_| window.close()
+    ));
+    automatic_button.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        #[strong]
+        on_done,
+        move |_| {
+            on_done(None);
+            window.close();
+        }
+    ));
+    apply_button.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        move |_| {
+            let share = |button: &SpinButton| (button.value() / 100.0) as f32;
+            on_done(Some(CropBox::new(
+                share(&left),
+                share(&top),
+                share(&right),
+                share(&bottom),
+            )));
+            window.close();
+        }
+    ));
+
+    window.present();
+    apply_button.grab_focus();
+}
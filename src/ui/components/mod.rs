@@ -1 +1,9 @@
 pub mod card;
+pub mod chapter_transition;
+pub mod crop_dialog;
+pub mod notification;
+pub mod page_error;
+pub mod page_thumbnails;
+pub mod password_dialog;
+pub mod reader_hud;
+pub mod table_of_contents;
@@ -0,0 +1,76 @@
+use gtk::prelude::*;
+use gtk::{Button, Label, Revealer, glib};
+use gtk4 as gtk;
+
+/// Banner shown over the top of the window, e.g. for errors in the config file
+#[derive(Debug, Clone)]
+pub struct Notification {
+    revealer: Revealer,
+    title: Label,
+    body: Label,
+}
+impl Default for Notification {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notification {
+    pub fn new() -> Self {
+        let title = Label::new(None);
+        title.set_halign(gtk::Align::Start);
+        title.set_hexpand(true);
+        title.add_css_class("notification-title");
+
+        let close_button = Button::with_label("Dismiss");
+        close_button.set_cursor(gtk::gdk::Cursor::from_name("pointer", None).as_ref());
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        header.append(&title);
+        header.append(&close_button);
+
+        let body = Label::new(None);
+        body.set_halign(gtk::Align::Start);
+        body.set_wrap(true);
+        body.set_selectable(true);
+        body.add_css_class("notification-body");
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        container.add_css_class("notification");
+        container.append(&header);
+        container.append(&body);
+
+        let revealer = Revealer::new();
+        revealer.set_transition_type(gtk::RevealerTransitionType::SlideDown);
+        revealer.set_halign(gtk::Align::Center);
+        revealer.set_valign(gtk::Align::Start);
+        revealer.set_child(Some(&container));
+
+        close_button.connect_clicked(glib::clone!(
+            #[weak]
+            revealer,
+            move |_| revealer.set_reveal_child(false)
+        ));
+
+        Self {
+            revealer,
+            title,
+            body,
+        }
+    }
+
+    pub fn widget(&self) -> &Revealer {
+        &self.revealer
+    }
+
+    pub fn show(&self, title: &str, body: &str) {
+        self.title.set_text(title);
+        self.body.set_text(body);
+        self.body.set_visible(!body.is_empty());
+        self.revealer.set_reveal_child(true);
+    }
+
+    pub fn hide(&self) {
+        self.revealer.set_reveal_child(false);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::core::error::SourceError;
+use gtk::prelude::*;
+use gtk::{Button, Label};
+use gtk4 as gtk;
+
+/// Shown in place of a page that couldn't be rendered, with a button to try again
+#[derive(Debug, Clone)]
+pub struct PageErrorView {
+    container: gtk::Box,
+    title: Label,
+    path: Label,
+    reason: Label,
+    retry_button: Button,
+}
+impl Default for PageErrorView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PageErrorView {
+    pub fn new() -> Self {
+        let title = Label::new(None);
+        title.add_css_class("page-error-title");
+        let path = Label::new(None);
+        path.set_wrap(true);
+        path.set_selectable(true);
+        path.add_css_class("reader-hud-dim");
+        let reason = Label::new(None);
+        reason.set_wrap(true);
+        reason.set_selectable(true);
+        reason.set_justify(gtk::Justification::Center);
+
+        let retry_button = Button::with_label("Retry");
+        retry_button.set_halign(gtk::Align::Center);
+        retry_button.set_cursor(gtk::gdk::Cursor::from_name("pointer", None).as_ref());
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 10);
+        container.set_halign(gtk::Align::Center);
+        container.set_valign(gtk::Align::Center);
+        container.add_css_class("page-error");
+        container.append(&title);
+        container.append(&path);
+        container.append(&reason);
+        container.append(&retry_button);
+
+        Self {
+            container,
+            title,
+            path,
+            reason,
+            retry_button,
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    pub fn connect_retry(&self, on_retry: impl Fn() + 'static) {
+        self.retry_button.connect_clicked(move |_| on_retry());
+    }
+
+    pub fn update(&self, error: &SourceError) {
+        let title = match (error, error.page()) {
+            (SourceError::Decode { .. }, Some(page)) => format!("Page {} is damaged", page + 1),
+            (_, Some(page)) => format!("Page {} could not be shown", page + 1),
+            (_, None) => "This chapter could not be opened".to_string(),
+        };
+        self.title.set_text(&title);
+        self.path.set_text(&error.path().display().to_string());
+        self.reason.set_text(error.reason());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::core::image;
+use crate::core::layout::PageView;
+use crate::core::reader::Source;
+use ::image::DynamicImage;
+use gtk::prelude::*;
+use gtk::{Button, FlowBox, Picture, Revealer, ScrolledWindow, glib};
+use gtk4 as gtk;
+use log::warn;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// Pages are rendered at the height of the grid. The filmstrip shows them smaller
+const GRID_THUMBNAIL_HEIGHT: i32 = 240;
+const STRIP_THUMBNAIL_HEIGHT: i32 = 110;
+/// Size of the placeholders shown until a page is rendered
+const THUMBNAIL_ASPECT_RATIO: f64 = 0.7;
+
+/// Thumbnails of the pages of a chapter, shown in a filmstrip at the bottom of the reader and in a grid overview.\
+/// Both are filled from a single background render.
+#[derive(Debug)]
+pub struct PageThumbnails {
+    strip: Revealer,
+    strip_scroll: ScrolledWindow,
+    strip_box: gtk::Box,
+    grid: ScrolledWindow,
+    grid_box: FlowBox,
+    /// Buttons of each page in the strip and in the grid
+    buttons: RefCell<Vec<(Button, Button)>>,
+    /// Pages shown, counting the pages of every source
+    pages: RefCell<Range<usize>>,
+    /// How each page is shown, so rotating or splitting pages shows their thumbnails again
+    views: RefCell<Vec<PageView>>,
+    /// Set to stop rendering when another chapter is loaded
+    cancelled: RefCell<Arc<AtomicBool>>,
+}
+impl Default for PageThumbnails {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PageThumbnails {
+    pub fn new() -> Self {
+        let strip_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        strip_box.add_css_class("page-strip");
+        let strip_scroll = ScrolledWindow::new();
+        strip_scroll.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Never);
+        strip_scroll.set_child(Some(&strip_box));
+
+        let strip = Revealer::new();
+        strip.set_transition_type(gtk::RevealerTransitionType::SlideUp);
+        strip.set_child(Some(&strip_scroll));
+
+        let grid_box = FlowBox::new();
+        grid_box.set_selection_mode(gtk::SelectionMode::None);
+        grid_box.set_homogeneous(true);
+        grid_box.set_max_children_per_line(20);
+        grid_box.set_valign(gtk::Align::Start);
+        grid_box.add_css_class("page-grid");
+        let grid = ScrolledWindow::new();
+        grid.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        grid.set_hexpand(true);
+        grid.set_vexpand(true);
+        grid.set_child(Some(&grid_box));
+
+        Self {
+            strip,
+            strip_scroll,
+            strip_box,
+            grid,
+            grid_box,
+            buttons: RefCell::new(Vec::new()),
+            pages: RefCell::new(0..0),
+            views: RefCell::new(Vec::new()),
+            cancelled: RefCell::new(Arc::new(AtomicBool::new(false))),
+        }
+    }
+
+    /// Filmstrip, hidden until `toggle_strip`
+    pub fn strip_widget(&self) -> &Revealer {
+        &self.strip
+    }
+
+    /// Grid overview of every page
+    pub fn grid_widget(&self) -> &ScrolledWindow {
+        &self.grid
+    }
+
+    pub fn toggle_strip(&self) {
+        self.strip.set_reveal_child(!self.strip.reveals_child());
+    }
+
+    pub fn pages(&self) -> Range<usize> {
+        self.pages.borrow().clone()
+    }
+
+    /// Whether the thumbnails shown are of `pages`, shown as `views`
+    pub fn shows(&self, pages: &Range<usize>, views: &[PageView]) -> bool {
+        *self.pages.borrow() == *pages && *self.views.borrow() == views
+    }
+
+    /// Show the pages of the source at `path`, rendering them in the background.\
+    /// `views` are how the pages of the file are shown, and `pages` the indexes of those pages in the reader, which are passed to `on_click`.
+    pub fn load(
+        &self,
+        path: PathBuf,
+        views: Vec<PageView>,
+        pages: Range<usize>,
+        on_click: impl Fn(usize) + Clone + 'static,
+    ) {
+        self.cancelled.borrow().store(true, Ordering::Relaxed);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancelled.replace(Arc::clone(&cancelled));
+
+        while let Some(child) = self.strip_box.first_child() {
+            self.strip_box.remove(&child);
+        }
+        self.grid_box.remove_all();
+
+        let mut buttons: Vec<(Button, Button)> = Vec::new();
+        let mut pictures: Vec<(Picture, Picture)> = Vec::new();
+        for (i, page) in pages.clone().enumerate() {
+            let (strip_button, strip_picture) =
+                page_button(STRIP_THUMBNAIL_HEIGHT, None, on_click.clone(), page);
+            strip_button.set_tooltip_text(Some(&format!("Page {}", i + 1)));
+            self.strip_box.append(&strip_button);
+
+            let (grid_button, grid_picture) = page_button(
+                GRID_THUMBNAIL_HEIGHT,
+                Some(&(i + 1).to_string()),
+                on_click.clone(),
+                page,
+            );
+            self.grid_box.append(&grid_button);
+
+            buttons.push((strip_button, grid_button));
+            pictures.push((strip_picture, grid_picture));
+        }
+        self.buttons.replace(buttons);
+        self.pages.replace(pages);
+        self.views.replace(views.clone());
+
+        let pictures = Arc::new(fragile::Sticky::new(pictures));
+        thread::spawn(move || {
+            // Sources can't be shared between threads, so this one is opened again
+            let mut source = match Source::source_type_for_path(&path) {
+                Ok(source_type) => Source::new(source_type, path.clone(), true),
+                Err(e) => {
+                    warn!("{}", e);
+                    return;
+                }
+            };
+            // Both halves of a spread are cut from the same render
+            let mut rendered: Option<(usize, DynamicImage)> = None;
+            for (i, view) in views.into_iter().enumerate() {
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                if rendered
+                    .as_ref()
+                    .is_none_or(|(page, _)| *page != view.file_page)
+                {
+                    match source.render_thumbnail(view.file_page, GRID_THUMBNAIL_HEIGHT as u32) {
+                        Ok(thumbnail) => rendered = Some((view.file_page, thumbnail)),
+                        Err(e) => {
+                            warn!("Failed to render a thumbnail. {}", e);
+                            continue;
+                        }
+                    }
+                }
+                let Some((_, page)) = &rendered else {
+                    continue;
+                };
+                let thumbnail = view.apply(page);
+                let pictures = Arc::clone(&pictures);
+                let cancelled = Arc::clone(&cancelled);
+                glib::MainContext::default().invoke(move || {
+                    fragile::stack_token!(tok);
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if let Some((strip_picture, grid_picture)) = pictures.get(tok).get(i) {
+                        let texture = image::texture_from_image(thumbnail);
+                        strip_picture.set_paintable(Some(&texture));
+                        grid_picture.set_paintable(Some(&texture));
+                    }
+                });
+            }
+        });
+    }
+
+    /// Highlight `page` and scroll the filmstrip to it
+    pub fn set_current(&self, page: usize) {
+        let pages = self.pages();
+        for (i, (strip_button, grid_button)) in self.buttons.borrow().iter().enumerate() {
+            let is_current = pages.start + i == page;
+            for button in [strip_button, grid_button] {
+                if is_current {
+                    button.add_css_class("page-thumbnail-current");
+                } else {
+                    button.remove_css_class("page-thumbnail-current");
+                }
+            }
+        }
+
+        let buttons = self.buttons.borrow();
+        let Some((strip_button, _)) = page.checked_sub(pages.start).and_then(|i| buttons.get(i))
+        else {
+            return;
+        };
+        // Not allocated yet if the strip was never shown
+        if let Some(bounds) = strip_button.compute_bounds(&self.strip_box) {
+            let adjustment = self.strip_scroll.hadjustment();
+            let center = bounds.x() as f64 + bounds.width() as f64 / 2.0;
+            adjustment.set_value(center - adjustment.page_size() / 2.0);
+        }
+    }
+}
+
+impl Drop for PageThumbnails {
+    fn drop(&mut self) {
+        self.cancelled.borrow().store(true, Ordering::Relaxed);
+    }
+}
+
+/// Button with an empty picture of a page, and a label under it if `label` is given
+fn page_button(
+    height: i32,
+    label: Option<&str>,
+    on_click: impl Fn(usize) + 'static,
+    page: usize,
+) -> (Button, Picture) {
+    let picture = Picture::new();
+    picture.set_can_shrink(true);
+    picture.set_content_fit(gtk::ContentFit::Contain);
+    picture.set_size_request((height as f64 * THUMBNAIL_ASPECT_RATIO) as i32, height);
+
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    content.append(&picture);
+    if let Some(label) = label {
+        content.append(&gtk::Label::new(Some(label)));
+    }
+
+    let button = Button::new();
+    button.set_child(Some(&content));
+    button.add_css_class("page-thumbnail");
+    button.set_cursor(gtk::gdk::Cursor::from_name("pointer", None).as_ref());
+    button.connect_clicked(move |_| on_click(page));
+    (button, picture)
+}
@@ -0,0 +1,99 @@
+use crate::core::error::SourceError;
+use gtk::prelude::*;
+use gtk::{Button, CheckButton, Label, PasswordEntry, Window, glib};
+use gtk4 as gtk;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Ask for the password of an encrypted PDF.\
+/// `unlock` is called with the password and whether to remember it, and the dialog stays open while it fails.
+/// `on_close` is called once the dialog closes, whether the PDF was unlocked or not.
+pub fn ask_pdf_password(
+    parent: &impl IsA<Window>,
+    path: &Path,
+    unlock: impl Fn(&str, bool) -> Result<(), SourceError> + 'static,
+    on_close: impl FnOnce() + 'static,
+) {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let title = Label::new(Some(&format!(
+        "“{}” is protected with a password",
+        file_name
+    )));
+    title.set_wrap(true);
+    title.add_css_class("password-dialog-title");
+    let entry = PasswordEntry::new();
+    entry.set_show_peek_icon(true);
+    let remember = CheckButton::with_label("Remember the password");
+    let error_label = Label::new(None);
+    error_label.add_css_class("password-dialog-error");
+    error_label.set_visible(false);
+
+    let cancel_button = Button::with_label("Cancel");
+    let unlock_button = Button::with_label("Unlock");
+    let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    buttons.set_halign(gtk::Align::End);
+    buttons.append(&cancel_button);
+    buttons.append(&unlock_button);
+
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    container.add_css_class("password-dialog");
+    container.append(&title);
+    container.append(&entry);
+    container.append(&remember);
+    container.append(&error_label);
+    container.append(&buttons);
+
+    let window = Window::builder()
+        .title("Password needed")
+        .modal(true)
+        .transient_for(parent)
+        .default_width(400)
+        .resizable(false)
+        .child(&container)
+        .build();
+
+    let on_close = RefCell::new(Some(on_close));
+    window.connect_close_request(move |_| {
+        if let Some(on_close) = on_close.take() {
+            on_close();
+        }
+        glib::Propagation::Proceed
+    });
+    cancel_button.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        move |_| window.close()
+    ));
+
+    let submit = Rc::new(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        entry,
+        #[weak]
+        remember,
+        #[weak]
+        error_label,
+        move || match unlock(&entry.text(), remember.is_active()) {
+            Ok(()) => window.close(),
+            Err(e) => {
+                error_label.set_text(e.reason());
+                error_label.set_visible(true);
+                entry.grab_focus();
+            }
+        }
+    ));
+    unlock_button.connect_clicked({
+        let submit = Rc::clone(&submit);
+        move |_| submit()
+    });
+    entry.connect_activate(move |_| submit());
+
+    window.present();
+    entry.grab_focus();
+}
@@ -0,0 +1,175 @@
+use gtk::prelude::*;
+use gtk::{Label, Revealer, Scale, glib};
+use gtk4 as gtk;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// The HUD hides after the pointer stops moving for this long
+const HIDE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Pages are only rendered once the slider stops for this long, instead of every page it passes over
+const SEEK_DELAY: Duration = Duration::from_millis(150);
+
+/// What the HUD shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct HudState {
+    pub chapter_title: String,
+    /// 0-based, counting the pages of every source
+    pub page: usize,
+    pub page_count: usize,
+    /// Label of the page in the PDF, e.g. "iv", shown before the page number
+    pub page_label: Option<String>,
+    /// e.g. "5 min left"
+    pub time_left: String,
+}
+
+/// Chapter title, page number, progress slider and time left, shown over the bottom of the reader
+#[derive(Debug, Clone)]
+pub struct ReaderHud {
+    revealer: Revealer,
+    title: Label,
+    page_label: Label,
+    time_label: Label,
+    scale: Scale,
+    hide_timeout: Rc<RefCell<Option<glib::SourceId>>>,
+    seek_timeout: Rc<RefCell<Option<glib::SourceId>>>,
+}
+impl Default for ReaderHud {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReaderHud {
+    pub fn new() -> Self {
+        let title = Label::new(None);
+        title.set_halign(gtk::Align::Start);
+        title.set_hexpand(true);
+        title.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        title.add_css_class("reader-hud-title");
+        let time_label = Label::new(None);
+        time_label.add_css_class("reader-hud-dim");
+        let page_label = Label::new(None);
+        page_label.add_css_class("reader-hud-page");
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        header.append(&title);
+        header.append(&time_label);
+        header.append(&page_label);
+
+        let scale = Scale::with_range(gtk::Orientation::Horizontal, 1.0, 2.0, 1.0);
+        scale.set_draw_value(false);
+        scale.set_hexpand(true);
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        container.add_css_class("reader-hud");
+        container.append(&header);
+        container.append(&scale);
+
+        let revealer = Revealer::new();
+        revealer.set_transition_type(gtk::RevealerTransitionType::Crossfade);
+        revealer.set_valign(gtk::Align::End);
+        revealer.set_child(Some(&container));
+
+        Self {
+            revealer,
+            title,
+            page_label,
+            time_label,
+            scale,
+            hide_timeout: Rc::new(RefCell::new(None)),
+            seek_timeout: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    pub fn widget(&self) -> &Revealer {
+        &self.revealer
+    }
+
+    /// Call `on_seek` with the 0-based page the slider was dragged to
+    pub fn connect_seek(&self, on_seek: impl Fn(usize) + 'static) {
+        let on_seek = Rc::new(on_seek);
+        let page_label = self.page_label.downgrade();
+        let revealer = self.revealer.downgrade();
+        let hide_timeout = Rc::clone(&self.hide_timeout);
+        let seek_timeout = Rc::clone(&self.seek_timeout);
+        // `change-value` is only emitted by the user, unlike `value-changed`
+        self.scale.connect_change_value(move |scale, _, value| {
+            let adjustment = scale.adjustment();
+            let value = value.clamp(adjustment.lower(), adjustment.upper()).round();
+            if let Some(page_label) = page_label.upgrade() {
+                page_label.set_text(&page_text(value as usize - 1, adjustment.upper() as usize));
+            }
+            if let Some(revealer) = revealer.upgrade() {
+                show_for_a_while(&revealer, &hide_timeout);
+            }
+
+            if let Some(timeout) = seek_timeout.borrow_mut().take() {
+                timeout.remove();
+            }
+            let on_seek = Rc::clone(&on_seek);
+            let timeout = glib::timeout_add_local_once(SEEK_DELAY, {
+                let seek_timeout = Rc::clone(&seek_timeout);
+                move || {
+                    seek_timeout.borrow_mut().take();
+                    on_seek(value as usize - 1);
+                }
+            });
+            seek_timeout.replace(Some(timeout));
+            glib::Propagation::Proceed
+        });
+    }
+
+    pub fn update(&self, state: &HudState) {
+        self.title.set_text(&state.chapter_title);
+        let page_text = page_text(state.page, state.page_count);
+        match &state.page_label {
+            Some(label) => self
+                .page_label
+                .set_text(&format!("{} · {}", label, page_text)),
+            None => self.page_label.set_text(&page_text),
+        }
+        self.time_label.set_text(&state.time_left);
+        // A range of 1..1 can't be dragged, and looks broken
+        let upper = state.page_count.max(2) as f64;
+        self.scale.set_range(1.0, upper);
+        self.scale.set_sensitive(state.page_count > 1);
+        if self.seek_timeout.borrow().is_none() {
+            self.scale.set_value(state.page as f64 + 1.0);
+        }
+    }
+
+    /// Show the HUD and hide it again after `HIDE_TIMEOUT`
+    pub fn show(&self) {
+        show_for_a_while(&self.revealer, &self.hide_timeout);
+    }
+
+    pub fn hide(&self) {
+        if let Some(timeout) = self.hide_timeout.borrow_mut().take() {
+            timeout.remove();
+        }
+        self.revealer.set_reveal_child(false);
+    }
+}
+
+fn show_for_a_while(revealer: &Revealer, hide_timeout: &Rc<RefCell<Option<glib::SourceId>>>) {
+    revealer.set_reveal_child(true);
+    if let Some(timeout) = hide_timeout.borrow_mut().take() {
+        timeout.remove();
+    }
+    let timeout = glib::timeout_add_local_once(HIDE_TIMEOUT, {
+        let revealer = revealer.downgrade();
+        let hide_timeout = Rc::clone(hide_timeout);
+        move || {
+            hide_timeout.borrow_mut().take();
+            if let Some(revealer) = revealer.upgrade() {
+                revealer.set_reveal_child(false);
+            }
+        }
+    });
+    hide_timeout.replace(Some(timeout));
+}
+
+fn page_text(page: usize, page_count: usize) -> String {
+    format!("{} / {}", page + 1, page_count)
+}
@@ -0,0 +1,137 @@
+use gtk::prelude::*;
+use gtk::{Label, ListBox, ListBoxRow, Revealer, ScrolledWindow};
+use gtk4 as gtk;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Indentation of each level of the outline, in pixels
+const LEVEL_INDENT: i32 = 15;
+const PANEL_WIDTH: i32 = 260;
+
+/// An entry of the table of contents
+#[derive(Debug, Clone, PartialEq)]
+pub struct TocEntry {
+    pub title: String,
+    /// 0 for top-level entries
+    pub level: usize,
+    /// Index of the page in the reader, counting the pages of every source
+    pub page: usize,
+    /// Page label from the PDF, e.g. "iv"
+    pub label: Option<String>,
+}
+
+/// Outline of the PDF being read, in a panel at the side of the reader
+#[derive(Debug)]
+pub struct TableOfContentsPanel {
+    revealer: Revealer,
+    list: ListBox,
+    entries: Rc<RefCell<Vec<TocEntry>>>,
+    /// File whose outline is shown
+    path: RefCell<Option<PathBuf>>,
+}
+impl Default for TableOfContentsPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TableOfContentsPanel {
+    pub fn new() -> Self {
+        let list = ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::Single);
+        list.add_css_class("toc-list");
+        let placeholder = Label::new(Some("No table of contents"));
+        placeholder.add_css_class("reader-hud-dim");
+        list.set_placeholder(Some(&placeholder));
+
+        let scrolled_window = ScrolledWindow::new();
+        scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled_window.set_size_request(PANEL_WIDTH, -1);
+        scrolled_window.set_vexpand(true);
+        scrolled_window.add_css_class("toc-panel");
+        scrolled_window.set_child(Some(&list));
+
+        let revealer = Revealer::new();
+        revealer.set_transition_type(gtk::RevealerTransitionType::SlideRight);
+        revealer.set_child(Some(&scrolled_window));
+
+        Self {
+            revealer,
+            list,
+            entries: Rc::new(RefCell::new(Vec::new())),
+            path: RefCell::new(None),
+        }
+    }
+
+    /// The panel, hidden until `toggle`
+    pub fn widget(&self) -> &Revealer {
+        &self.revealer
+    }
+
+    pub fn toggle(&self) {
+        self.revealer
+            .set_reveal_child(!self.revealer.reveals_child());
+    }
+
+    /// Call `on_activate` with the page of the entry that was clicked
+    pub fn connect_activate(&self, on_activate: impl Fn(usize) + 'static) {
+        let entries = Rc::clone(&self.entries);
+        self.list.connect_row_activated(move |_, row| {
+            let page = usize::try_from(row.index())
+                .ok()
+                .and_then(|i| Some(entries.borrow().get(i)?.page));
+            if let Some(page) = page {
+                on_activate(page);
+            }
+        });
+    }
+
+    /// File whose outline is shown, if any
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.borrow().clone()
+    }
+
+    /// Show the outline of the file at `path`
+    pub fn load(&self, path: &Path, entries: Vec<TocEntry>) {
+        self.list.remove_all();
+        for entry in &entries {
+            let title = Label::new(Some(&entry.title));
+            title.set_halign(gtk::Align::Start);
+            title.set_hexpand(true);
+            title.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            title.set_tooltip_text(Some(&entry.title));
+            let page = Label::new(Some(
+                &entry
+                    .label
+                    .clone()
+                    .unwrap_or_else(|| (entry.page + 1).to_string()),
+            ));
+            page.add_css_class("reader-hud-dim");
+
+            let content = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+            content.set_margin_start(entry.level as i32 * LEVEL_INDENT);
+            content.append(&title);
+            content.append(&page);
+            let row = ListBoxRow::new();
+            row.add_css_class("toc-entry");
+            row.set_child(Some(&content));
+            self.list.append(&row);
+        }
+        self.entries.replace(entries);
+        self.path.replace(Some(path.to_path_buf()));
+    }
+
+    /// Highlight the entry `page` belongs to, which is the last one starting at or before it
+    pub fn set_current(&self, page: usize) {
+        let current = self
+            .entries
+            .borrow()
+            .iter()
+            .rposition(|entry| entry.page <= page);
+        match current.and_then(|i| self.list.row_at_index(i as i32)) {
+            Some(row) => self.list.select_row(Some(&row)),
+            None => self.list.unselect_all(),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::app::FiapoController;
+use crate::core::logging;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{Button, Label, TextView, glib};
+use gtk4 as gtk;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Name of the diagnostics screen in the view stack
+pub const DIAGNOSTICS_SCREEN: &str = "diagnostics_screen";
+
+/// Recent logs, and a button to copy everything a bug report needs
+#[derive(Debug)]
+pub struct Diagnostics {
+    controller: Rc<RefCell<FiapoController>>,
+}
+impl Diagnostics {
+    pub fn new(controller: Rc<RefCell<FiapoController>>) -> Self {
+        Self { controller }
+    }
+
+    pub fn build(&self) -> gtk::Box {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 10);
+        container.add_css_class("settings");
+
+        let title = Label::new(Some("Diagnostics"));
+        title.set_hexpand(true);
+        title.set_halign(gtk::Align::Start);
+        title.add_css_class("settings-title");
+        let refresh_button = Button::with_label("Refresh");
+        let copy_button = Button::with_label("Copy bug report");
+        let back_button = Button::with_label("<- Back");
+        back_button.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            self.controller,
+            move |_| FiapoController::open_settings(Rc::clone(&controller))
+        ));
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        header.set_margin_top(10);
+        header.set_margin_start(10);
+        header.set_margin_end(10);
+        header.append(&title);
+        header.append(&refresh_button);
+        header.append(&copy_button);
+        header.append(&back_button);
+        container.append(&header);
+
+        let paths = self.controller.borrow().paths.clone();
+        let info = Label::new(Some(&format!(
+            "Fiapo {} · {} · Logs are saved to {}",
+            env!("CARGO_PKG_VERSION"),
+            toolkit_version(),
+            paths.log_file().display()
+        )));
+        info.set_halign(gtk::Align::Start);
+        info.set_wrap(true);
+        info.set_selectable(true);
+        info.set_margin_start(10);
+        info.set_margin_end(10);
+        info.add_css_class("settings-hint");
+        container.append(&info);
+
+        let log_view = TextView::new();
+        log_view.set_editable(false);
+        log_view.set_cursor_visible(false);
+        log_view.set_monospace(true);
+        log_view.set_wrap_mode(gtk::WrapMode::WordChar);
+        log_view.add_css_class("diagnostics-logs");
+
+        let scroll = gtk::ScrolledWindow::new();
+        scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scroll.set_vexpand(true);
+        scroll.set_margin_start(10);
+        scroll.set_margin_end(10);
+        scroll.set_margin_bottom(10);
+        scroll.set_child(Some(&log_view));
+        container.append(&scroll);
+
+        Diagnostics::show_logs(&log_view);
+        refresh_button.connect_clicked(clone!(
+            #[weak]
+            log_view,
+            move |_| Diagnostics::show_logs(&log_view)
+        ));
+        copy_button.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            self.controller,
+            move |button| {
+                let ctrl = controller.borrow();
+                let report = logging::bug_report(
+                    &ctrl.config,
+                    &ctrl.paths,
+                    &toolkit_version(),
+                    &logging::recent_lines(),
+                );
+                button.clipboard().set_text(&report);
+                ctrl.notification.show(
+                    "Bug report copied",
+                    "Passwords and tokens were left out. Paste it in the issue.",
+                );
+            }
+        ));
+
+        container
+    }
+
+    /// Fill the view with the recent logs and scroll to the newest one
+    fn show_logs(log_view: &TextView) {
+        let buffer = log_view.buffer();
+        buffer.set_text(&logging::recent_lines().join("\n"));
+        // Unlike iters, marks are scrolled to once the text is laid out
+        let end = buffer.create_mark(None, &buffer.end_iter(), false);
+        log_view.scroll_to_mark(&end, 0.0, false, 0.0, 0.0);
+    }
+}
+
+fn toolkit_version() -> String {
+    format!(
+        "GTK {}.{}.{}",
+        gtk::major_version(),
+        gtk::minor_version(),
+        gtk::micro_version()
+    )
+}
@@ -1,27 +1,90 @@
 use crate::app::FiapoController;
-use crate::core::reader::{Source, SourceType};
+use crate::core::export::{self, ExportFormat};
+use crate::core::keybindings::Action;
+use crate::core::reader::Source;
 use crate::server;
-use crate::ui::components::card::{Card, MangadexSearchDataObject};
+use crate::server::database::SourceRecord;
+use crate::ui::components::card::{Card, LibrarySourceObject, MangadexSearchDataObject};
 use glib::MainContext;
 use glib::clone;
 use gtk::prelude::{
-    BoxExt, ButtonExt, EditableExt, FileExt, ListItemExt, ListModelExtManual, WidgetExt,
+    BoxExt, ButtonExt, EditableExt, FileExt, GestureSingleExt, ListItemExt, ListModelExt,
+    ListModelExtManual, PopoverExt, WidgetExt,
 };
-use gtk::{Button, Label, SearchEntry, gio, glib};
+use gtk::{Button, Label, SearchEntry, gdk, gio, glib};
 use gtk4 as gtk;
-use gtk4::glib::object::{Cast, CastNone};
-use log::{debug, warn};
+use gtk4::glib::object::{Cast, CastNone, ObjectExt};
+use log::warn;
 use std::cell::RefCell;
-use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
+use std::thread;
 
-#[derive(Debug, Default)]
+/// Name of the home screen in the view stack
+pub const HOME_SCREEN: &str = "home_screen";
+
+/// Progress of background tasks, like exports, shown at the bottom of the home screen
+#[derive(Debug, Default, Clone)]
+struct TaskProgress {
+    container: gtk::Box,
+    bar: gtk::ProgressBar,
+    label: Label,
+}
+impl TaskProgress {
+    fn new() -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        container.set_margin_start(10);
+        container.set_margin_end(10);
+        container.set_margin_bottom(10);
+        container.set_visible(false);
+
+        let label = Label::new(None);
+        let bar = gtk::ProgressBar::new();
+        bar.set_hexpand(true);
+        bar.set_valign(gtk::Align::Center);
+        container.append(&label);
+        container.append(&bar);
+
+        Self {
+            container,
+            bar,
+            label,
+        }
+    }
+
+    fn start(&self, text: &str) {
+        self.label.set_text(text);
+        self.bar.set_fraction(0.0);
+        self.container.set_visible(true);
+    }
+
+    fn update(&self, done: usize, total: usize) {
+        self.bar.set_fraction(done as f64 / total.max(1) as f64);
+    }
+
+    /// Show `text` for a few seconds and then hide
+    fn finish(&self, text: &str) {
+        self.label.set_text(text);
+        self.bar.set_fraction(1.0);
+        glib::timeout_add_local_once(
+            std::time::Duration::from_secs(3),
+            clone!(
+                #[weak(rename_to = container)]
+                self.container,
+                move || container.set_visible(false)
+            ),
+        );
+    }
+}
+
+#[derive(Debug)]
 pub struct Home {
     controller: Rc<RefCell<FiapoController>>,
     container: gtk::Box,
     is_searching: Arc<AtomicBool>,
+    progress: TaskProgress,
 }
 impl Home {
     pub fn new(controller: Rc<RefCell<FiapoController>>) -> Self {
@@ -32,6 +95,7 @@ impl Home {
             controller: controller,
             container: container,
             is_searching,
+            progress: TaskProgress::new(),
         }
     }
 
@@ -52,57 +116,13 @@ impl Home {
                             ctrl.get_window()
                         };
 
-                        let mut total_page_count: usize = 0;
-
                         match Home::open_file_dialog(&window).await {
                             Ok(files) => {
-                                let mut files_vec: Vec<Source> = Vec::new();
-
-                                for (i, file) in files.iter::<gio::File>().enumerate() {
-                                    let file = file.unwrap();
-
-                                    let path = file.path().unwrap();
-                                    let source_type: SourceType = match path.is_file() {
-                                        true => match path.extension().and_then(OsStr::to_str) {
-                                            Some("pdf") => SourceType::Pdf,
-                                            Some("png") => SourceType::ImageSequence,
-                                            Some("jpg") => SourceType::ImageSequence,
-                                            _ => SourceType::Pdf,
-                                        },
-                                        _ => SourceType::Directory,
-                                    };
-
-                                    let str_path = path.clone().to_str().unwrap().to_string();
-
-                                    // Ignore directories for now
-                                    if source_type == SourceType::Directory {
-                                        warn!("Skipping directory: {}", str_path);
-                                        continue;
-                                    }
-
-                                    let should_keep_pdf_object: bool = i == 0;
-                                    let source =
-                                        Source::new(source_type, path, should_keep_pdf_object);
-                                    let page_count = source.get_page_count();
-
-                                    // Ignore empty PDFs
-                                    if page_count <= 0 {
-                                        warn!("Skipping empty file: {}", str_path);
-                                        continue;
-                                    }
-
-                                    total_page_count += page_count;
-                                    files_vec.push(source);
-                                }
-
-                                files_vec.sort();
-
-                                controller
-                                    .borrow_mut()
-                                    .server
-                                    .set_sources(files_vec, total_page_count);
-                                debug!("{:?}", controller.borrow());
-                                FiapoController::open_reader(controller);
+                                let paths: Vec<PathBuf> = files
+                                    .iter::<gio::File>()
+                                    .filter_map(|file| file.ok()?.path())
+                                    .collect();
+                                FiapoController::import_and_read(controller, &paths);
                             }
                             Err(e) => warn!("Could not open file: {}", e),
                         }
@@ -114,11 +134,42 @@ impl Home {
         manga_search_bar.set_search_delay(500); // ms
         manga_search_bar.set_hexpand(true);
         manga_search_bar.set_placeholder_text(Some("Search for mangas..."));
+        // Going home from home clears the search and shows the library again
+        self.controller.borrow_mut().actions.register(
+            Some(HOME_SCREEN),
+            Action::GoHome,
+            clone!(
+                #[weak]
+                manga_search_bar,
+                move || manga_search_bar.set_text("")
+            ),
+        );
+
+        let settings_button = Button::with_label("Settings");
+        settings_button.set_hexpand(false);
+        settings_button.set_cursor(gtk::gdk::Cursor::from_name("pointer", None).as_ref());
+        settings_button.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            self.controller,
+            move |_| FiapoController::open_settings(Rc::clone(&controller))
+        ));
+
+        let rescan_button = Button::with_label("Rescan");
+        rescan_button.set_hexpand(false);
+        rescan_button.set_cursor(gtk::gdk::Cursor::from_name("pointer", None).as_ref());
+        rescan_button.set_tooltip_text(Some("Import new files from the library folders"));
+        rescan_button.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            self.controller,
+            move |_| FiapoController::rescan_library(&controller)
+        ));
 
         let headercontainer = gtk::Box::new(gtk::Orientation::Horizontal, 10);
         headercontainer.set_vexpand(false);
         headercontainer.append(&manga_search_bar);
         headercontainer.append(&open_button);
+        headercontainer.append(&rescan_button);
+        headercontainer.append(&settings_button);
         headercontainer.set_margin_top(10);
         headercontainer.set_margin_end(10);
         headercontainer.set_margin_start(10);
@@ -131,10 +182,38 @@ impl Home {
         scroll.set_hexpand(true);
 
         self.container.append(&scroll);
+        self.container.append(&self.progress.container);
+
+        Home::show_library(&self.controller, &scroll, &self.progress);
+        // The grid follows the model on its own, but the empty library message has to be swapped with it
+        let library_model = self.controller.borrow().library_model.clone();
+        library_model.connect_items_changed(clone!(
+            #[weak]
+            scroll,
+            #[weak]
+            manga_search_bar,
+            #[strong(rename_to = controller)]
+            self.controller,
+            #[strong(rename_to = progress)]
+            self.progress,
+            move |model, _, _, _| {
+                // Labels are wrapped in a viewport, grids are scrolled directly
+                let shows_grid = scroll
+                    .child()
+                    .is_some_and(|child| child.is::<gtk::GridView>());
+                if manga_search_bar.text().is_empty() && shows_grid == (model.n_items() == 0) {
+                    Home::show_library(&controller, &scroll, &progress);
+                }
+            }
+        ));
 
         manga_search_bar.connect_search_changed(clone!(
             #[strong(rename_to = is_searching)]
             self.is_searching,
+            #[strong(rename_to = controller)]
+            self.controller,
+            #[strong(rename_to = progress)]
+            self.progress,
             move |entry| {
                 let search_text = entry.text().to_string();
 
@@ -201,6 +280,8 @@ impl Home {
                             is_searching.store(false, std::sync::atomic::Ordering::Relaxed);
                         }
                     ));
+                } else {
+                    Home::show_library(&controller, &scroll, &progress);
                 }
             }
         ));
@@ -213,9 +294,11 @@ impl Home {
     ) -> Result<gio::ListModel, glib::Error> {
         let filters = gio::ListStore::new::<gtk::FileFilter>();
         let file_filter = gtk::FileFilter::new();
-        file_filter.set_name(Some("Images and PDF files"));
+        file_filter.set_name(Some("Images, PDF and CBZ files"));
         file_filter.add_mime_type("image/*");
         file_filter.add_mime_type("application/pdf");
+        file_filter.add_mime_type("application/vnd.comicbook+zip");
+        file_filter.add_suffix("cbz");
         filters.append(&file_filter);
 
         let file_dialog = gtk::FileDialog::builder()
@@ -228,6 +311,266 @@ impl Home {
         file_dialog.open_multiple_future(Some(window)).await
     }
 
+    /// Show the sources in the library.\
+    /// Double clicking a card opens it in the reader, right clicking shows the context menu.
+    fn show_library(
+        controller: &Rc<RefCell<FiapoController>>,
+        scroll: &gtk::ScrolledWindow,
+        progress: &TaskProgress,
+    ) {
+        let model = controller.borrow().library_model.clone();
+        if model.n_items() == 0 {
+            let empty_label = Label::new(Some("Your library is empty. Import some files!"));
+            scroll.set_child(Some(&empty_label));
+            return;
+        }
+
+        let factory = gtk::SignalListItemFactory::new();
+        factory.connect_setup(clone!(
+            #[strong]
+            controller,
+            #[strong]
+            progress,
+            move |_, list_item| {
+                Home::create_library_card(list_item, &controller, &progress);
+            }
+        ));
+        let thumbnails_dir = controller.borrow().paths.thumbnails_dir();
+        factory.connect_bind(move |_, list_item| {
+            Home::update_library_card(list_item, &thumbnails_dir);
+        });
+
+        let grid_view = gtk::GridView::builder()
+            .model(&gtk::NoSelection::new(Some(model)))
+            .factory(&factory)
+            .min_columns(3)
+            .build();
+        grid_view.add_css_class("search-grid-view");
+
+        scroll.set_child(Some(&grid_view));
+    }
+
+    fn create_library_card(
+        list_item: &glib::Object,
+        controller: &Rc<RefCell<FiapoController>>,
+        progress: &TaskProgress,
+    ) {
+        let list_item = list_item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Could not downcast ListItem");
+        let card = Card::new();
+
+        // The controller is added here instead of on bind, so recycled cards don't pile them up
+        let click_controller = gtk::GestureClick::builder()
+            .button(0) // All buttons
+            .build();
+        click_controller.connect_pressed(clone!(
+            #[weak]
+            list_item,
+            #[weak]
+            card,
+            #[strong]
+            controller,
+            #[strong]
+            progress,
+            move |gesture, press_count, x, y| {
+                let Some(record) = list_item
+                    .item()
+                    .and_downcast::<LibrarySourceObject>()
+                    .map(|object| object.data())
+                else {
+                    return;
+                };
+                match (gesture.current_button(), press_count) {
+                    // Left button, double click
+                    (1, 2) => {
+                        FiapoController::open_library_source(Rc::clone(&controller), record.id)
+                    }
+                    // Right button, single click
+                    (3, 1) => {
+                        Home::show_library_context_menu(&card, x, y, record, &controller, &progress)
+                    }
+                    _ => (),
+                }
+            }
+        ));
+        card.add_controller(click_controller);
+        list_item.set_child(Some(&card));
+    }
+
+    fn update_library_card(list_item: &glib::Object, thumbnails_dir: &Path) {
+        let list_item = list_item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Could not downcast ListItem");
+        let record = list_item
+            .item()
+            .and_downcast::<LibrarySourceObject>()
+            .expect("Could not downcast LibrarySourceObject")
+            .data();
+        let card = list_item
+            .child()
+            .and_downcast::<Card>()
+            .expect("Could not downcast Card");
+        card.update_from_library(&record, thumbnails_dir);
+    }
+
+    fn show_library_context_menu(
+        card: &Card,
+        x: f64,
+        y: f64,
+        record: SourceRecord,
+        controller: &Rc<RefCell<FiapoController>>,
+        progress: &TaskProgress,
+    ) {
+        let menu_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        let popover = gtk::Popover::new();
+        popover.set_child(Some(&menu_box));
+        popover.set_parent(card);
+        popover.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+
+        for (label, format) in [
+            ("Export as CBZ", ExportFormat::Cbz),
+            ("Export as PDF", ExportFormat::Pdf),
+            ("Export as image folder", ExportFormat::ImageFolder),
+        ] {
+            let button = Button::with_label(label);
+            button.connect_clicked(clone!(
+                #[weak]
+                popover,
+                #[strong]
+                controller,
+                #[strong]
+                progress,
+                #[strong]
+                record,
+                move |_| {
+                    popover.popdown();
+                    Home::export_library_source(
+                        Rc::clone(&controller),
+                        record.clone(),
+                        format,
+                        progress.clone(),
+                    );
+                }
+            ));
+            menu_box.append(&button);
+        }
+
+        // Popovers must be unparented once they're gone
+        popover.connect_closed(|popover| {
+            glib::idle_add_local_once(clone!(
+                #[weak]
+                popover,
+                move || popover.unparent()
+            ));
+        });
+        popover.popup();
+    }
+
+    fn export_library_source(
+        controller: Rc<RefCell<FiapoController>>,
+        record: SourceRecord,
+        format: ExportFormat,
+        progress: TaskProgress,
+    ) {
+        MainContext::default().spawn_local(async move {
+            let window = controller.borrow().get_window();
+            let Some(output) = Home::choose_export_path(&window, &record.title, format).await
+            else {
+                return;
+            };
+
+            let chapters = match &controller.borrow().library {
+                Some(library) => library.list_chapters(record.id),
+                None => Err(anyhow::anyhow!("Library is unavailable")),
+            };
+            let mut paths: Vec<PathBuf> = match chapters {
+                Ok(chapters) => chapters.into_iter().map(|chapter| chapter.path).collect(),
+                Err(e) => {
+                    warn!("Could not export `{}`: {}", record.title, e);
+                    return;
+                }
+            };
+            // Chapters of a PDF volume share its file, which is exported once
+            paths.dedup();
+            let options = controller.borrow().config.export.options(format);
+
+            progress.start(&format!("Exporting {}...", record.title));
+            let progress = Arc::new(fragile::Sticky::new(progress));
+            thread::spawn(move || {
+                let sources = paths
+                    .into_iter()
+                    .map(|path| Source::open(Source::source_type_for_path(&path)?, path, false))
+                    .collect::<Result<Vec<Source>, _>>();
+                let result = sources
+                    .map_err(anyhow::Error::from)
+                    .and_then(|mut sources| {
+                        export::export_sources(&mut sources, &options, &output, {
+                            let progress = Arc::clone(&progress);
+                            move |done, total| {
+                                let progress = Arc::clone(&progress);
+                                glib::MainContext::default().invoke(move || {
+                                    fragile::stack_token!(tok);
+                                    progress.get(tok).update(done, total);
+                                });
+                            }
+                        })
+                    });
+
+                let message = match result {
+                    Ok(()) => format!("Exported {}", record.title),
+                    Err(e) => {
+                        warn!("Failed to export `{}`: {}", record.title, e);
+                        format!("Failed to export {}: {}", record.title, e)
+                    }
+                };
+                glib::MainContext::default().invoke(move || {
+                    fragile::stack_token!(tok);
+                    progress.get(tok).finish(&message);
+                });
+            });
+        });
+    }
+
+    /// Ask where to export. Image folders are created inside the chosen directory
+    async fn choose_export_path(
+        window: &gtk::ApplicationWindow,
+        title: &str,
+        format: ExportFormat,
+    ) -> Option<PathBuf> {
+        let file_dialog = gtk::FileDialog::builder()
+            .title("Export")
+            .accept_label("Export")
+            .modal(true)
+            .build();
+
+        let result = match format {
+            ExportFormat::ImageFolder => file_dialog
+                .select_folder_future(Some(window))
+                .await
+                .map(|dir| dir.path().map(|path| path.join(title))),
+            ExportFormat::Cbz | ExportFormat::Pdf => {
+                let extension = if format == ExportFormat::Pdf {
+                    "pdf"
+                } else {
+                    "cbz"
+                };
+                file_dialog.set_initial_name(Some(&format!("{}.{}", title, extension)));
+                file_dialog
+                    .save_future(Some(window))
+                    .await
+                    .map(|file| file.path())
+            }
+        };
+        match result {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("Could not choose where to export: {}", e);
+                None
+            }
+        }
+    }
+
     fn create_cards_for_grid_view(list_item: &glib::Object) {
         let list_item = list_item
             .downcast_ref::<gtk::ListItem>()
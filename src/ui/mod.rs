@@ -1,3 +1,6 @@
+pub mod actions;
 pub mod components;
+pub mod diagnostics;
 pub mod home;
 pub mod reader;
+pub mod settings;
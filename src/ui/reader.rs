@@ -1,33 +1,106 @@
 use crate::app::FiapoController;
+use crate::core::crop::CropBox;
 use crate::core::image;
+use crate::core::keybindings::Action;
+use crate::core::layout::Rotation;
+use crate::core::pace::{self, ReadingPace};
+use crate::core::reader::{Server, Source, SourceType, Volume};
+use crate::ui::components::chapter_transition::ChapterTransitionView;
+use crate::ui::components::crop_dialog;
+use crate::ui::components::page_error::PageErrorView;
+use crate::ui::components::page_thumbnails::PageThumbnails;
+use crate::ui::components::reader_hud::{HudState, ReaderHud};
+use crate::ui::components::table_of_contents::{TableOfContentsPanel, TocEntry};
 use glib::clone;
-use gtk::gdk::Key;
-use gtk::prelude::{ButtonExt, OrientableExt, WidgetExt};
-use gtk::{CenterBox, Picture, glib};
+use gtk::prelude::{BoxExt, ButtonExt, OrientableExt, WidgetExt};
+use gtk::{CenterBox, Picture, ScrolledWindow, Stack, glib};
 use gtk4 as gtk;
+use log::{info, warn};
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::time::Instant;
 
-#[derive(Debug, Default)]
+type ReaderHandler = fn(&mut Reader);
+
+/// Name of the reader in the view stack
+pub const READER_SCREEN: &str = "reader_screen";
+
+const MIN_ZOOM: f64 = 1.0;
+const MAX_ZOOM: f64 = 5.0;
+const ZOOM_STEP: f64 = 0.25;
+
+/// Names of the children of the page stack
+const PAGE_VIEW: &str = "page";
+const PAGE_GRID_VIEW: &str = "page_grid";
+const TRANSITION_VIEW: &str = "transition";
+const PAGE_ERROR_VIEW: &str = "page_error";
+
+#[derive(Debug)]
 pub struct Reader {
     controller: Rc<RefCell<FiapoController>>,
     container: CenterBox,
     picture: Picture,
+    scrolled_window: ScrolledWindow,
+    /// Switches between the page, the grid of every page of the chapter, the chapter transition and page errors
+    page_stack: Stack,
+    transition: ChapterTransitionView,
+    page_error: PageErrorView,
+    /// Filmstrip and page grid of the current chapter
+    thumbnails: PageThumbnails,
+    /// Outline of the current PDF, at the side of the page
+    table_of_contents: TableOfContentsPanel,
+    /// Page, chapter and time left, shown over the page. See `ReaderConfig::show_bottom_indicator`
+    hud: ReaderHud,
+    pace: ReadingPace,
+    /// When the current page was shown, to measure the reading pace
+    page_shown_at: Instant,
+    /// 1.0 fits the page to the window
+    zoom: f64,
+    /// Set by `build`, for the handlers of the thumbnails
+    weak_self: Weak<RefCell<Reader>>,
 }
 impl Reader {
     pub fn new(controller: Rc<RefCell<FiapoController>>) -> Self {
         let container = CenterBox::new();
         container.set_orientation(gtk::Orientation::Vertical);
         let picture = Picture::new();
+        let scrolled_window = ScrolledWindow::new();
+        scrolled_window.set_hexpand(true);
+        scrolled_window.set_vexpand(true);
+        scrolled_window.set_child(Some(&picture));
+
+        let thumbnails = PageThumbnails::new();
+        let page_stack = Stack::new();
+        page_stack.set_transition_type(gtk::StackTransitionType::Crossfade);
+        page_stack.add_named(&scrolled_window, Some(PAGE_VIEW));
+        page_stack.add_named(thumbnails.grid_widget(), Some(PAGE_GRID_VIEW));
+        let transition = ChapterTransitionView::new();
+        page_stack.add_named(transition.widget(), Some(TRANSITION_VIEW));
+        let page_error = PageErrorView::new();
+        page_stack.add_named(page_error.widget(), Some(PAGE_ERROR_VIEW));
+        let hud = ReaderHud::new();
 
         Self {
             controller,
             container,
             picture,
+            scrolled_window,
+            page_stack,
+            transition,
+            page_error,
+            thumbnails,
+            table_of_contents: TableOfContentsPanel::new(),
+            hud,
+            pace: ReadingPace::new(),
+            page_shown_at: Instant::now(),
+            zoom: MIN_ZOOM,
+            weak_self: Weak::new(),
         }
     }
 
     pub fn build(reader: Rc<RefCell<Self>>) -> CenterBox {
+        reader.borrow_mut().weak_self = Rc::downgrade(&reader);
         let label = gtk::Label::new(Some("Reader"));
         let btn = gtk::Button::with_label("<- Back");
         btn.connect_clicked(clone!(
@@ -36,58 +109,554 @@ impl Reader {
             move |_| controller.borrow_mut().go_home()
         ));
 
+        let save_metadata_btn = gtk::Button::with_label("Save metadata");
+        save_metadata_btn.set_tooltip_text(Some("Write the library metadata to ComicInfo.xml"));
+        save_metadata_btn.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            reader.borrow().controller,
+            move |_| controller.borrow().write_comic_info()
+        ));
+        let has_archives = reader
+            .borrow()
+            .controller
+            .borrow()
+            .server
+            .get_chapters()
+            .any(|source| *source.get_source_type() == SourceType::Archive);
+        save_metadata_btn.set_visible(has_archives);
+
+        let thumbnails_btn = gtk::Button::with_label("Thumbnails");
+        thumbnails_btn.set_tooltip_text(Some("Show the pages of the chapter at the bottom"));
+        thumbnails_btn.connect_clicked(clone!(
+            #[weak]
+            reader,
+            move |_| {
+                reader.borrow().thumbnails.toggle_strip();
+            }
+        ));
+        let table_of_contents_btn = gtk::Button::with_label("Contents");
+        table_of_contents_btn.set_tooltip_text(Some("Show the table of contents of the PDF"));
+        table_of_contents_btn.connect_clicked(clone!(
+            #[weak]
+            reader,
+            move |_| {
+                reader.borrow().table_of_contents.toggle();
+            }
+        ));
+        let page_grid_btn = gtk::Button::with_label("Pages");
+        page_grid_btn.set_tooltip_text(Some("Show every page of the chapter"));
+        page_grid_btn.connect_clicked(clone!(
+            #[weak]
+            reader,
+            move |_| {
+                reader.borrow().toggle_page_grid();
+            }
+        ));
+
+        let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        buttons.append(&table_of_contents_btn);
+        buttons.append(&thumbnails_btn);
+        buttons.append(&page_grid_btn);
+        buttons.append(&save_metadata_btn);
+        buttons.append(&btn);
+
         let container = CenterBox::new();
         container.set_orientation(gtk::Orientation::Horizontal);
         container.set_start_widget(Some(&label));
-        container.set_end_widget(Some(&btn));
+        container.set_end_widget(Some(&buttons));
+
+        let overlay = gtk::Overlay::new();
+        overlay.set_child(Some(&reader.borrow().page_stack));
+        overlay.add_overlay(reader.borrow().hud.widget());
+        // Moving the pointer over the page brings the HUD back
+        let motion_controller = gtk::EventControllerMotion::new();
+        motion_controller.connect_motion(clone!(
+            #[weak]
+            reader,
+            move |_, _, _| {
+                reader.borrow().show_hud();
+            }
+        ));
+        overlay.add_controller(motion_controller);
+        reader.borrow().hud.connect_seek(clone!(
+            #[weak]
+            reader,
+            move |page| {
+                reader
+                    .borrow_mut()
+                    .navigate(|server| server.go_to_page(page));
+            }
+        ));
+
+        reader.borrow().table_of_contents.connect_activate(clone!(
+            #[weak]
+            reader,
+            move |page| {
+                let mut reader = reader.borrow_mut();
+                reader.page_stack.set_visible_child_name(PAGE_VIEW);
+                reader.navigate(|server| server.go_to_page(page));
+            }
+        ));
+
+        reader.borrow().page_error.connect_retry(clone!(
+            #[weak]
+            reader,
+            move || {
+                reader.borrow().retry_page();
+            }
+        ));
 
-        reader.borrow_mut().next_page();
+        reader.borrow().show_current_page();
+        reader.borrow().show_hud();
 
         reader.borrow().container.set_start_widget(Some(&container));
+        let content = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        content.append(reader.borrow().table_of_contents.widget());
+        content.append(&overlay);
+        overlay.set_hexpand(true);
+        reader.borrow().container.set_center_widget(Some(&content));
         reader
             .borrow()
             .container
-            .set_center_widget(Some(&reader.borrow().picture));
+            .set_end_widget(Some(reader.borrow().thumbnails.strip_widget()));
 
-        let key_handler = gtk::EventControllerKey::new();
-        let window = reader.borrow().controller.borrow().window.clone();
+        Reader::register_actions(&reader);
 
-        key_handler.connect_key_pressed(clone!(
-            #[strong]
-            reader,
-            move |_, key, _, _| {
-                match key {
-                    Key::Left => reader.borrow_mut().next_page(),
-                    Key::Right => reader.borrow_mut().prev_page(),
-                    _ => println!("{key}"),
+        reader.borrow().container.clone()
+    }
+
+    /// Register the handlers of the reader actions. They replace the ones of the previous reader.\
+    /// Nothing else holds the reader, so the handlers keep it alive until they're replaced or `go_home` drops them.
+    fn register_actions(reader: &Rc<RefCell<Self>>) {
+        let handlers: [(Action, ReaderHandler); 20] = [
+            (Action::NextPage, |reader| {
+                reader.navigate(Server::next_page)
+            }),
+            (Action::PrevPage, |reader| {
+                reader.navigate(Server::prev_page)
+            }),
+            (Action::PageLeft, |reader| {
+                if reader.is_right_to_left() {
+                    reader.navigate(Server::next_page)
+                } else {
+                    reader.navigate(Server::prev_page)
+                }
+            }),
+            (Action::PageRight, |reader| {
+                if reader.is_right_to_left() {
+                    reader.navigate(Server::prev_page)
+                } else {
+                    reader.navigate(Server::next_page)
                 }
-                gtk::glib::Propagation::Stop
+            }),
+            (Action::FirstPage, |reader| {
+                reader.navigate(|server| server.go_to_page(0))
+            }),
+            (Action::LastPage, |reader| {
+                reader
+                    .navigate(|server| server.go_to_page(server.get_page_count().saturating_sub(1)))
+            }),
+            (Action::NextChapter, |reader| {
+                reader.navigate(Server::next_source)
+            }),
+            (Action::PrevChapter, |reader| {
+                reader.navigate(Server::prev_source)
+            }),
+            (Action::ZoomIn, |reader| {
+                reader.set_zoom(reader.zoom + ZOOM_STEP)
+            }),
+            (Action::ZoomOut, |reader| {
+                reader.set_zoom(reader.zoom - ZOOM_STEP)
+            }),
+            (Action::ZoomReset, |reader| reader.set_zoom(MIN_ZOOM)),
+            (Action::Bookmark, |reader| reader.toggle_bookmark()),
+            (Action::ToggleThumbnails, |reader| {
+                reader.thumbnails.toggle_strip()
+            }),
+            (Action::PageGrid, |reader| reader.toggle_page_grid()),
+            (Action::TableOfContents, |reader| {
+                reader.table_of_contents.toggle()
+            }),
+            (Action::NightMode, |reader| reader.toggle_night_mode()),
+            (Action::CropPage, |reader| reader.edit_page_crop()),
+            (Action::Rotate90, |reader| {
+                reader.rotate_page(Rotation::Clockwise)
+            }),
+            (Action::Rotate180, |reader| {
+                reader.rotate_page(Rotation::UpsideDown)
+            }),
+            (Action::Rotate270, |reader| {
+                reader.rotate_page(Rotation::CounterClockwise)
+            }),
+        ];
+
+        let controller = reader.borrow().controller.clone();
+        let mut ctrl = controller.borrow_mut();
+        for (action, handler) in handlers {
+            let reader = Rc::clone(reader);
+            ctrl.actions.register(Some(READER_SCREEN), action, move || {
+                handler(&mut reader.borrow_mut());
+            });
+        }
+    }
+
+    fn is_right_to_left(&self) -> bool {
+        self.controller.borrow().server.is_right_to_left()
+    }
+
+    /// Move the server with `navigation` and show the new page if it moved
+    fn navigate(&mut self, navigation: impl FnOnce(&mut Server) -> bool) {
+        let (previous_page, was_in_transition) = {
+            let server = &self.controller.borrow().server;
+            (
+                server.get_current_page_index(),
+                server.get_transition().is_some(),
+            )
+        };
+        let moved = navigation(&mut self.controller.borrow_mut().server);
+        if !moved {
+            return;
+        }
+        let (current_page, transition) = {
+            let server = &self.controller.borrow().server;
+            (server.get_current_page_index(), server.get_transition())
+        };
+        // Turning the last page of a chapter shows its transition on the same page index
+        let finished_chapter = transition
+            .filter(|_| !was_in_transition && current_page == previous_page)
+            .map(|transition| transition.finished);
+        // Only pages that were read count for the pace, not skipped or revisited ones
+        if !was_in_transition && (current_page == previous_page + 1 || finished_chapter.is_some()) {
+            self.pace.record(self.page_shown_at.elapsed());
+        }
+        if let Some(chapter) = finished_chapter {
+            self.mark_chapter_read(chapter);
+        }
+        self.page_shown_at = Instant::now();
+        self.show_current_page();
+    }
+
+    fn show_current_page(&self) {
+        {
+            let controller = self.controller.borrow();
+            let server = &controller.server;
+            if let Some(page) = server.get_current_page() {
+                let texture = image::texture_from_shared(Arc::clone(page));
+                self.picture.set_paintable(Some(&texture));
             }
-        ));
-        window.add_controller(key_handler);
+            if let Some(error) = server.get_current_page_error() {
+                self.page_error.update(error);
+            }
+            if let Some(transition) = server.get_transition() {
+                let title = |chapter| server.get_chapter(chapter).map(Source::get_title);
+                self.transition.update(
+                    &title(transition.finished).unwrap_or_default(),
+                    transition.next.and_then(title).as_deref(),
+                );
+            }
+        }
+        // Turning pages from the grid keeps it open, unless there's something else to show
+        let view = self.current_view();
+        if view != PAGE_VIEW
+            || self.page_stack.visible_child_name().as_deref() != Some(PAGE_GRID_VIEW)
+        {
+            self.page_stack.set_visible_child_name(view);
+        }
+        self.update_thumbnails();
+        self.update_table_of_contents();
+        self.update_hud();
+    }
 
-        reader.borrow().container.clone()
+    /// What to show in place of the page grid: the page, its error or the chapter transition
+    fn current_view(&self) -> &'static str {
+        let server = &self.controller.borrow().server;
+        if server.get_transition().is_some() {
+            TRANSITION_VIEW
+        } else if server.get_current_page_error().is_some() {
+            PAGE_ERROR_VIEW
+        } else {
+            PAGE_VIEW
+        }
     }
 
-    fn prev_page(&mut self) {
-        match self.controller.borrow_mut().server.get_prev_page() {
-            Some(page) => {
-                if let Ok(texture) = image::dynamic_image_to_texture(page) {
-                    self.picture.set_paintable(Some(&texture));
-                }
+    /// Render the pages that failed again, e.g. after the file came back
+    fn retry_page(&self) {
+        let shown = self.controller.borrow_mut().server.retry_failed_pages();
+        if !shown {
+            warn!("The page still can't be shown");
+        }
+        self.show_current_page();
+    }
+
+    /// Mark a chapter that was read to the end in the library
+    fn mark_chapter_read(&self, chapter: usize) {
+        let controller = self.controller.borrow();
+        let Some(library) = &controller.library else {
+            return;
+        };
+        let Some(source) = controller.server.get_chapter(chapter) else {
+            return;
+        };
+        let result = library
+            .get_chapter_id(source.get_path(), source.get_file_pages().start)
+            .and_then(|id| match id {
+                Some(id) => library.mark_chapter_read(id),
+                None => Err(anyhow::anyhow!("not in the library")),
+            });
+        match result {
+            Ok(()) => info!("Finished `{}`", source.get_path().display()),
+            Err(e) => warn!(
+                "Could not mark `{}` as read: {}",
+                source.get_path().display(),
+                e
+            ),
+        }
+    }
+
+    fn update_hud(&self) {
+        let state = {
+            let controller = self.controller.borrow();
+            let server = &controller.server;
+            let Some(source) = server.get_current_source() else {
+                return;
+            };
+            let page = server.get_current_page_index();
+            let pages_left = server.get_current_source_pages().end.saturating_sub(page);
+            let chapter_title = match server.get_current_volume().and_then(Volume::get_number) {
+                Some(volume) => format!("Vol. {} · {}", volume, source.get_title()),
+                None => source.get_title(),
+            };
+            HudState {
+                chapter_title,
+                page,
+                page_count: server.get_page_count(),
+                page_label: source.get_page_label(server.get_page_in_source()),
+                time_left: pace::format_time_left(self.pace.estimate(pages_left)),
             }
-            _ => {}
+        };
+        self.hud.update(&state);
+    }
+
+    /// Show the HUD for a while, if it's enabled in the config
+    fn show_hud(&self) {
+        if self.controller.borrow().config.reader.show_bottom_indicator {
+            self.hud.show();
+        } else {
+            self.hud.hide();
         }
     }
 
-    fn next_page(&mut self) {
-        match self.controller.borrow_mut().server.get_next_page() {
-            Some(page) => {
-                if let Ok(texture) = image::dynamic_image_to_texture(page) {
-                    self.picture.set_paintable(Some(&texture));
+    /// Load the thumbnails of the current chapter if it changed, and highlight the current page
+    fn update_thumbnails(&self) {
+        let (path, views, pages, current_page) = {
+            let controller = self.controller.borrow();
+            let server = &controller.server;
+            let Some(source) = server.get_current_source() else {
+                return;
+            };
+            (
+                source.get_path().clone(),
+                server.get_current_source_views(),
+                server.get_current_source_pages(),
+                server.get_current_page_index(),
+            )
+        };
+        if !self.thumbnails.shows(&pages, &views) {
+            let weak_self = self.weak_self.clone();
+            self.thumbnails.load(path, views, pages, move |page| {
+                if let Some(reader) = weak_self.upgrade() {
+                    let mut reader = reader.borrow_mut();
+                    reader.page_stack.set_visible_child_name(PAGE_VIEW);
+                    reader.navigate(|server| server.go_to_page(page));
                 }
+            });
+        }
+        self.thumbnails.set_current(current_page);
+    }
+
+    /// Load the outline of the current file if it changed, and highlight the entry of the current page
+    fn update_table_of_contents(&self) {
+        let controller = self.controller.borrow();
+        let server = &controller.server;
+        let Some(source) = server.get_current_source() else {
+            return;
+        };
+        let path = source.get_path();
+        if self.table_of_contents.path().as_ref() != Some(path) {
+            let table_of_contents = source.table_of_contents();
+            // Entries in chapters that aren't open, e.g. missing ones, are left out
+            let entries = table_of_contents
+                .outline
+                .iter()
+                .filter_map(|item| {
+                    Some(TocEntry {
+                        title: item.title.clone(),
+                        level: item.level,
+                        page: server.find_file_page(path, item.page)?,
+                        label: table_of_contents.label(item.page),
+                    })
+                })
+                .collect();
+            self.table_of_contents.load(path, entries);
+        }
+        self.table_of_contents
+            .set_current(server.get_current_page_index());
+    }
+
+    /// Invert the colors of the pages, on top of the filters of the config
+    fn toggle_night_mode(&self) {
+        {
+            let server = &mut self.controller.borrow_mut().server;
+            let mut filters = *server.get_filters();
+            filters.invert = !filters.invert;
+            server.set_filters(filters);
+        }
+        self.show_current_page();
+    }
+
+    /// Rotate the current page on top of its rotation, and save its rotation in the library
+    fn rotate_page(&self, rotation: Rotation) {
+        let rotation = {
+            let server = &mut self.controller.borrow_mut().server;
+            if server.get_transition().is_some() {
+                return;
+            }
+            server.rotate_current_page(rotation)
+        };
+        let Some(rotation) = rotation else {
+            return;
+        };
+        self.show_current_page();
+
+        let controller = self.controller.borrow();
+        let Some(library) = &controller.library else {
+            warn!("Library is unavailable. The rotation of the page won't be kept");
+            return;
+        };
+        let Some(source) = controller.server.get_current_source() else {
+            return;
+        };
+        let page = controller.server.get_page_in_source();
+        let result = library
+            .get_chapter_id(source.get_path(), source.get_file_pages().start)
+            .and_then(|id| match id {
+                Some(id) => library.set_page_rotation(id, page, rotation),
+                None => Err(anyhow::anyhow!("not in the library")),
+            });
+        if let Err(e) = result {
+            warn!(
+                "Could not save the rotation of page {} of `{}`: {}",
+                page + 1,
+                source.get_path().display(),
+                e
+            );
+        }
+    }
+
+    /// Ask for the crop of the current page, starting from the one it has now
+    fn edit_page_crop(&self) {
+        let (window, page, crop) = {
+            let controller = self.controller.borrow();
+            let server = &controller.server;
+            if server.get_transition().is_some() {
+                return;
             }
-            _ => {}
+            let Some(crop) = server.get_current_crop() else {
+                return;
+            };
+            (controller.get_window(), server.get_page_in_source(), crop)
+        };
+        let weak_reader = self.weak_self.clone();
+        crop_dialog::edit_page_crop(&window, page, crop, move |crop| {
+            if let Some(reader) = weak_reader.upgrade() {
+                reader.borrow().set_page_crop(crop);
+            }
+        });
+    }
+
+    /// Crop the current page by hand and save the crop in the library, or go back to the automatic crop with `None`
+    fn set_page_crop(&self, crop: Option<CropBox>) {
+        let crop = self.controller.borrow_mut().server.set_current_crop(crop);
+        self.show_current_page();
+
+        let controller = self.controller.borrow();
+        let Some(library) = &controller.library else {
+            warn!("Library is unavailable. The crop of the page won't be kept");
+            return;
+        };
+        let Some(source) = controller.server.get_current_source() else {
+            return;
+        };
+        let page = controller.server.get_page_in_source();
+        let result = library
+            .get_chapter_id(source.get_path(), source.get_file_pages().start)
+            .and_then(|id| match id {
+                Some(id) => library.set_page_crop(id, page, crop),
+                None => Err(anyhow::anyhow!("not in the library")),
+            });
+        if let Err(e) = result {
+            warn!(
+                "Could not save the crop of page {} of `{}`: {}",
+                page + 1,
+                source.get_path().display(),
+                e
+            );
+        }
+    }
+
+    fn toggle_page_grid(&self) {
+        let showing_grid = self.page_stack.visible_child_name().as_deref() == Some(PAGE_GRID_VIEW);
+        self.page_stack.set_visible_child_name(if showing_grid {
+            self.current_view()
+        } else {
+            PAGE_GRID_VIEW
+        });
+    }
+
+    fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        if self.zoom == MIN_ZOOM {
+            self.picture.set_size_request(-1, -1);
+        } else {
+            let width = self.scrolled_window.width() as f64 * self.zoom;
+            let height = self.scrolled_window.height() as f64 * self.zoom;
+            self.picture.set_size_request(width as i32, height as i32);
+        }
+    }
+
+    fn toggle_bookmark(&mut self) {
+        let controller = self.controller.borrow();
+        let Some(library) = &controller.library else {
+            warn!("Library is unavailable. Can't bookmark the page");
+            return;
+        };
+        let Some(source) = controller.server.get_current_source() else {
+            return;
+        };
+        let page = controller.server.get_page_in_source();
+        let result = library
+            .get_chapter_id(source.get_path(), source.get_file_pages().start)
+            .and_then(|id| match id {
+                Some(id) => library.toggle_bookmark(id, page),
+                None => Err(anyhow::anyhow!("not in the library")),
+            });
+        match result {
+            Ok(true) => info!(
+                "Bookmarked page {} of `{}`",
+                page + 1,
+                source.get_path().display()
+            ),
+            Ok(false) => info!(
+                "Removed bookmark from page {} of `{}`",
+                page + 1,
+                source.get_path().display()
+            ),
+            Err(e) => warn!(
+                "Could not bookmark page {} of `{}`: {}",
+                page + 1,
+                source.get_path().display(),
+                e
+            ),
         }
     }
 }
@@ -0,0 +1,677 @@
+use crate::app::FiapoController;
+use crate::core::config::FiapoConfig;
+use crate::core::keybindings::Action;
+use crate::core::pdf::PdfBackendKind;
+use crate::core::theme::ThemePreset;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{
+    Button, ColorDialogButton, DropDown, Entry, Label, PasswordEntry, SpinButton, Switch, gdk, glib,
+};
+use gtk4 as gtk;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Name of the settings screen in the view stack
+pub const SETTINGS_SCREEN: &str = "settings_screen";
+
+const PAGE_FORMATS: [&str; 3] = ["jpeg", "png", "webp"];
+
+/// Rows of a list of values. Each row is kept with the widgets its value is read from
+type Rows<T> = Rc<RefCell<Vec<(gtk::Box, T)>>>;
+
+/// Widgets of the settings form, so the values can be read back when saving
+#[derive(Debug, Clone)]
+struct SettingsForm {
+    theme: DropDown,
+    text_color: ColorDialogButton,
+    background_color: ColorDialogButton,
+    show_bottom_indicator: Switch,
+    pdf_backend: DropDown,
+    auto_crop: Switch,
+    crop_tolerance: SpinButton,
+    max_crop: SpinButton,
+    split_wide_pages: Switch,
+    preloaded_pages: SpinButton,
+    brightness: SpinButton,
+    contrast: SpinButton,
+    gamma: SpinButton,
+    grayscale: Switch,
+    invert: Switch,
+    auto_levels: Switch,
+    sharpen: SpinButton,
+    opds_enabled: Switch,
+    opds_address: Entry,
+    opds_password: PasswordEntry,
+    export_page_format: DropDown,
+    export_quality: SpinButton,
+    export_max_resolution: SpinButton,
+    library_folders: Rows<String>,
+    mangadex_username: Entry,
+    mangadex_password: PasswordEntry,
+    anilist_token: PasswordEntry,
+    keybindings: Rows<(Entry, DropDown)>,
+}
+
+impl SettingsForm {
+    /// Fill a copy of `config` with the values of the form
+    fn read(&self, config: &FiapoConfig) -> FiapoConfig {
+        let mut config = config.clone();
+        let preset = selected_preset(&self.theme);
+        config.theme = preset.name().to_string();
+        // Colors are only saved if they override the ones of the theme
+        let palette = preset.palette();
+        config.text_color = color_override(&self.text_color, palette.text);
+        config.background_color = color_override(&self.background_color, palette.background_dark);
+        config.reader.show_bottom_indicator = self.show_bottom_indicator.is_active();
+        config.reader.pdf_backend = PdfBackendKind::ALL
+            .get(self.pdf_backend.selected() as usize)
+            .map_or("poppler", |backend| backend.name())
+            .to_string();
+        config.reader.auto_crop = self.auto_crop.is_active();
+        config.reader.crop_tolerance = self.crop_tolerance.value_as_int() as u8;
+        config.reader.max_crop = decimal_value(&self.max_crop);
+        config.reader.split_wide_pages = self.split_wide_pages.is_active();
+        config.cache.preloaded_pages = self.preloaded_pages.value_as_int() as usize;
+
+        // Filters of single series are only set in the file, and kept as they are
+        config.filters.brightness = decimal_value(&self.brightness);
+        config.filters.contrast = decimal_value(&self.contrast);
+        config.filters.gamma = decimal_value(&self.gamma);
+        config.filters.grayscale = self.grayscale.is_active();
+        config.filters.invert = self.invert.is_active();
+        config.filters.auto_levels = self.auto_levels.is_active();
+        config.filters.sharpen = decimal_value(&self.sharpen);
+
+        config.opds.enabled = self.opds_enabled.is_active();
+        config.opds.address = self.opds_address.text().trim().to_string();
+        config.opds.password = optional_text(&self.opds_password.text());
+
+        config.export.page_format = PAGE_FORMATS
+            .get(self.export_page_format.selected() as usize)
+            .unwrap_or(&PAGE_FORMATS[0])
+            .to_string();
+        config.export.quality = self.export_quality.value_as_int() as u8;
+        // 0 means no limit
+        config.export.max_resolution =
+            Some(self.export_max_resolution.value_as_int() as u32).filter(|max| *max > 0);
+
+        config.library.folders = self
+            .library_folders
+            .borrow()
+            .iter()
+            .map(|(_, folder)| folder.clone())
+            .collect();
+
+        config.providers.mangadex_username = optional_text(&self.mangadex_username.text());
+        config.providers.mangadex_password = optional_text(&self.mangadex_password.text());
+        config.providers.anilist_token = optional_text(&self.anilist_token.text());
+
+        config.keybindings = self
+            .keybindings
+            .borrow()
+            .iter()
+            .filter(|(_, (combo, _))| !combo.text().trim().is_empty())
+            .map(|(_, (combo, action))| {
+                let action = Action::ALL
+                    .get(action.selected() as usize)
+                    .map_or("none", |action| action.name());
+                (combo.text().trim().to_string(), action.to_string())
+            })
+            .collect();
+
+        config
+    }
+}
+
+#[derive(Debug)]
+pub struct Settings {
+    controller: Rc<RefCell<FiapoController>>,
+}
+impl Settings {
+    pub fn new(controller: Rc<RefCell<FiapoController>>) -> Self {
+        Self { controller }
+    }
+
+    pub fn build(&self) -> gtk::Box {
+        let config = self.controller.borrow().config.clone();
+        let window = self.controller.borrow().get_window();
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 10);
+        container.add_css_class("settings");
+
+        let title = Label::new(Some("Settings"));
+        title.set_hexpand(true);
+        title.set_halign(gtk::Align::Start);
+        title.add_css_class("settings-title");
+        let save_button = Button::with_label("Save");
+        let diagnostics_button = Button::with_label("Diagnostics");
+        diagnostics_button.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            self.controller,
+            move |_| FiapoController::open_diagnostics(Rc::clone(&controller))
+        ));
+        let back_button = Button::with_label("<- Back");
+        back_button.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            self.controller,
+            move |_| controller.borrow_mut().go_home()
+        ));
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        header.set_margin_top(10);
+        header.set_margin_start(10);
+        header.set_margin_end(10);
+        header.append(&title);
+        header.append(&diagnostics_button);
+        header.append(&save_button);
+        header.append(&back_button);
+        container.append(&header);
+
+        let sections = gtk::Box::new(gtk::Orientation::Vertical, 20);
+        sections.set_margin_start(10);
+        sections.set_margin_end(10);
+        sections.set_margin_bottom(10);
+
+        // Appearance
+        let preset = ThemePreset::from_name(&config.theme).unwrap_or(ThemePreset::Dark);
+        let preset_names: Vec<&str> = ThemePreset::ALL.iter().map(ThemePreset::name).collect();
+        let theme = DropDown::from_strings(&preset_names);
+        theme.set_selected(
+            ThemePreset::ALL
+                .iter()
+                .position(|p| *p == preset)
+                .unwrap_or(0) as u32,
+        );
+        let text_color = color_button(
+            config
+                .text_color
+                .as_deref()
+                .unwrap_or(preset.palette().text),
+        );
+        let background_color = color_button(
+            config
+                .background_color
+                .as_deref()
+                .unwrap_or(preset.palette().background_dark),
+        );
+        // Colors left as the ones of the previous theme follow the new one
+        let previous_preset = Rc::new(Cell::new(preset));
+        theme.connect_selected_notify(clone!(
+            #[weak]
+            text_color,
+            #[weak]
+            background_color,
+            move |theme| {
+                let preset = selected_preset(theme);
+                let previous = previous_preset.replace(preset).palette();
+                let palette = preset.palette();
+                follow_theme(&text_color, previous.text, palette.text);
+                follow_theme(
+                    &background_color,
+                    previous.background_dark,
+                    palette.background_dark,
+                );
+            }
+        ));
+        let user_css_hint = Label::new(Some(&format!(
+            "Custom styles are loaded from {}",
+            self.controller.borrow().paths.user_css_file().display()
+        )));
+        user_css_hint.set_halign(gtk::Align::Start);
+        user_css_hint.set_wrap(true);
+        user_css_hint.add_css_class("settings-hint");
+        let appearance_section = section(
+            "Appearance",
+            &[
+                row("Theme", &theme),
+                row("Text color", &text_color),
+                row("Background color", &background_color),
+            ],
+        );
+        appearance_section.append(&user_css_hint);
+        sections.append(&appearance_section);
+
+        // Reader
+        let show_bottom_indicator = Switch::new();
+        show_bottom_indicator.set_active(config.reader.show_bottom_indicator);
+        let pdf_backend =
+            DropDown::from_strings(&PdfBackendKind::ALL.map(|backend| backend.name()));
+        pdf_backend.set_selected(
+            PdfBackendKind::ALL
+                .iter()
+                .position(|backend| *backend == config.reader.pdf_backend())
+                .unwrap_or(0) as u32,
+        );
+        let auto_crop = Switch::new();
+        auto_crop.set_active(config.reader.auto_crop);
+        let crop_tolerance = SpinButton::with_range(0.0, 128.0, 1.0);
+        crop_tolerance.set_value(config.reader.crop_tolerance as f64);
+        let max_crop = decimal_spin_button(0.0, 0.45, 0.05, config.reader.max_crop);
+        let split_wide_pages = Switch::new();
+        split_wide_pages.set_active(config.reader.split_wide_pages);
+        let preloaded_pages = SpinButton::with_range(0.0, 10.0, 1.0);
+        preloaded_pages.set_value(config.cache.preloaded_pages as f64);
+        sections.append(&section(
+            "Reader",
+            &[
+                row("Show page HUD", &show_bottom_indicator),
+                row("PDF renderer", &pdf_backend),
+                row("Crop the margins of pages", &auto_crop),
+                row("Crop tolerance", &crop_tolerance),
+                row("Largest share cropped off each side", &max_crop),
+                row("Split wide pages in two", &split_wide_pages),
+                row(
+                    "Pages kept rendered around the current one",
+                    &preloaded_pages,
+                ),
+            ],
+        ));
+
+        // Page filters
+        let brightness = decimal_spin_button(-1.0, 1.0, 0.05, config.filters.brightness);
+        let contrast = decimal_spin_button(-1.0, 1.0, 0.05, config.filters.contrast);
+        let gamma = decimal_spin_button(0.2, 5.0, 0.1, config.filters.gamma);
+        let grayscale = Switch::new();
+        grayscale.set_active(config.filters.grayscale);
+        let invert = Switch::new();
+        invert.set_active(config.filters.invert);
+        let auto_levels = Switch::new();
+        auto_levels.set_active(config.filters.auto_levels);
+        let sharpen = decimal_spin_button(0.0, 5.0, 0.5, config.filters.sharpen);
+        let filters_hint = Label::new(Some(
+            "Filters of single series can be set in the config file, under [filters.series.\"Title\"]",
+        ));
+        filters_hint.set_halign(gtk::Align::Start);
+        filters_hint.set_wrap(true);
+        filters_hint.add_css_class("settings-hint");
+        let filters_section = section(
+            "Page filters",
+            &[
+                row("Brightness", &brightness),
+                row("Contrast", &contrast),
+                row("Gamma", &gamma),
+                row("Grayscale", &grayscale),
+                row("Night mode (invert colors)", &invert),
+                row("Auto-levels", &auto_levels),
+                row("Sharpening sigma (0 for none)", &sharpen),
+            ],
+        );
+        filters_section.append(&filters_hint);
+        sections.append(&filters_section);
+
+        // Library
+        let library_folders: Rows<String> = Rc::default();
+        let folders_list = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        for folder in &config.library.folders {
+            add_folder_row(&folders_list, &library_folders, folder);
+        }
+        let add_folder_button = Button::with_label("Add folder");
+        add_folder_button.set_halign(gtk::Align::Start);
+        add_folder_button.connect_clicked(clone!(
+            #[weak]
+            window,
+            #[weak]
+            folders_list,
+            #[strong]
+            library_folders,
+            move |_| {
+                glib::MainContext::default().spawn_local(clone!(
+                    #[strong]
+                    library_folders,
+                    async move {
+                        let file_dialog = gtk::FileDialog::builder()
+                            .title("Add a folder to the library")
+                            .modal(true)
+                            .build();
+                        match file_dialog.select_folder_future(Some(&window)).await {
+                            Ok(folder) => {
+                                if let Some(path) = folder.path() {
+                                    add_folder_row(
+                                        &folders_list,
+                                        &library_folders,
+                                        &path.to_string_lossy(),
+                                    );
+                                }
+                            }
+                            Err(e) => warn!("Could not select folder: {}", e),
+                        }
+                    }
+                ));
+            }
+        ));
+        let library_section = section("Library folders", &[]);
+        library_section.append(&folders_list);
+        library_section.append(&add_folder_button);
+        sections.append(&library_section);
+
+        // OPDS
+        let opds_enabled = Switch::new();
+        opds_enabled.set_active(config.opds.enabled);
+        let opds_address = Entry::new();
+        opds_address.set_text(&config.opds.address);
+        let opds_password = PasswordEntry::new();
+        opds_password.set_show_peek_icon(true);
+        opds_password.set_text(config.opds.password.as_deref().unwrap_or_default());
+        sections.append(&section(
+            "OPDS server",
+            &[
+                row("Enabled", &opds_enabled),
+                row("Address", &opds_address),
+                row("Password", &opds_password),
+            ],
+        ));
+
+        // Export
+        let export_page_format = DropDown::from_strings(&PAGE_FORMATS);
+        let page_format = config.export.page_format.to_lowercase();
+        let page_format = if page_format == "jpg" {
+            "jpeg"
+        } else {
+            &page_format
+        };
+        export_page_format.set_selected(
+            PAGE_FORMATS
+                .iter()
+                .position(|format| *format == page_format)
+                .unwrap_or(0) as u32,
+        );
+        let export_quality = SpinButton::with_range(1.0, 100.0, 1.0);
+        export_quality.set_value(config.export.quality as f64);
+        let export_max_resolution = SpinButton::with_range(0.0, 16384.0, 100.0);
+        export_max_resolution.set_value(config.export.max_resolution.unwrap_or(0) as f64);
+        sections.append(&section(
+            "Export",
+            &[
+                row("Page format", &export_page_format),
+                row("JPEG quality", &export_quality),
+                row(
+                    "Maximum resolution (0 for no limit)",
+                    &export_max_resolution,
+                ),
+            ],
+        ));
+
+        // Providers
+        let mangadex_username = Entry::new();
+        mangadex_username.set_text(
+            config
+                .providers
+                .mangadex_username
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        let mangadex_password = PasswordEntry::new();
+        mangadex_password.set_show_peek_icon(true);
+        mangadex_password.set_text(
+            config
+                .providers
+                .mangadex_password
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        let anilist_token = PasswordEntry::new();
+        anilist_token.set_show_peek_icon(true);
+        anilist_token.set_text(
+            config
+                .providers
+                .anilist_token
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        sections.append(&section(
+            "Accounts",
+            &[
+                row("MangaDex username", &mangadex_username),
+                row("MangaDex password", &mangadex_password),
+                row("AniList token", &anilist_token),
+            ],
+        ));
+
+        // Keybindings
+        let keybindings: Rows<(Entry, DropDown)> = Rc::default();
+        let keybindings_list = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        for (combo, action) in &config.keybindings {
+            add_keybinding_row(&keybindings_list, &keybindings, combo, action);
+        }
+        let add_keybinding_button = Button::with_label("Add keybinding");
+        add_keybinding_button.set_halign(gtk::Align::Start);
+        add_keybinding_button.connect_clicked(clone!(
+            #[weak]
+            keybindings_list,
+            #[strong]
+            keybindings,
+            move |_| add_keybinding_row(&keybindings_list, &keybindings, "", "")
+        ));
+        let keybindings_hint = Label::new(Some(
+            "Key combos like \"Ctrl+Right\" are bound on top of the defaults. Bind a key to \"none\" to unbind it.",
+        ));
+        keybindings_hint.set_halign(gtk::Align::Start);
+        keybindings_hint.set_wrap(true);
+        keybindings_hint.add_css_class("settings-hint");
+        let keybindings_section = section("Keybindings", &[]);
+        keybindings_section.append(&keybindings_hint);
+        keybindings_section.append(&keybindings_list);
+        keybindings_section.append(&add_keybinding_button);
+        sections.append(&keybindings_section);
+
+        let form = SettingsForm {
+            theme,
+            text_color,
+            background_color,
+            show_bottom_indicator,
+            pdf_backend,
+            auto_crop,
+            crop_tolerance,
+            max_crop,
+            split_wide_pages,
+            preloaded_pages,
+            brightness,
+            contrast,
+            gamma,
+            grayscale,
+            invert,
+            auto_levels,
+            sharpen,
+            opds_enabled,
+            opds_address,
+            opds_password,
+            export_page_format,
+            export_quality,
+            export_max_resolution,
+            library_folders,
+            mangadex_username,
+            mangadex_password,
+            anilist_token,
+            keybindings,
+        };
+        save_button.connect_clicked(clone!(
+            #[strong(rename_to = controller)]
+            self.controller,
+            move |_| Settings::save(&controller, &form)
+        ));
+
+        let scroll = gtk::ScrolledWindow::new();
+        scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scroll.set_vexpand(true);
+        scroll.set_child(Some(&sections));
+        container.append(&scroll);
+
+        container
+    }
+
+    /// Write the form to the config file and apply it
+    fn save(controller: &Rc<RefCell<FiapoController>>, form: &SettingsForm) {
+        let config = form.read(&controller.borrow().config);
+        let config_path = controller.borrow().paths.config_file.clone();
+        if let Err(e) = config.save(&config_path) {
+            warn!(
+                "Could not save the config to `{}`: {}",
+                config_path.display(),
+                e
+            );
+            controller
+                .borrow()
+                .notification
+                .show("Could not save the settings", &e.to_string());
+            return;
+        }
+
+        FiapoController::reload_config(controller);
+        controller.borrow_mut().go_home();
+    }
+}
+
+/// Spin button for a value with decimals, e.g. the filters
+fn decimal_spin_button(min: f64, max: f64, step: f64, value: f64) -> SpinButton {
+    let spin_button = SpinButton::with_range(min, max, step);
+    spin_button.set_digits(2);
+    spin_button.set_value(value);
+    spin_button
+}
+
+/// Value of a `decimal_spin_button`, without the rounding errors of its steps
+fn decimal_value(spin_button: &SpinButton) -> f64 {
+    (spin_button.value() * 100.0).round() / 100.0
+}
+
+fn section(title: &str, rows: &[gtk::Box]) -> gtk::Box {
+    let section = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    section.add_css_class("settings-section");
+    let title = Label::new(Some(title));
+    title.set_halign(gtk::Align::Start);
+    title.add_css_class("settings-section-title");
+    section.append(&title);
+    for row in rows {
+        section.append(row);
+    }
+    section
+}
+
+fn row(label: &str, widget: &impl IsA<gtk::Widget>) -> gtk::Box {
+    let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    row.add_css_class("settings-row");
+    let label = Label::new(Some(label));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    row.append(&label);
+    widget.set_valign(gtk::Align::Center);
+    row.append(widget);
+    row
+}
+
+fn add_folder_row(list: &gtk::Box, rows: &Rows<String>, folder: &str) {
+    let remove_button = Button::with_label("Remove");
+    let folder_row = row(folder, &remove_button);
+    list.append(&folder_row);
+    rows.borrow_mut()
+        .push((folder_row.clone(), folder.to_string()));
+
+    remove_button.connect_clicked(clone!(
+        #[weak]
+        list,
+        #[weak]
+        folder_row,
+        #[strong]
+        rows,
+        move |_| {
+            list.remove(&folder_row);
+            rows.borrow_mut().retain(|(row, _)| *row != folder_row);
+        }
+    ));
+}
+
+/// `action` is the name of an action, or anything else for "none"
+fn add_keybinding_row(list: &gtk::Box, rows: &Rows<(Entry, DropDown)>, combo: &str, action: &str) {
+    let combo_entry = Entry::new();
+    combo_entry.set_text(combo);
+    combo_entry.set_placeholder_text(Some("Ctrl+Right"));
+    combo_entry.set_hexpand(true);
+
+    // The last option is "none", which unbinds the key
+    let action_names: Vec<&str> = Action::ALL
+        .iter()
+        .map(Action::name)
+        .chain(["none"])
+        .collect();
+    let action_dropdown = DropDown::from_strings(&action_names);
+    action_dropdown.set_selected(
+        Action::from_name(action)
+            .and_then(|action| Action::ALL.iter().position(|a| *a == action))
+            .unwrap_or(Action::ALL.len()) as u32,
+    );
+    let remove_button = Button::with_label("Remove");
+
+    let keybinding_row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    keybinding_row.add_css_class("settings-row");
+    keybinding_row.append(&combo_entry);
+    keybinding_row.append(&action_dropdown);
+    keybinding_row.append(&remove_button);
+    list.append(&keybinding_row);
+    rows.borrow_mut()
+        .push((keybinding_row.clone(), (combo_entry, action_dropdown)));
+
+    remove_button.connect_clicked(clone!(
+        #[weak]
+        list,
+        #[weak]
+        keybinding_row,
+        #[strong]
+        rows,
+        move |_| {
+            list.remove(&keybinding_row);
+            rows.borrow_mut().retain(|(row, _)| *row != keybinding_row);
+        }
+    ));
+}
+
+fn color_button(color: &str) -> ColorDialogButton {
+    let button = ColorDialogButton::new(Some(gtk::ColorDialog::new()));
+    match gdk::RGBA::parse(color) {
+        Ok(rgba) => button.set_rgba(&rgba),
+        Err(_) => warn!("Invalid color `{}`", color),
+    }
+    button
+}
+
+fn selected_preset(dropdown: &DropDown) -> ThemePreset {
+    ThemePreset::ALL
+        .get(dropdown.selected() as usize)
+        .copied()
+        .unwrap_or(ThemePreset::Dark)
+}
+
+/// Color of the button, or `None` if it's the one of the theme
+fn color_override(button: &ColorDialogButton, theme_color: &str) -> Option<String> {
+    Some(rgba_to_hex(&button.rgba())).filter(|color| !color.eq_ignore_ascii_case(theme_color))
+}
+
+/// Switch the button to `new` if it had the color `old` of the previous theme
+fn follow_theme(button: &ColorDialogButton, old: &str, new: &str) {
+    if rgba_to_hex(&button.rgba()).eq_ignore_ascii_case(old)
+        && let Ok(rgba) = gdk::RGBA::parse(new)
+    {
+        button.set_rgba(&rgba);
+    }
+}
+
+/// "#RRGGBB", or "#RRGGBBAA" if it's not opaque
+fn rgba_to_hex(rgba: &gdk::RGBA) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let hex = format!(
+        "#{:02X}{:02X}{:02X}",
+        channel(rgba.red()),
+        channel(rgba.green()),
+        channel(rgba.blue())
+    );
+    if rgba.alpha() < 1.0 {
+        format!("{}{:02X}", hex, channel(rgba.alpha()))
+    } else {
+        hex
+    }
+}
+
+fn optional_text(text: &str) -> Option<String> {
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
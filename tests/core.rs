@@ -5,3 +5,1225 @@ fn test_structure() {
     let result = _test_fn(34, 35);
     assert_eq!(result, 69);
 }
+
+#[test]
+fn test_comic_info_round_trip() {
+    use fiapo::core::comic_info::{ComicInfo, Manga};
+
+    let xml = r#"<?xml version="1.0"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Series>Foo &amp; Bar</Series>
+  <Number>10.5</Number>
+  <Volume>3</Volume>
+  <Writer>Someone</Writer>
+  <Genre>Action, Drama</Genre>
+  <Manga>YesAndRightToLeft</Manga>
+  <Notes>Unknown fields are ignored</Notes>
+  <Pages><Page Image="0" Type="FrontCover"/><Page Image="1"/></Pages>
+</ComicInfo>"#;
+    let info = ComicInfo::parse(xml).unwrap();
+    assert_eq!(info.series.as_deref(), Some("Foo & Bar"));
+    assert_eq!(info.number.as_deref(), Some("10.5"));
+    assert_eq!(info.volume, Some(3));
+    assert_eq!(info.manga, Manga::YesAndRightToLeft);
+    assert!(info.is_right_to_left());
+    assert_eq!(info.genres(), vec!["Action", "Drama"]);
+    assert_eq!(info.pages.as_ref().unwrap().pages.len(), 2);
+
+    let written = ComicInfo::parse(&info.to_xml().unwrap()).unwrap();
+    assert_eq!(written, info);
+}
+
+#[test]
+fn test_comic_info_keeps_unknown_elements() {
+    use fiapo::core::comic_info::{ComicInfo, Manga};
+
+    let xml = r#"<?xml version="1.0"?>
+<ComicInfo>
+  <Title>Set by the user</Title>
+  <Series>Old</Series>
+  <Notes>Scanned by someone</Notes>
+  <Publisher>Foo &amp; Co</Publisher>
+  <Pages><Page Image="0" Type="FrontCover"/></Pages>
+</ComicInfo>"#;
+    let info = ComicInfo {
+        series: Some("New".to_string()),
+        volume: Some(2),
+        manga: Manga::YesAndRightToLeft,
+        ..Default::default()
+    };
+    let merged = info.merge_into(xml).unwrap();
+    assert!(merged.contains("<Notes>Scanned by someone</Notes>"));
+    assert!(merged.contains("<Publisher>Foo &amp; Co</Publisher>"));
+    assert!(!merged.contains("Old"));
+
+    let written = ComicInfo::parse(&merged).unwrap();
+    assert_eq!(written.title.as_deref(), Some("Set by the user"));
+    assert_eq!(written.series.as_deref(), Some("New"));
+    assert_eq!(written.volume, Some(2));
+    assert_eq!(written.manga, Manga::YesAndRightToLeft);
+    assert_eq!(
+        written.pages.unwrap().pages[0].page_type.as_deref(),
+        Some("FrontCover")
+    );
+}
+
+#[test]
+fn test_scan_paths() {
+    use fiapo::core::scanner::scan_paths;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_scan_paths");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("vol 2")).unwrap();
+    fs::create_dir_all(dir.join("vol 3")).unwrap();
+    fs::create_dir_all(dir.join(".hidden")).unwrap();
+    for file in [
+        "b.pdf",
+        "a.CBZ",
+        "notes.txt",
+        "vol 2/c.zip",
+        "vol 3/001.png",
+        ".hidden/d.pdf",
+    ] {
+        fs::write(dir.join(file), b"").unwrap();
+    }
+
+    let files = scan_paths(std::slice::from_ref(&dir));
+    assert_eq!(
+        files,
+        vec![
+            dir.join("a.CBZ"),
+            dir.join("b.pdf"),
+            dir.join("vol 2/c.zip"),
+            // Folders of images are a single source
+            dir.join("vol 3"),
+        ]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_keymap_from_config() {
+    use fiapo::core::keybindings::{Action, Keymap};
+    use gtk4::gdk::{Key, ModifierType};
+    use std::collections::BTreeMap;
+
+    let config = BTreeMap::from([
+        ("Ctrl+Right".to_string(), "next_chapter".to_string()),
+        ("Ctrl++".to_string(), "zoom_in".to_string()),
+        ("Escape".to_string(), "none".to_string()),
+        ("Hyper+x".to_string(), "quit".to_string()),
+        ("n".to_string(), "not_an_action".to_string()),
+    ]);
+    let (keymap, errors) = Keymap::from_config(&config);
+    assert_eq!(errors.len(), 2);
+
+    assert_eq!(
+        keymap.lookup(Key::Right, ModifierType::CONTROL_MASK),
+        Some(Action::NextChapter)
+    );
+    assert_eq!(
+        keymap.lookup(
+            Key::plus,
+            ModifierType::CONTROL_MASK | ModifierType::SHIFT_MASK
+        ),
+        Some(Action::ZoomIn)
+    );
+    // Defaults are kept unless they're unbound
+    assert_eq!(
+        keymap.lookup(Key::Right, ModifierType::empty()),
+        Some(Action::PageRight)
+    );
+    assert_eq!(keymap.lookup(Key::Escape, ModifierType::empty()), None);
+}
+
+#[test]
+fn test_config_validation() {
+    use fiapo::core::config::FiapoConfig;
+
+    let contents = r##"text_color = "#000000"
+background_color = 5
+
+[reader]
+show_bottom_indicator = "yes"
+
+[export]
+quality = 50
+typo = true
+"##;
+    let (config, issues) = FiapoConfig::from_toml(contents);
+    // Valid keys are kept, even in sections with invalid ones
+    assert_eq!(config.text_color.as_deref(), Some("#000000"));
+    assert_eq!(config.export.quality, 50);
+    // Invalid keys are reset to their defaults
+    assert_eq!(
+        config.background_color,
+        FiapoConfig::defaults().background_color
+    );
+
+    let issues: Vec<(Option<usize>, Option<&str>)> = issues
+        .iter()
+        .map(|issue| (issue.line, issue.key.as_deref()))
+        .collect();
+    assert_eq!(
+        issues,
+        vec![
+            (Some(2), Some("background_color")),
+            (Some(5), Some("reader.show_bottom_indicator")),
+            (Some(9), Some("export.typo")),
+        ]
+    );
+}
+
+#[test]
+fn test_thumbnail_uses_cover_image() {
+    use fiapo::core::reader::Source;
+    use fiapo::core::thumbnail::{self, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_thumbnail");
+    let _ = fs::remove_dir_all(&dir);
+    let chapter = dir.join("chapter 1");
+    fs::create_dir_all(&chapter).unwrap();
+    RgbImage::from_pixel(400, 600, Rgb([255, 255, 255]))
+        .save(chapter.join("001.png"))
+        .unwrap();
+    RgbImage::from_pixel(400, 600, Rgb([255, 0, 0]))
+        .save(chapter.join("cover.png"))
+        .unwrap();
+
+    let source = Source::new(
+        Source::source_type_for_path(&chapter).unwrap(),
+        chapter.clone(),
+        false,
+    );
+    assert_eq!(source.get_cover_page(), 1);
+
+    let thumbnails_dir = dir.join("thumbnails");
+    let path = thumbnail::get_or_generate(&thumbnails_dir, &chapter).unwrap();
+    assert_eq!(path, thumbnail::thumbnail_path(&thumbnails_dir, &chapter));
+    let thumbnail = image::open(&path).unwrap().to_rgb8();
+    assert_eq!(thumbnail.dimensions(), (THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT));
+    let pixel = thumbnail.get_pixel(THUMBNAIL_WIDTH / 2, THUMBNAIL_HEIGHT / 2);
+    assert!(pixel[0] > 200 && pixel[1] < 50, "{:?}", pixel);
+
+    // Sources without pages have no thumbnail
+    fs::create_dir_all(dir.join("empty")).unwrap();
+    assert!(thumbnail::get_or_generate(&thumbnails_dir, &dir.join("empty")).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_page_thumbnails_and_chapter_pages() {
+    use fiapo::core::reader::{Server, Source, Volume};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_page_thumbnails");
+    let _ = fs::remove_dir_all(&dir);
+    let mut sources = Vec::new();
+    for (chapter, pages) in [("chapter 1", 2), ("chapter 2", 3)] {
+        let chapter = dir.join(chapter);
+        fs::create_dir_all(&chapter).unwrap();
+        for page in 0..pages {
+            RgbImage::from_pixel(400, 600, Rgb([255, 255, 255]))
+                .save(chapter.join(format!("{:03}.png", page)))
+                .unwrap();
+        }
+        sources.push(Source::new(
+            Source::source_type_for_path(&chapter).unwrap(),
+            chapter,
+            false,
+        ));
+    }
+
+    let thumbnail = sources[0].render_thumbnail(0, 120).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (80, 120));
+
+    let mut server = Server::new();
+    server.set_volumes(vec![Volume::new(None, sources)]);
+    assert_eq!(server.get_current_source_pages(), 0..2);
+    assert!(server.go_to_page(3));
+    assert_eq!(server.get_current_page_index(), 3);
+    assert_eq!(server.get_current_source_pages(), 2..5);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_chapter_transitions() {
+    use fiapo::core::reader::{ChapterTransition, Server, Source, Volume};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_chapter_transitions");
+    let _ = fs::remove_dir_all(&dir);
+    let mut chapters = Vec::new();
+    for (volume, chapter, pages) in [
+        (Some(1), "chapter 1", 2),
+        (Some(1), "chapter 2", 1),
+        (Some(2), "chapter 3", 1),
+    ] {
+        let path = dir.join(chapter);
+        fs::create_dir_all(&path).unwrap();
+        for page in 0..pages {
+            RgbImage::from_pixel(10, 10, Rgb([255, 255, 255]))
+                .save(path.join(format!("{:03}.png", page)))
+                .unwrap();
+        }
+        chapters.push((
+            volume,
+            Source::new(Source::source_type_for_path(&path).unwrap(), path, false),
+        ));
+    }
+    let volumes = Volume::group(chapters);
+    assert_eq!(volumes.len(), 2);
+    assert_eq!(volumes[0].get_chapters().len(), 2);
+
+    let mut server = Server::new();
+    server.set_volumes(volumes);
+    assert_eq!(server.get_page_count(), 4);
+    assert!(server.next_page());
+    assert_eq!(server.get_transition(), None);
+
+    // The last page of a chapter is followed by its transition, then by the next chapter
+    assert!(server.next_page());
+    assert_eq!(
+        server.get_transition(),
+        Some(ChapterTransition {
+            finished: 0,
+            next: Some(1)
+        })
+    );
+    assert_eq!(server.get_current_page_index(), 1);
+    assert!(server.next_page());
+    assert_eq!(server.get_transition(), None);
+    assert_eq!(server.get_current_page_index(), 2);
+
+    // Going back shows the transition again, then the last page of the previous chapter
+    assert!(server.prev_page());
+    assert_eq!(server.get_current_page_index(), 1);
+    assert!(server.get_transition().is_some());
+    assert!(server.prev_page());
+    assert_eq!(server.get_transition(), None);
+    assert_eq!(server.get_current_page_index(), 1);
+
+    assert!(server.next_source());
+    assert_eq!(
+        server.get_current_volume().and_then(Volume::get_number),
+        Some(1)
+    );
+    assert!(server.next_source());
+    assert_eq!(
+        server.get_current_volume().and_then(Volume::get_number),
+        Some(2)
+    );
+    assert!(!server.next_source());
+
+    // The last chapter ends with a transition to nothing
+    assert!(server.next_page());
+    assert_eq!(
+        server.get_transition(),
+        Some(ChapterTransition {
+            finished: 2,
+            next: None
+        })
+    );
+    assert!(!server.next_page());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_parse_chapter_numbers() {
+    use fiapo::core::sorting::{ChapterNumbers, parse_numbers};
+
+    let cases: [(&str, Option<f64>, Option<f64>); 12] = [
+        ("v03 c021.5", Some(3.0), Some(21.5)),
+        ("Vol. 2 Ch. 12", Some(2.0), Some(12.0)),
+        ("Volume 1 - Chapter 7", Some(1.0), Some(7.0)),
+        ("Berserk_v03_c021", Some(3.0), Some(21.0)),
+        ("Ch.12", None, Some(12.0)),
+        ("c001", None, Some(1.0)),
+        ("Chapter 10.5 - Extra", None, Some(10.5)),
+        ("One Piece 1001", None, Some(1001.0)),
+        ("Berserk v03", Some(3.0), None),
+        ("[Group] Title 12 (2019)", None, Some(12.0)),
+        ("Title Ch 5 - 20 pages", None, Some(5.0)),
+        ("Prologue", None, None),
+    ];
+    for (name, volume, chapter) in cases {
+        assert_eq!(
+            parse_numbers(name),
+            ChapterNumbers { volume, chapter },
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_natural_sort() {
+    use fiapo::core::sorting::{SortKey, natural_cmp};
+    use std::cmp::Ordering;
+    use std::path::Path;
+
+    let cases: [(&str, &str, Ordering); 7] = [
+        ("Chapter 2.pdf", "Chapter 10.pdf", Ordering::Less),
+        ("page9.png", "page10.png", Ordering::Less),
+        ("page010.png", "page9.png", Ordering::Greater),
+        ("a", "B", Ordering::Less),
+        ("Chapter 2", "Chapter 2 extra", Ordering::Less),
+        ("001.jpg", "001.jpg", Ordering::Equal),
+        ("x1y2", "x1y10", Ordering::Less),
+    ];
+    for (a, b, expected) in cases {
+        assert_eq!(natural_cmp(a, b), expected, "{} vs {}", a, b);
+    }
+
+    let mut names = vec![
+        "Chapter 10.cbz",
+        "Chapter 2.cbz",
+        "Chapter 10.5.cbz",
+        "v02 c011.cbz",
+        "v01 c001.cbz",
+        "Chapter 1.cbz",
+    ];
+    names.sort_by_key(|name| SortKey::from_path(Path::new(name)));
+    assert_eq!(
+        names,
+        [
+            "v01 c001.cbz",
+            "v02 c011.cbz",
+            "Chapter 1.cbz",
+            "Chapter 2.cbz",
+            "Chapter 10.cbz",
+            "Chapter 10.5.cbz",
+        ]
+    );
+
+    // Metadata numbers win over the file name
+    let from_metadata =
+        SortKey::from_path(Path::new("Chapter 1.cbz")).with_metadata(None, Some("20"));
+    assert!(from_metadata > SortKey::from_path(Path::new("Chapter 10.cbz")));
+    let with_volume = SortKey::from_path(Path::new("Chapter 30.cbz")).with_metadata(Some(1), None);
+    assert!(with_volume < SortKey::from_path(Path::new("v02 c011.cbz")));
+}
+
+#[test]
+fn test_sources_with_the_same_name() {
+    use fiapo::core::reader::{Source, SourceType};
+    use std::path::PathBuf;
+
+    let a = Source::new(
+        SourceType::Directory,
+        PathBuf::from("/nonexistent/a/Chapter 1"),
+        false,
+    );
+    let b = Source::new(
+        SourceType::Directory,
+        PathBuf::from("/nonexistent/b/Chapter 1"),
+        false,
+    );
+    assert_ne!(a, b);
+    assert!(a < b);
+}
+
+#[test]
+fn test_source_errors() {
+    use fiapo::core::error::SourceError;
+    use fiapo::core::reader::{Server, Source, SourceType, Volume};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_source_errors");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let missing = dir.join("missing.cbz");
+    let error = Source::open(SourceType::Archive, missing.clone(), false).unwrap_err();
+    assert!(matches!(error, SourceError::Open { .. }));
+    assert_eq!(error.path(), missing);
+
+    let corrupt = dir.join("corrupt.cbz");
+    fs::write(&corrupt, b"not a zip").unwrap();
+    let error = Source::open(SourceType::Archive, corrupt.clone(), false).unwrap_err();
+    assert!(matches!(error, SourceError::Parse { .. }));
+    assert!(error.to_string().contains("corrupt.cbz"));
+    // The lenient constructor logs the error and returns an empty source
+    assert_eq!(
+        Source::new(SourceType::Archive, corrupt, false).get_page_count(),
+        0
+    );
+
+    // Single images are a source of one page, and unknown files aren't read as PDFs
+    let image = dir.join("page.png");
+    RgbImage::from_pixel(10, 20, Rgb([255, 255, 255]))
+        .save(&image)
+        .unwrap();
+    let mut source =
+        Source::open(Source::source_type_for_path(&image).unwrap(), image, false).unwrap();
+    assert_eq!(source.get_page_count(), 1);
+    assert_eq!(source.page_sizes(), vec![Some((10, 20))]);
+    assert!(source.render_page(0).is_ok());
+    let unknown = dir.join("notes.txt");
+    fs::write(&unknown, b"not a comic").unwrap();
+    let error = Source::source_type_for_path(&unknown).unwrap_err();
+    assert!(matches!(error, SourceError::Unsupported { .. }));
+
+    // A password can't unlock a PDF that isn't there
+    let error = fiapo::core::reader::unlock_pdf(&dir.join("missing.pdf"), "secret").unwrap_err();
+    assert!(matches!(error, SourceError::Open { .. }));
+
+    // A damaged page is reported by the server, and can be retried once it's fixed
+    let chapter = dir.join("chapter");
+    fs::create_dir_all(&chapter).unwrap();
+    fs::write(chapter.join("001.png"), b"not a png").unwrap();
+    RgbImage::from_pixel(10, 10, Rgb([255, 255, 255]))
+        .save(chapter.join("002.png"))
+        .unwrap();
+    let source = Source::open(SourceType::Directory, chapter.clone(), false).unwrap();
+    let mut server = Server::new();
+    server.set_volumes(vec![Volume::new(None, vec![source])]);
+    assert!(server.get_current_page().is_none());
+    let error = server.get_current_page_error().unwrap();
+    assert!(matches!(error, SourceError::Decode { page: 0, .. }));
+    assert!(!server.retry_failed_pages());
+
+    RgbImage::from_pixel(10, 10, Rgb([0, 0, 0]))
+        .save(chapter.join("001.png"))
+        .unwrap();
+    assert!(server.retry_failed_pages());
+    assert!(server.get_current_page_error().is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_reading_pace() {
+    use fiapo::core::pace::{ReadingPace, format_time_left};
+    use std::time::Duration;
+
+    let mut pace = ReadingPace::new();
+    // A default is used until some pages were read
+    assert!(pace.time_per_page() > Duration::ZERO);
+
+    pace.record(Duration::from_secs(10));
+    pace.record(Duration::from_secs(20));
+    // Pages left open for too long are ignored
+    pace.record(Duration::from_secs(60 * 60));
+    assert_eq!(pace.time_per_page(), Duration::from_secs(15));
+    assert_eq!(pace.estimate(8), Duration::from_secs(120));
+
+    assert_eq!(format_time_left(Duration::from_secs(20)), "< 1 min left");
+    assert_eq!(format_time_left(Duration::from_secs(125)), "2 min left");
+    assert_eq!(
+        format_time_left(Duration::from_secs(65 * 60)),
+        "1 h 5 min left"
+    );
+}
+
+#[test]
+fn test_library_folder_paths() {
+    use fiapo::core::config::FiapoConfig;
+    use std::path::PathBuf;
+
+    let (config, issues) = FiapoConfig::from_toml(
+        r#"[library]
+folders = ["~/Manga", "/mnt/shared", "~user/Manga"]
+"#,
+    );
+    assert!(issues.is_empty());
+    let home = PathBuf::from(std::env::var_os("HOME").expect("HOME is not set"));
+    assert_eq!(
+        config.library.folder_paths(),
+        vec![
+            home.join("Manga"),
+            PathBuf::from("/mnt/shared"),
+            // Other users' homes aren't expanded
+            PathBuf::from("~user/Manga"),
+        ]
+    );
+}
+
+#[test]
+fn test_config_save_keeps_comments() {
+    use fiapo::core::config::FiapoConfig;
+
+    let dir = std::env::temp_dir().join("fiapo_test_config_save");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fiapo.toml");
+    std::fs::write(
+        &path,
+        r##"# My config
+text_color = "#000000" # black
+
+[export]
+typo = true
+"##,
+    )
+    .unwrap();
+
+    let (mut config, _) = FiapoConfig::from_toml(&std::fs::read_to_string(&path).unwrap());
+    config.export.quality = 50;
+    config.save(&path).unwrap();
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("# My config"));
+    assert!(saved.contains(r##"text_color = "#000000" # black"##));
+    assert!(saved.contains("quality = 50"));
+    // Invalid keys are dropped, and defaults are not written
+    assert!(!saved.contains("typo"));
+    assert!(!saved.contains("background_color"));
+
+    let (reloaded, issues) = FiapoConfig::from_toml(&saved);
+    assert!(issues.is_empty());
+    assert_eq!(reloaded.export.quality, 50);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_theme_css() {
+    use fiapo::core::config::FiapoConfig;
+    use fiapo::core::theme::{ThemePreset, theme_css};
+
+    let mut config = FiapoConfig::defaults();
+    config.theme = "AMOLED".to_string();
+    let css = theme_css(&config);
+    assert!(css.contains("--background-dark: #000000;"));
+    assert!(css.contains(&format!("--text: {};", ThemePreset::Amoled.palette().text)));
+
+    // Config colors override the theme, and invalid ones are ignored
+    config.background_color = Some("#202020".to_string());
+    config.text_color = Some("red; } * { color: blue".to_string());
+    let css = theme_css(&config);
+    assert!(css.contains("--background-dark: #202020;"));
+    assert!(css.contains("--background-light: mix(#202020, #FFFFFF, 0.1);"));
+    assert!(!css.contains("blue"));
+}
+
+#[test]
+fn test_app_paths() {
+    use fiapo::core::paths::AppPaths;
+    use std::path::PathBuf;
+
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        move |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| PathBuf::from(value))
+        }
+    };
+
+    let paths = AppPaths::from_env(
+        None,
+        None,
+        env(&[
+            ("HOME", "/home/user"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_CACHE_HOME", "relative"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(
+        paths.config_file,
+        PathBuf::from("/home/user/.config/fiapo/fiapo.toml")
+    );
+    assert_eq!(
+        paths.library_file(),
+        PathBuf::from("/data/fiapo/library.db")
+    );
+    // Relative XDG paths are ignored
+    assert_eq!(paths.cache_dir, PathBuf::from("/home/user/.cache/fiapo"));
+
+    // `--config` wins over FIAPO_CONFIG, and style.css sits next to the config
+    let paths = AppPaths::from_env(
+        None,
+        Some(PathBuf::from("/etc/fiapo/custom.toml")),
+        env(&[("HOME", "/home/user"), ("FIAPO_CONFIG", "/tmp/other.toml")]),
+    )
+    .unwrap();
+    assert_eq!(paths.config_file, PathBuf::from("/etc/fiapo/custom.toml"));
+    assert_eq!(paths.user_css_file(), PathBuf::from("/etc/fiapo/style.css"));
+
+    let paths = AppPaths::from_env(Some(PathBuf::from("/usb/fiapo-data")), None, env(&[])).unwrap();
+    assert!(paths.portable);
+    assert_eq!(
+        paths.config_file,
+        PathBuf::from("/usb/fiapo-data/fiapo.toml")
+    );
+    assert_eq!(
+        paths.downloads_dir(),
+        PathBuf::from("/usb/fiapo-data/downloads")
+    );
+
+    assert_eq!(AppPaths::from_env(None, None, env(&[])), None);
+
+    let mut args: Vec<String> = ["fiapo", "--config", "a.toml", "list"]
+        .map(String::from)
+        .to_vec();
+    assert_eq!(
+        AppPaths::take_config_arg(&mut args),
+        Ok(Some(PathBuf::from("a.toml")))
+    );
+    assert_eq!(args, ["fiapo", "list"]);
+    assert!(
+        AppPaths::take_config_arg(&mut vec!["fiapo".to_string(), "--config".to_string()]).is_err()
+    );
+}
+
+#[test]
+fn test_log_rotation() {
+    use fiapo::core::logging::{RotatingFile, rotated_path};
+
+    let dir = std::env::temp_dir().join("fiapo_test_log_rotation");
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("fiapo.log");
+
+    let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+    for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+        file.write(line.as_bytes()).unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+    assert_eq!(
+        std::fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+        "third\n"
+    );
+    // Only 2 rotated files are kept
+    assert_eq!(
+        std::fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+        "second\n"
+    );
+    assert!(!rotated_path(&path, 3).exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_log_config_and_bug_report() {
+    use fiapo::core::config::FiapoConfig;
+    use fiapo::core::logging::bug_report;
+    use fiapo::core::paths::AppPaths;
+
+    let (mut config, issues) = FiapoConfig::from_toml(
+        r#"[log]
+level = "loud"
+
+[log.filters]
+"fiapo::server" = "DEBUG"
+"#,
+    );
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].key.as_deref(), Some("log.level"));
+    assert_eq!(config.log.filter_spec(), "info,fiapo::server=DEBUG");
+
+    config.opds.password = Some("hunter2".to_string());
+    let report = bug_report(
+        &config,
+        &AppPaths::default(),
+        "GTK 4.0.0",
+        &["[INFO] hello".to_string()],
+    );
+    assert!(!report.contains("hunter2"));
+    assert!(report.contains("<redacted>"));
+    assert!(report.contains("[INFO] hello"));
+}
+
+#[test]
+fn test_pdf_outline_and_page_labels() {
+    use fiapo::core::outline::{PageLabels, TableOfContents, parse_pdftohtml_outline};
+
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE pdf2xml SYSTEM "pdf2xml.dtd">
+<pdf2xml producer="poppler" version="24.02.0">
+<page number="1" position="absolute" top="0" left="0" height="1262" width="892">
+<text top="10" left="10" width="50" height="20" font="0"><b>Cover</b></text>
+</page>
+<outline>
+<item page="1">Volume 3</item>
+<outline>
+<item page="3">Chapter 21 &amp; more</item>
+<item page="20">Chapter 22</item>
+<outline>
+<item page="25">Omake</item>
+</outline>
+<item page="40">Chapter 23</item>
+</outline>
+</outline>
+</pdf2xml>"#;
+    let outline = parse_pdftohtml_outline(xml).unwrap();
+    assert_eq!(outline.len(), 5);
+    assert_eq!(outline[1].title, "Chapter 21 & more");
+    assert_eq!((outline[1].page, outline[1].level), (2, 1));
+    assert_eq!((outline[3].page, outline[3].level), (24, 2));
+
+    // The single top-level entry is the volume, so its children are the chapters.
+    // The cover before the first chapter belongs to it
+    let table_of_contents = TableOfContents {
+        outline,
+        ..Default::default()
+    };
+    let chapters = table_of_contents.chapters(60);
+    let pages: Vec<_> = chapters
+        .iter()
+        .map(|chapter| chapter.pages.clone())
+        .collect();
+    assert_eq!(pages, vec![0..19, 19..39, 39..60]);
+    assert_eq!(chapters[2].title, "Chapter 23");
+    assert!(TableOfContents::default().chapters(60).is_empty());
+
+    let pdf = b"%PDF-1.4
+1 0 obj
+<< /Type /Catalog /Pages 2 0 R /PageLabels 3 0 R >>
+endobj
+3 0 obj
+<< /Nums [0 << /S /r >> 4 4 0 R 10 << /S /A /P (Extra\\040) /St 26 >>] >>
+endobj
+4 0 obj
+<< /S /D >>
+endobj
+trailer << /Root 1 0 R >>
+%%EOF";
+    let labels = PageLabels::parse(pdf).unwrap();
+    assert_eq!(labels.label(0).as_deref(), Some("i"));
+    assert_eq!(labels.label(3).as_deref(), Some("iv"));
+    assert_eq!(labels.label(4).as_deref(), Some("1"));
+    assert_eq!(labels.label(9).as_deref(), Some("6"));
+    assert_eq!(labels.label(10).as_deref(), Some("Extra Z"));
+    assert_eq!(labels.label(11).as_deref(), Some("Extra AA"));
+    assert!(PageLabels::parse(b"%PDF-1.4\n%%EOF").is_none());
+}
+
+#[test]
+fn test_pdf_backend_selection() {
+    use fiapo::core::pdf::{self, PdfBackendKind};
+
+    for kind in PdfBackendKind::ALL {
+        assert_eq!(PdfBackendKind::from_name(kind.name()), Some(kind));
+    }
+    assert_eq!(
+        PdfBackendKind::from_name("PDFium"),
+        Some(PdfBackendKind::Pdfium)
+    );
+    assert_eq!(PdfBackendKind::from_name("mupdf"), None);
+
+    // Without the `pdfium` feature, or the library, poppler is used instead
+    if PdfBackendKind::Pdfium.create().is_err() {
+        assert_eq!(
+            pdf::set_backend(PdfBackendKind::Pdfium),
+            PdfBackendKind::Poppler
+        );
+    }
+    assert_eq!(
+        pdf::set_backend(PdfBackendKind::Poppler),
+        PdfBackendKind::Poppler
+    );
+}
+
+#[test]
+fn test_export_replaces_leftovers() {
+    use fiapo::core::export::{self, ExportFormat, ExportOptions};
+    use fiapo::core::reader::{Source, SourceType};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_export");
+    let _ = fs::remove_dir_all(&dir);
+    let chapter = dir.join("chapter");
+    fs::create_dir_all(&chapter).unwrap();
+    for name in ["001.png", "002.png"] {
+        RgbImage::from_pixel(10, 10, Rgb([255, 255, 255]))
+            .save(chapter.join(name))
+            .unwrap();
+    }
+    // Left behind by an export that crashed
+    let output = dir.join("out");
+    fs::create_dir_all(dir.join("out.fiapo-tmp")).unwrap();
+    fs::write(dir.join("out.fiapo-tmp").join("999.jpg"), b"stale").unwrap();
+
+    let options = ExportOptions {
+        format: ExportFormat::ImageFolder,
+        ..Default::default()
+    };
+    let mut sources = vec![Source::open(SourceType::Directory, chapter, false).unwrap()];
+    export::export_sources(&mut sources, &options, &output, |_, _| {}).unwrap();
+    assert_eq!(fs::read_dir(&output).unwrap().count(), 2);
+    assert!(!dir.join("out.fiapo-tmp").exists());
+
+    // Nothing is written without pages
+    let empty = dir.join("empty.cbz");
+    assert!(export::export_sources(&mut [], &ExportOptions::default(), &empty, |_, _| {}).is_err());
+    assert!(!empty.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_opds_routes_and_auth() {
+    use fiapo::core::config::OpdsConfig;
+    use fiapo::core::reader::{Source, SourceType};
+    use fiapo::server::database::Library;
+    use fiapo::server::opds::OpdsServer;
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_opds");
+    let _ = fs::remove_dir_all(&dir);
+    let chapter = dir.join("Series").join("Chapter 1");
+    fs::create_dir_all(&chapter).unwrap();
+    RgbImage::from_pixel(10, 10, Rgb([255, 255, 255]))
+        .save(chapter.join("001.png"))
+        .unwrap();
+    let library_path = dir.join("library.db");
+    let chapter_id = Library::open(&library_path)
+        .unwrap()
+        .import_source(&Source::open(SourceType::Directory, chapter, false).unwrap())
+        .unwrap();
+
+    let config = OpdsConfig {
+        enabled: true,
+        address: "127.0.0.1:0".to_string(),
+        password: Some("hunter2".to_string()),
+    };
+    let mut server = OpdsServer::start(&config, library_path).unwrap();
+    let client = reqwest::blocking::Client::new();
+    let status = |path: &str, password: Option<&str>| {
+        let mut request = client.get(format!("http://{}{}", server.address(), path));
+        if let Some(password) = password {
+            request = request.basic_auth("anyone", Some(password));
+        }
+        request.send().unwrap().status().as_u16()
+    };
+
+    assert_eq!(status("/opds", None), 401);
+    assert_eq!(status("/opds", Some("hunter3")), 401);
+    assert_eq!(status("/opds", Some("hunter2")), 200);
+    assert_eq!(status("/nowhere", Some("hunter2")), 404);
+    assert_eq!(status("/opds/series/first", Some("hunter2")), 400);
+    let page = |page: &str| format!("/opds/pages/{}/{}", chapter_id, page);
+    assert_eq!(status(&page("0"), Some("hunter2")), 200);
+    assert_eq!(status(&page("1"), Some("hunter2")), 404);
+    assert_eq!(status(&page("one"), Some("hunter2")), 400);
+    assert_eq!(status("/opds/pages/999/0", Some("hunter2")), 404);
+
+    server.stop();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_locked_pdf() {
+    use fiapo::core::error::SourceError;
+    use fiapo::core::pdf::{PdfBackend, PdfBackendKind, Poppler};
+    use std::path::Path;
+    use std::process::Command;
+
+    // One page of 100x100 points, encrypted with the user password "secret"
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/assets/locked.pdf");
+    if let Ok(pdfium) = PdfBackendKind::Pdfium.create() {
+        let document = pdfium.open(&path, Some("secret")).unwrap();
+        assert_eq!(document.page_count(), 1);
+        assert_eq!(document.page_sizes().unwrap(), vec![(100.0, 100.0)]);
+        for password in [None, Some("wrong")] {
+            let error = pdfium.open(&path, password).unwrap_err();
+            assert!(matches!(error, SourceError::Encrypted { .. }));
+        }
+    } else if Command::new("pdfinfo").arg("-v").output().is_ok() {
+        // Poppler would need the password on its command line
+        let error = Poppler.open(&path, Some("secret")).unwrap_err();
+        assert!(matches!(error, SourceError::Unsupported { .. }));
+        assert!(error.to_string().contains("pdfium"));
+    }
+}
+
+#[test]
+fn test_image_filters() {
+    use fiapo::core::config::FiapoConfig;
+    use fiapo::core::filters::ImageFilters;
+    use image::{DynamicImage, GrayImage, RgbImage};
+
+    // A faded scan: tones from 50 to 200
+    let faded = DynamicImage::ImageLuma8(GrayImage::from_fn(100, 1, |x, _| {
+        image::Luma([(50 + x * 3 / 2) as u8])
+    }));
+    let untouched = ImageFilters::default().apply(faded.clone());
+    assert_eq!(untouched, faded);
+
+    let leveled = ImageFilters {
+        auto_levels: true,
+        ..Default::default()
+    }
+    .apply(faded.clone());
+    let tones = leveled.as_bytes();
+    assert_eq!(tones.iter().min(), Some(&0));
+    assert_eq!(tones.iter().max(), Some(&255));
+
+    let night = ImageFilters {
+        invert: true,
+        grayscale: true,
+        ..Default::default()
+    }
+    .apply(DynamicImage::ImageRgb8(RgbImage::from_pixel(
+        2,
+        2,
+        image::Rgb([255, 255, 255]),
+    )));
+    assert_eq!(night.color(), image::ColorType::L8);
+    assert!(night.as_bytes().iter().all(|tone| *tone == 0));
+
+    // Series override the global filters key by key
+    let (config, issues) = FiapoConfig::from_toml(
+        r#"
+[filters]
+contrast = 0.2
+grayscale = true
+
+[filters.series."Old Scans"]
+auto_levels = true
+grayscale = false
+"#,
+    );
+    assert!(issues.is_empty(), "{:?}", issues);
+    let global = config.filters.for_series(None);
+    assert!(global.grayscale && !global.auto_levels);
+    let series = config.filters.for_series(Some("Old Scans"));
+    assert!(!series.grayscale && series.auto_levels);
+    assert_eq!(series.contrast, 0.2);
+    assert_eq!(config.filters.for_series(Some("Other")), global);
+}
+
+#[test]
+fn test_auto_crop() {
+    use fiapo::core::crop::{AutoCrop, CropBox};
+    use image::{DynamicImage, GrayImage};
+
+    // A white page of 200x100 with a dark block in x 40..160, y 10..90, and a speck of dust in the left margin
+    let page = DynamicImage::ImageLuma8(GrayImage::from_fn(200, 100, |x, y| {
+        let inside = (40..160).contains(&x) && (10..90).contains(&y);
+        let speck = x == 5 && y == 50;
+        image::Luma([if inside || speck { 30 } else { 250 }])
+    }));
+    let auto_crop = AutoCrop {
+        tolerance: 24,
+        max_crop: 0.45,
+    };
+    let crop = auto_crop.detect(&page);
+    assert_eq!(crop, CropBox::new(0.2, 0.1, 0.2, 0.1));
+    let cropped = crop.apply(&page);
+    assert_eq!((cropped.width(), cropped.height()), (120, 80));
+    assert!(cropped.as_bytes().iter().all(|tone| *tone == 30));
+
+    // The cap keeps large blank areas from being cropped down to nothing
+    let capped = AutoCrop {
+        max_crop: 0.1,
+        ..auto_crop
+    }
+    .detect(&page);
+    assert_eq!(capped, CropBox::new(0.1, 0.1, 0.1, 0.1));
+
+    // Blank pages are cropped up to the cap on every side, and still keep part of the page
+    let blank = DynamicImage::ImageLuma8(GrayImage::from_pixel(100, 100, image::Luma([0])));
+    let crop = auto_crop.detect(&blank);
+    assert!(!crop.is_empty());
+    assert!(crop.apply(&blank).width() >= 10);
+
+    // Crops set by hand that leave nothing are scaled down
+    let (_, _, width, height) = CropBox::new(0.8, 0.0, 0.8, 0.0).pixels(100, 100);
+    assert_eq!((width, height), (10, 100));
+}
+
+#[test]
+fn test_crop_follows_rotation() {
+    use fiapo::core::crop::CropBox;
+    use fiapo::core::layout::Rotation;
+    use fiapo::core::reader::{Server, Source, Volume};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_crop_rotation");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    // A white margin of 10 pixels on the left of a black page
+    RgbImage::from_fn(40, 20, |x, _| {
+        if x < 10 {
+            Rgb([255, 255, 255])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    })
+    .save(dir.join("000.png"))
+    .unwrap();
+    let source = Source::new(
+        Source::source_type_for_path(&dir).unwrap(),
+        dir.clone(),
+        false,
+    );
+    let mut server = Server::new();
+    server.set_volumes(Volume::group([(None, source)]));
+    assert!(server.get_current_page().is_some());
+
+    let kept = server.set_current_crop(Some(CropBox::new(0.25, 0.0, 0.0, 0.0)));
+    assert_eq!(kept, Some(CropBox::new(0.25, 0.0, 0.0, 0.0)));
+    let page = server.get_current_page().unwrap();
+    assert_eq!((page.width(), page.height()), (30, 20));
+
+    // The margin is still cut once the page is turned, and it's shown on top
+    server.rotate_current_page(Rotation::Clockwise);
+    let page = server.get_current_page().unwrap();
+    assert_eq!((page.width(), page.height()), (20, 30));
+    assert!(
+        page.to_rgb8()
+            .pixels()
+            .all(|pixel| *pixel == Rgb([0, 0, 0]))
+    );
+    assert_eq!(
+        server.get_current_crop(),
+        Some(CropBox::new(0.0, 0.25, 0.0, 0.0))
+    );
+
+    // Crops set on the turned page are kept in the orientation of the file
+    let kept = server.set_current_crop(Some(CropBox::new(0.0, 0.25, 0.0, 0.5)));
+    assert_eq!(kept, Some(CropBox::new(0.25, 0.0, 0.5, 0.0)));
+    server.rotate_current_page(Rotation::CounterClockwise);
+    let page = server.get_current_page().unwrap();
+    assert_eq!((page.width(), page.height()), (10, 20));
+    assert!(
+        page.to_rgb8()
+            .pixels()
+            .all(|pixel| *pixel == Rgb([0, 0, 0]))
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_split_and_rotate_pages() {
+    use fiapo::core::layout::Rotation;
+    use fiapo::core::reader::{Server, Source, Volume};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join("fiapo_test_split_pages");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let white = Rgb([255, 255, 255]);
+    RgbImage::from_pixel(10, 20, white)
+        .save(dir.join("000.png"))
+        .unwrap();
+    // A spread: red on the left, blue on the right
+    RgbImage::from_fn(40, 20, |x, _| {
+        if x < 20 {
+            Rgb([255, 0, 0])
+        } else {
+            Rgb([0, 0, 255])
+        }
+    })
+    .save(dir.join("001.png"))
+    .unwrap();
+    RgbImage::from_pixel(10, 20, white)
+        .save(dir.join("002.png"))
+        .unwrap();
+    let source = Source::new(
+        Source::source_type_for_path(&dir).unwrap(),
+        dir.clone(),
+        false,
+    );
+
+    let mut server = Server::new();
+    server.set_split_wide_pages(true);
+    server.set_volumes(Volume::group([(None, source)]));
+    assert_eq!(server.get_page_count(), 4);
+
+    // Right to left, so the right half comes first. Both halves are the same page of the chapter
+    assert!(server.go_to_page(1));
+    assert_eq!(server.get_page_in_source(), 1);
+    let page = server.get_current_page().unwrap();
+    assert_eq!((page.width(), page.height()), (20, 20));
+    assert_eq!(page.to_rgb8().get_pixel(0, 0), &Rgb([0, 0, 255]));
+    assert!(server.go_to_page(2));
+    assert_eq!(server.get_page_in_source(), 1);
+    assert_eq!(
+        server.get_current_page().unwrap().to_rgb8().get_pixel(0, 0),
+        &Rgb([255, 0, 0])
+    );
+    assert!(server.go_to_page(3));
+    assert_eq!(server.get_page_in_source(), 2);
+
+    // Turned sideways, the last page is wide enough to be split too
+    assert_eq!(
+        server.rotate_current_page(Rotation::Clockwise),
+        Some(Rotation::Clockwise)
+    );
+    assert_eq!(server.get_page_count(), 5);
+    assert_eq!(server.get_current_page_index(), 3);
+    assert_eq!(server.get_page_in_source(), 2);
+    let page = server.get_current_page().unwrap();
+    assert_eq!((page.width(), page.height()), (10, 10));
+    assert_eq!(
+        server.rotate_current_page(Rotation::CounterClockwise),
+        Some(Rotation::None)
+    );
+    assert_eq!(server.get_page_count(), 4);
+
+    // Back to whole pages, on the same page
+    assert!(server.go_to_page(2));
+    server.set_split_wide_pages(false);
+    assert_eq!(server.get_page_count(), 3);
+    assert_eq!(server.get_current_page_index(), 1);
+    assert_eq!(server.get_current_page().unwrap().width(), 40);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_split_pages_chapter_by_chapter() {
+    use fiapo::core::reader::{Server, Source, Volume};
+    use image::{Rgb, RgbImage};
+    use std::fs;
+
+    // Four chapters of a page and a spread
+    let dir = std::env::temp_dir().join("fiapo_test_split_chapters");
+    let _ = fs::remove_dir_all(&dir);
+    let mut chapters = Vec::new();
+    for chapter in 0..4u8 {
+        let chapter_dir = dir.join(format!("chapter {}", chapter));
+        fs::create_dir_all(&chapter_dir).unwrap();
+        RgbImage::from_pixel(10, 20, Rgb([chapter, 0, 0]))
+            .save(chapter_dir.join("000.png"))
+            .unwrap();
+        RgbImage::from_pixel(40, 20, Rgb([chapter, 1, 0]))
+            .save(chapter_dir.join("001.png"))
+            .unwrap();
+        let source = Source::new(
+            Source::source_type_for_path(&chapter_dir).unwrap(),
+            chapter_dir,
+            false,
+        );
+        chapters.push((None, source));
+    }
+    let mut server = Server::new();
+    server.set_split_wide_pages(true);
+    server.set_volumes(Volume::group(chapters));
+
+    // Only the chapters next to the reader are split
+    assert_eq!(server.get_page_count(), 10);
+    assert!(server.next_source());
+    assert_eq!(server.get_page_count(), 11);
+    // Going back lands on the last half of the previous chapter
+    assert!(server.prev_page());
+    assert!(server.prev_page());
+    assert_eq!(server.get_current_page_index(), 2);
+
+    assert!(server.go_to_page(9));
+    assert_eq!(server.get_page_count(), 12);
+    assert_eq!(server.get_current_source_pages(), 9..12);
+    assert_eq!(
+        server.get_current_page().unwrap().to_rgb8().get_pixel(0, 0),
+        &Rgb([3, 0, 0])
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}